@@ -0,0 +1,69 @@
+use std::{fs, path::Path, time::{Duration, Instant}};
+
+use crate::loader::MeshError;
+use crate::mesh::Mesh;
+use crate::parse_obj;
+use crate::parse_obj_sequential;
+
+// run each measure for at least this long
+const MIN_DURATION: Duration = Duration::from_secs(2);
+const MIN_RUNS: u32 = 3;
+
+// compare the throughput of the former line by line obj parser with the chunked one,
+// on one thread and on all of them
+pub fn run(paths: &[String]) {
+    let threads = rayon::current_num_threads();
+    let single = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .expect("Failed to create thread pool");
+
+    for path in paths {
+        let path = Path::new(path);
+        let size = match fs::metadata(path) {
+            Ok(metadata) => metadata.len() as f64,
+            Err(error) => {
+                println!("{}: {}", path.display(), error);
+                continue;
+            }
+        };
+
+        let line_by_line = match measure(path, parse_obj_sequential::load_model) {
+            Some(time) => time,
+            None => continue,
+        };
+        let sequential = match single.install(|| measure(path, parse_obj::load_model)) {
+            Some(time) => time,
+            None => continue,
+        };
+        let parallel = match measure(path, parse_obj::load_model) {
+            Some(time) => time,
+            None => continue,
+        };
+
+        // speed-ups are relative to the line by line parser
+        println!("{} ({:.1} MB)", path.display(), size / 1e6);
+        println!("    {:<22} {:>8.2} ms {:>8.1} MB/s",
+            "line by line:", line_by_line * 1e3, size / line_by_line / 1e6);
+        println!("    {:<22} {:>8.2} ms {:>8.1} MB/s ({:.2}x)",
+            "chunked, 1 thread:", sequential * 1e3, size / sequential / 1e6, line_by_line / sequential);
+        println!("    {:<22} {:>8.2} ms {:>8.1} MB/s ({:.2}x)",
+            format!("chunked, {} threads:", threads), parallel * 1e3, size / parallel / 1e6, line_by_line / parallel);
+    }
+}
+
+// average time (in seconds) to load the model
+fn measure(path: &Path, load_model: fn(&Path) -> Result<Mesh, MeshError>) -> Option<f64> {
+    let start = Instant::now();
+    let mut runs = 0;
+
+    while runs < MIN_RUNS || start.elapsed() < MIN_DURATION {
+        if let Err(error) = load_model(path) {
+            println!("{}", error);
+            return None;
+        }
+        runs += 1;
+    }
+
+    Some(start.elapsed().as_secs_f64() / runs as f64)
+}
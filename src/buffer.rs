@@ -0,0 +1,126 @@
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+    slice,
+    sync::Arc,
+};
+
+use memmap2::Mmap;
+
+// list of plain values, owned or read in place from a memory mapped file
+// ------------------------------------------------------------------------
+// the vertices and triangles of a mesh loaded from its cache stay in the mapping: nothing
+// is copied until they are changed, then they become a vector of their own. a copy of
+// the mesh shares the mapping.
+
+// types that can be copied as raw memory (any bit pattern is a valid value)
+pub trait Plain: Copy + Default {}
+impl Plain for u8 {}
+impl Plain for f32 {}
+impl Plain for u32 {}
+
+pub enum Buffer<T: Plain> {
+    Owned(Vec<T>),
+    // `len` values starting at byte `offset` of the mapping, aligned for T
+    Mapped { map: Arc<Mmap>, offset: usize, len: usize },
+}
+
+impl<T: Plain> Buffer<T> {
+    // the values in a mapping, None when they are outside of it or not aligned
+    pub fn mapped(map: Arc<Mmap>, offset: usize, len: usize) -> Option<Self> {
+        let end = len.checked_mul(std::mem::size_of::<T>())?.checked_add(offset)?;
+        let aligned = (map.as_ptr() as usize + offset).is_multiple_of(std::mem::align_of::<T>());
+        (end <= map.len() && aligned).then_some(Buffer::Mapped { map, offset, len })
+    }
+
+    // the values as a vector, copied out of the mapping first if needed
+    pub fn to_mut(&mut self) -> &mut Vec<T> {
+        if let Buffer::Mapped { .. } = self {
+            *self = Buffer::Owned(self.to_vec());
+        }
+        match self {
+            Buffer::Owned(values) => values,
+            Buffer::Mapped { .. } => unreachable!(),
+        }
+    }
+
+    pub fn into_vec(self) -> Vec<T> {
+        match self {
+            Buffer::Owned(values) => values,
+            mapped => mapped.to_vec(),
+        }
+    }
+
+    pub fn is_mapped(&self) -> bool {
+        matches!(self, Buffer::Mapped { .. })
+    }
+}
+
+impl<T: Plain> Deref for Buffer<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        match self {
+            Buffer::Owned(values) => values,
+            // checked by Buffer::mapped. the cache files are replaced, never written
+            // in place, so the mapped bytes don't change under the mesh
+            Buffer::Mapped { map, offset, len } => unsafe {
+                slice::from_raw_parts(map.as_ptr().add(*offset) as *const T, *len)
+            },
+        }
+    }
+}
+
+impl<T: Plain> DerefMut for Buffer<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.to_mut()
+    }
+}
+
+impl<T: Plain> Clone for Buffer<T> {
+    fn clone(&self) -> Self {
+        match self {
+            Buffer::Owned(values) => Buffer::Owned(values.clone()),
+            Buffer::Mapped { map, offset, len } => Buffer::Mapped { map: map.clone(), offset: *offset, len: *len },
+        }
+    }
+}
+
+impl<T: Plain> Default for Buffer<T> {
+    fn default() -> Self {
+        Buffer::Owned(Vec::new())
+    }
+}
+
+impl<T: Plain> From<Vec<T>> for Buffer<T> {
+    fn from(values: Vec<T>) -> Self {
+        Buffer::Owned(values)
+    }
+}
+
+impl<T: Plain> FromIterator<T> for Buffer<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Buffer::Owned(iter.into_iter().collect())
+    }
+}
+
+impl<T: Plain + PartialEq> PartialEq for Buffer<T> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<'a, T: Plain> IntoIterator for &'a Buffer<T> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T: Plain + fmt::Debug> fmt::Debug for Buffer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
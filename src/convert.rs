@@ -0,0 +1,94 @@
+use std::path::Path;
+
+use crate::loader;
+use crate::model;
+use crate::weld::{self, WeldOptions};
+use crate::write_gltf;
+use crate::write_obj;
+use crate::write_ply;
+use crate::write_stl;
+
+const USAGE: &str = "usage: scop convert [--recenter | --normalize] [--weld[=EPSILON] | --weld-positions[=EPSILON]] input output\n\
+    output formats: .obj, .ply, .stl, .gltf, .glb\n\
+    --recenter        move the center of the bounding box to the origin\n\
+    --normalize       recenter and scale to fit in [-1, 1], as the model is displayed\n\
+    --weld            merge vertices whose attributes all differ by at most EPSILON (0 by default)\n\
+    --weld-positions  merge vertices whose positions differ by at most EPSILON, with smooth normals";
+
+// what to do to the vertices before saving them
+enum Placement {
+    Unchanged,
+    Recenter,
+    Normalize,
+}
+
+// scop convert [options] input output: load a model with any loader
+// and save it with the writer of the output extension, without opening a window
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut placement = Placement::Unchanged;
+    let mut weld_options = None;
+    let mut paths = Vec::new();
+    for arg in args {
+        let (option, value) = match arg.split_once('=') {
+            Some((option, value)) => (option, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        match option {
+            "--recenter" => placement = Placement::Recenter,
+            "--normalize" => placement = Placement::Normalize,
+            "--weld" | "--weld-positions" => {
+                let epsilon = match value {
+                    Some(value) => value.parse::<f32>()
+                        .ok()
+                        .filter(|epsilon| *epsilon >= 0.0)
+                        .ok_or_else(|| format!("invalid epsilon {}\n{}", value, USAGE))?,
+                    None => 0.0,
+                };
+                weld_options = Some(WeldOptions { epsilon, positions_only: option == "--weld-positions" });
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            option if option.starts_with("--") => return Err(format!("unknown option {}\n{}", option, USAGE)),
+            _ => paths.push(Path::new(arg)),
+        }
+    }
+    let (input, output) = match paths[..] {
+        [input, output] => (input, output),
+        _ => return Err(String::from(USAGE)),
+    };
+
+    let extension = output.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    let save = match extension.as_deref() {
+        Some("obj") => write_obj::save_model,
+        Some("ply") => write_ply::save_model,
+        Some("stl") => write_stl::save_model,
+        Some("gltf") | Some("glb") => write_gltf::save_model,
+        _ => return Err(format!("{}: unsupported output format\n{}", output.display(), USAGE)),
+    };
+
+    let mut mesh = loader::load_model(input).map_err(|error| error.to_string())?;
+
+    if let Some(weld_options) = weld_options {
+        let merged = weld::weld(&mut mesh, &weld_options);
+        println!("merged {} vertices", merged);
+    }
+
+    match placement {
+        Placement::Unchanged => {}
+        Placement::Recenter => mesh.transform(&model::generate_center_matrix(&mesh.vertices)),
+        Placement::Normalize => mesh.transform(&model::generate_model_matrix(&mesh.vertices)),
+    }
+
+    // formats that only hold triangles
+    let dropped = mesh.lines.len() / 2 + mesh.points.len();
+    if dropped > 0 && matches!(extension.as_deref(), Some("ply") | Some("stl")) {
+        println!("warning: {} line segments and points are not saved in {}", dropped, output.display());
+    }
+
+    save(&mesh, output).map_err(|error| format!("{}: {}", output.display(), error))?;
+    println!("saved {}", output.display());
+    Ok(())
+}
@@ -0,0 +1,347 @@
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::mesh::{Mesh, POSITION_OFFSET, VERTEX_SIZE};
+use crate::weld;
+
+// connectivity of the faces of a mesh
+// ------------------------------------------------------------------------
+// vertices are welded by position, so corners split by their normal or texture
+// coordinates share one vertex. there is a half-edge per corner, going from it to the
+// next corner of its face, and the half-edges of a face follow each other.
+// edges with more than two faces (or two faces going the same way) are not paired
+// and look like boundaries.
+
+#[derive(Clone, Copy, Debug)]
+pub struct HalfEdge {
+    // vertex the half-edge starts from
+    pub origin: u32,
+    // half-edge of the neighbouring face going the other way, None on a boundary
+    pub twin: Option<usize>,
+    // index in Mesh::vertices of the corner, to get back its other attributes
+    pub corner: u32,
+    pub face: u32,
+    // index in HalfEdgeMesh::edges
+    pub edge: u32,
+}
+
+// the half-edges between two vertices, whichever way they go
+#[derive(Clone, Copy, Debug)]
+pub struct Edge {
+    // number of faces on the edge
+    pub faces: u32,
+    // the half-edge of the first of them
+    pub half_edge: usize,
+}
+
+pub struct HalfEdgeMesh {
+    pub positions: Vec<[f32; 3]>,
+    pub half_edges: Vec<HalfEdge>,
+    pub edges: Vec<Edge>,
+    // first half-edge of each face, and the end of the last one
+    face_starts: Vec<usize>,
+    // an outgoing half-edge of each vertex, the one starting its boundary if it is on one
+    // (None for vertices that are only used by lines and points)
+    vertex_half_edges: Vec<Option<usize>>,
+}
+
+impl HalfEdgeMesh {
+    // the triangles of a mesh, face `f` is made of the half-edges 3f, 3f + 1 and 3f + 2
+    pub fn new(mesh: &Mesh) -> Self {
+        let face_starts = (0..=mesh.indices.len() / 3).map(|face| face * 3).collect();
+        Self::from_faces(mesh, &mesh.indices, face_starts)
+    }
+
+    // faces given by their corners (indices in Mesh::vertices) face after face,
+    // face `f` being corners[face_starts[f]..face_starts[f + 1]]
+    pub fn from_faces(mesh: &Mesh, corners: &[u32], face_starts: Vec<usize>) -> Self {
+        let mut positions = Vec::new();
+        let mut ids: HashMap<[u32; 3], u32> = HashMap::new();
+        let mut half_edges = Vec::with_capacity(corners.len());
+
+        for (face, range) in face_starts.windows(2).enumerate() {
+            for &corner in &corners[range[0]..range[1]] {
+                let p = corner as usize * VERTEX_SIZE + POSITION_OFFSET;
+                let position = [mesh.vertices[p], mesh.vertices[p + 1], mesh.vertices[p + 2]];
+                let origin = *ids.entry(weld::position_key(position)).or_insert_with(|| {
+                    positions.push(position);
+                    (positions.len() - 1) as u32
+                });
+                half_edges.push(HalfEdge { origin, twin: None, corner, face: face as u32, edge: 0 });
+            }
+        }
+
+        let mut result = Self {
+            vertex_half_edges: vec![None; positions.len()],
+            positions,
+            half_edges,
+            edges: Vec::new(),
+            face_starts,
+        };
+        result.pair_twins();
+        result
+    }
+
+    // group the half-edges by edge, and pair the two half-edges of the edges with two faces
+    fn pair_twins(&mut self) {
+        let mut ids: HashMap<(u32, u32), u32> = HashMap::new();
+        for h in 0..self.half_edges.len() {
+            let (a, b) = (self.origin(h), self.target(h));
+            let edge = *ids.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                self.edges.push(Edge { faces: 0, half_edge: h });
+                (self.edges.len() - 1) as u32
+            });
+            let Edge { faces, half_edge: first } = &mut self.edges[edge as usize];
+            *faces += 1;
+            let (faces, first) = (*faces, *first);
+            self.half_edges[h].edge = edge;
+
+            // degenerate edges (on a repeated vertex) are never paired
+            if faces == 2 && a != b && self.origin(first) == b {
+                self.half_edges[h].twin = Some(first);
+                self.half_edges[first].twin = Some(h);
+            }
+        }
+        // a third face on an edge unpairs it
+        for edge in &self.edges {
+            if edge.faces > 2 {
+                if let Some(twin) = self.half_edges[edge.half_edge].twin.take() {
+                    self.half_edges[twin].twin = None;
+                }
+            }
+        }
+
+        for h in 0..self.half_edges.len() {
+            let vertex = self.origin(h) as usize;
+            let current = self.vertex_half_edges[vertex];
+            let boundary = |h: Option<usize>| h.is_some_and(|h| self.is_boundary_edge(h));
+            if current.is_none() || (boundary(Some(h)) && !boundary(current)) {
+                self.vertex_half_edges[vertex] = Some(h);
+            }
+        }
+    }
+
+    pub fn face_count(&self) -> usize {
+        self.face_starts.len() - 1
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn face(&self, h: usize) -> usize {
+        self.half_edges[h].face as usize
+    }
+
+    // half-edges of a face, in order
+    pub fn face_half_edges(&self, face: usize) -> Range<usize> {
+        self.face_starts[face]..self.face_starts[face + 1]
+    }
+
+    pub fn next(&self, h: usize) -> usize {
+        let range = self.face_half_edges(self.face(h));
+        if h + 1 < range.end { h + 1 } else { range.start }
+    }
+
+    pub fn prev(&self, h: usize) -> usize {
+        let range = self.face_half_edges(self.face(h));
+        if h > range.start { h - 1 } else { range.end - 1 }
+    }
+
+    pub fn twin(&self, h: usize) -> Option<usize> {
+        self.half_edges[h].twin
+    }
+
+    pub fn origin(&self, h: usize) -> u32 {
+        self.half_edges[h].origin
+    }
+
+    pub fn target(&self, h: usize) -> u32 {
+        self.half_edges[self.next(h)].origin
+    }
+
+    // the three vertices of a triangle (of a mesh built with `new`)
+    pub fn triangle(&self, face: usize) -> [u32; 3] {
+        [self.origin(face * 3), self.origin(face * 3 + 1), self.origin(face * 3 + 2)]
+    }
+
+    pub fn is_boundary_edge(&self, h: usize) -> bool {
+        self.half_edges[h].twin.is_none()
+    }
+
+    // true for vertices on a boundary (or next to a non-manifold edge), and unused ones
+    pub fn is_boundary_vertex(&self, vertex: u32) -> bool {
+        self.vertex_half_edges[vertex as usize].is_none_or(|h| self.is_boundary_edge(h))
+    }
+
+    // half-edges leaving a vertex, turning counter-clockwise from the boundary if there is one.
+    // around a non-manifold vertex only one fan of faces is visited.
+    pub fn outgoing(&self, vertex: u32) -> Outgoing<'_> {
+        let first = self.vertex_half_edges[vertex as usize];
+        Outgoing { mesh: self, first, current: first }
+    }
+
+    // neighbours of a vertex, in the order of `outgoing`
+    pub fn one_ring(&self, vertex: u32) -> impl Iterator<Item = u32> + '_ {
+        // on a boundary the last neighbour is only reached by an incoming half-edge
+        let last = self.outgoing(vertex).last()
+            .map(|h| self.prev(h))
+            .filter(|&incoming| self.is_boundary_edge(incoming))
+            .map(|incoming| self.origin(incoming));
+        self.outgoing(vertex).map(|h| self.target(h)).chain(last)
+    }
+
+    // faces around a vertex
+    pub fn vertex_faces(&self, vertex: u32) -> impl Iterator<Item = usize> + '_ {
+        self.outgoing(vertex).map(|h| self.face(h))
+    }
+
+    // faces sharing an edge with a face
+    pub fn face_neighbours(&self, face: usize) -> impl Iterator<Item = usize> + '_ {
+        self.face_half_edges(face).filter_map(|h| self.twin(h)).map(|twin| self.face(twin))
+    }
+
+    // closed chains of boundary half-edges, as lists of vertices. two holes touching at a
+    // vertex are two loops. a chain that does not close (through a non-manifold edge) is left out.
+    pub fn boundary_loops(&self) -> Vec<Vec<u32>> {
+        let mut visited = vec![false; self.half_edges.len()];
+        let mut loops = Vec::new();
+
+        for start in 0..self.half_edges.len() {
+            if visited[start] || !self.is_boundary_edge(start) || self.origin(start) == self.target(start) {
+                continue;
+            }
+            let mut boundary = Vec::new();
+            let mut h = start;
+            let closed = loop {
+                visited[h] = true;
+                boundary.push(self.origin(h));
+                match self.next_boundary_edge(h) {
+                    Some(next) if next == start => break true,
+                    Some(next) if !visited[next] => h = next,
+                    _ => break false,
+                }
+            };
+            if closed {
+                loops.push(boundary);
+            }
+        }
+        loops
+    }
+
+    // the boundary half-edge leaving the vertex where the boundary half-edge `h` ends,
+    // found by turning around the vertex from the face of `h`
+    fn next_boundary_edge(&self, h: usize) -> Option<usize> {
+        let first = self.next(h);
+        let mut next = first;
+        // each half-edge is seen at most once
+        for _ in 0..self.half_edges.len() {
+            next = if self.origin(next) == self.target(next) {
+                // a degenerate edge is stepped over, it starts and ends on the vertex
+                self.next(next)
+            } else {
+                match self.twin(next) {
+                    None => return Some(next),
+                    Some(twin) => self.next(twin),
+                }
+            };
+            if next == first {
+                return None;
+            }
+        }
+        None
+    }
+}
+
+// half-edges leaving a vertex, see HalfEdgeMesh::outgoing
+pub struct Outgoing<'a> {
+    mesh: &'a HalfEdgeMesh,
+    first: Option<usize>,
+    current: Option<usize>,
+}
+
+impl Iterator for Outgoing<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let h = self.current?;
+        // the next face counter-clockwise shares the edge coming into the vertex
+        self.current = self.mesh.twin(self.mesh.prev(h)).filter(|&next| Some(next) != self.first);
+        Some(h)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tetrahedron() -> Mesh {
+        let positions = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        Mesh::from_triangles(&positions, &[0, 2, 1, 0, 1, 3, 0, 3, 2, 1, 2, 3])
+    }
+
+    // two triangles sharing the diagonal 0 2
+    fn quad() -> Mesh {
+        let positions = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+        Mesh::from_triangles(&positions, &[0, 1, 2, 0, 2, 3])
+    }
+
+    fn sorted(iterator: impl Iterator<Item = u32>) -> Vec<u32> {
+        let mut list: Vec<u32> = iterator.collect();
+        list.sort_unstable();
+        list
+    }
+
+    #[test]
+    fn closed_tetrahedron() {
+        let mesh = HalfEdgeMesh::new(&tetrahedron());
+        assert_eq!(mesh.vertex_count(), 4);
+        assert_eq!(mesh.edges.len(), 6);
+        assert!((0..mesh.half_edges.len()).all(|h| !mesh.is_boundary_edge(h)));
+        assert!(mesh.boundary_loops().is_empty());
+        for vertex in 0..4 {
+            assert!(!mesh.is_boundary_vertex(vertex));
+            let others: Vec<u32> = (0..4).filter(|&other| other != vertex).collect();
+            assert_eq!(sorted(mesh.one_ring(vertex)), others);
+            assert_eq!(mesh.vertex_faces(vertex).count(), 3);
+        }
+        for face in 0..4 {
+            let mut neighbours: Vec<usize> = mesh.face_neighbours(face).collect();
+            neighbours.sort_unstable();
+            assert_eq!(neighbours, (0..4).filter(|&other| other != face).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn open_quad() {
+        let mesh = HalfEdgeMesh::new(&quad());
+        assert_eq!(mesh.edges.len(), 5);
+        let boundary = (0..mesh.half_edges.len()).filter(|&h| mesh.is_boundary_edge(h)).count();
+        assert_eq!(boundary, 4);
+        // only the diagonal is paired
+        let diagonal = (0..mesh.half_edges.len()).find(|&h| !mesh.is_boundary_edge(h)).unwrap();
+        assert_eq!(sorted([mesh.origin(diagonal), mesh.target(diagonal)].into_iter()), [0, 2]);
+
+        // on a boundary the ring goes from one boundary edge to the other
+        assert_eq!(mesh.one_ring(0).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(sorted(mesh.one_ring(1)), [0, 2]);
+        assert_eq!(mesh.vertex_faces(0).count(), 2);
+        assert_eq!(mesh.vertex_faces(1).count(), 1);
+        assert!((0..4).all(|vertex| mesh.is_boundary_vertex(vertex)));
+
+        assert_eq!(mesh.face_neighbours(0).collect::<Vec<_>>(), [1]);
+        assert_eq!(mesh.face_neighbours(1).collect::<Vec<_>>(), [0]);
+
+        let loops = mesh.boundary_loops();
+        assert_eq!(loops.len(), 1);
+        assert_eq!(sorted(loops[0].iter().copied()), [0, 1, 2, 3]);
+        // the loop follows the boundary, every step is a boundary edge
+        for (k, &vertex) in loops[0].iter().enumerate() {
+            let next = loops[0][(k + 1) % loops[0].len()];
+            assert!((0..mesh.half_edges.len())
+                .any(|h| mesh.is_boundary_edge(h) && mesh.origin(h) == vertex && mesh.target(h) == next));
+        }
+    }
+}
@@ -0,0 +1,323 @@
+use std::{path::Path, collections::HashSet};
+
+use serde_json::json;
+
+use crate::half_edge::HalfEdgeMesh;
+use crate::loader;
+use crate::mesh::{Mesh, POSITION_OFFSET, VERTEX_SIZE};
+use crate::model;
+use crate::vector::{cross, dot, sub};
+use crate::weld;
+
+const USAGE: &str = "usage: scop info [--json] input";
+
+// counts and measures of a mesh. the topology (edges, genus, boundaries...) is computed
+// on positions, so vertices split by their normal or texture coordinates count as one
+struct Stats {
+    vertices: usize,
+    // distinct positions used by the triangles
+    positions: usize,
+    triangles: usize,
+    lines: usize,
+    points: usize,
+    objects: usize,
+    materials: usize,
+    bounding_box: ([f32; 3], [f32; 3]),
+    area: f64,
+    // none when the mesh is not closed
+    volume: Option<f64>,
+    edges: usize,
+    // edges of one triangle
+    boundary_edges: usize,
+    // edges shared by more than two triangles
+    non_manifold_edges: usize,
+    boundary_loops: usize,
+    // pieces not connected by any edge
+    components: usize,
+    // from the euler characteristic, none when the mesh is not manifold
+    genus: Option<i64>,
+    // vertices equal to another one in every attribute
+    duplicate_vertices: usize,
+    // triangles with a zero area (repeated corner or flat)
+    degenerate_triangles: usize,
+}
+
+// scop info [--json] input: print statistics of a model without opening a window
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut as_json = false;
+    let mut paths = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--json" => as_json = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            option if option.starts_with("--") => return Err(format!("unknown option {}\n{}", option, USAGE)),
+            path => paths.push(Path::new(path)),
+        }
+    }
+    let path = match paths[..] {
+        [path] => path,
+        _ => return Err(String::from(USAGE)),
+    };
+
+    let mesh = loader::load_model(path).map_err(|error| error.to_string())?;
+    let stats = compute(&mesh);
+    if as_json {
+        print_json(path, &stats);
+    } else {
+        print_text(path, &stats);
+    }
+    Ok(())
+}
+
+fn compute(mesh: &Mesh) -> Stats {
+    let position = |index: u32| -> [f32; 3] {
+        let p = index as usize * VERTEX_SIZE + POSITION_OFFSET;
+        [mesh.vertices[p], mesh.vertices[p + 1], mesh.vertices[p + 2]]
+    };
+    let topology = HalfEdgeMesh::new(mesh);
+    let positions = topology.vertex_count();
+
+    let bounding_box = model::bounding_box(&mesh.vertices);
+    let diagonal: f32 = (0..3).map(|i| (bounding_box.1[i] - bounding_box.0[i]).powi(2)).sum();
+
+    let mut area = 0.0;
+    let mut volume = 0.0;
+    let mut degenerate_triangles = 0;
+    for (face, indices) in mesh.indices.chunks_exact(3).enumerate() {
+        let [a, b, c] = [position(indices[0]), position(indices[1]), position(indices[2])];
+        let n = cross(sub(b, a), sub(c, a));
+        let triangle_area = dot(n, n).sqrt() / 2.0;
+        area += triangle_area as f64;
+        // signed volume of the tetrahedron with the origin
+        volume += dot(a, cross(b, c)) as f64 / 6.0;
+        let ids = topology.triangle(face);
+        if ids[0] == ids[1] || ids[1] == ids[2] || ids[2] == ids[0] || triangle_area <= diagonal * 1e-12 {
+            degenerate_triangles += 1;
+        }
+    }
+
+    // edges on a repeated vertex are not edges
+    let edges: Vec<(u32, u32, u32)> = topology.edges.iter()
+        .map(|edge| (topology.origin(edge.half_edge), topology.target(edge.half_edge), edge.faces))
+        .filter(|&(a, b, _)| a != b)
+        .collect();
+    let boundary_edges = edges.iter().filter(|&&(_, _, faces)| faces == 1).count();
+    let non_manifold_edges = edges.iter().filter(|&&(_, _, faces)| faces > 2).count();
+    let boundary_loops = topology.boundary_loops().len();
+
+    // pieces of the surface
+    let mut surface = DisjointSets::new(positions);
+    for &(a, b, _) in &edges {
+        surface.union(a, b);
+    }
+    let components = surface.count((0..topology.face_count()).flat_map(|face| topology.triangle(face)));
+
+    // V - E + F = 2C - 2g - b for orientable manifolds
+    let genus = if non_manifold_edges == 0 {
+        let euler = positions as i64 - edges.len() as i64 + topology.face_count() as i64;
+        Some((2 * components as i64 - boundary_loops as i64 - euler) / 2)
+    } else {
+        None
+    };
+    // the signed volume only means something inside a closed surface with consistent winding:
+    // every half-edge has a twin going the other way
+    let closed = topology.face_count() > 0 && (0..topology.half_edges.len())
+        .all(|h| !topology.is_boundary_edge(h) || topology.origin(h) == topology.target(h));
+
+    let distinct: HashSet<Vec<u32>> = mesh.vertices.chunks_exact(VERTEX_SIZE)
+        .map(|vertex| vertex.iter().copied().map(weld::value_key).collect())
+        .collect();
+    let vertex_count = mesh.vertices.len() / VERTEX_SIZE;
+
+    Stats {
+        vertices: vertex_count,
+        positions,
+        triangles: topology.face_count(),
+        lines: mesh.lines.len() / 2,
+        points: mesh.points.len(),
+        objects: mesh.nodes.len(),
+        materials: mesh.materials.len(),
+        bounding_box,
+        area,
+        volume: closed.then_some(volume.abs()),
+        edges: edges.len(),
+        boundary_edges,
+        non_manifold_edges,
+        boundary_loops,
+        components,
+        genus,
+        duplicate_vertices: vertex_count - distinct.len(),
+        degenerate_triangles,
+    }
+}
+
+fn print_text(path: &Path, stats: &Stats) {
+    let (min, max) = stats.bounding_box;
+    let genus = stats.genus.map_or_else(|| String::from("- (not manifold)"), |genus| genus.to_string());
+    let volume = stats.volume.map_or_else(|| String::from("- (not closed)"), |volume| volume.to_string());
+    println!("{}", path.display());
+    println!("    vertices:             {} ({} positions)", stats.vertices, stats.positions);
+    println!("    triangles:            {}", stats.triangles);
+    println!("    lines / points:       {} / {}", stats.lines, stats.points);
+    println!("    objects and groups:   {}", stats.objects);
+    println!("    materials:            {}", stats.materials);
+    println!("    bounding box:         [{}, {}, {}] - [{}, {}, {}]", min[0], min[1], min[2], max[0], max[1], max[2]);
+    println!("    size:                 {} x {} x {}", max[0] - min[0], max[1] - min[1], max[2] - min[2]);
+    println!("    surface area:         {}", stats.area);
+    println!("    volume:               {}", volume);
+    println!("    edges:                {}", stats.edges);
+    println!("    boundary edges:       {} ({} loops)", stats.boundary_edges, stats.boundary_loops);
+    println!("    non-manifold edges:   {}", stats.non_manifold_edges);
+    println!("    components:           {}", stats.components);
+    println!("    genus:                {}", genus);
+    println!("    duplicate vertices:   {}", stats.duplicate_vertices);
+    println!("    degenerate triangles: {}", stats.degenerate_triangles);
+}
+
+fn print_json(path: &Path, stats: &Stats) {
+    let (min, max) = stats.bounding_box;
+    let report = json!({
+        "path": path.to_string_lossy(),
+        "vertices": stats.vertices,
+        "positions": stats.positions,
+        "triangles": stats.triangles,
+        "lines": stats.lines,
+        "points": stats.points,
+        "objects": stats.objects,
+        "materials": stats.materials,
+        "bounding_box": { "min": min, "max": max },
+        "surface_area": stats.area,
+        "volume": stats.volume,
+        "edges": stats.edges,
+        "boundary_edges": stats.boundary_edges,
+        "boundary_loops": stats.boundary_loops,
+        "non_manifold_edges": stats.non_manifold_edges,
+        "components": stats.components,
+        "genus": stats.genus,
+        "duplicate_vertices": stats.duplicate_vertices,
+        "degenerate_triangles": stats.degenerate_triangles,
+    });
+    println!("{}", serde_json::to_string_pretty(&report).unwrap_or_default());
+}
+
+// union-find over position ids
+struct DisjointSets {
+    parents: Vec<u32>,
+}
+
+impl DisjointSets {
+    fn new(size: usize) -> Self {
+        Self { parents: (0..size as u32).collect() }
+    }
+
+    fn find(&mut self, mut id: u32) -> u32 {
+        while self.parents[id as usize] != id {
+            // path halving
+            let parent = self.parents[id as usize];
+            self.parents[id as usize] = self.parents[parent as usize];
+            id = parent;
+        }
+        id
+    }
+
+    fn union(&mut self, a: u32, b: u32) {
+        let (a, b) = (self.find(a), self.find(b));
+        self.parents[a as usize] = b;
+    }
+
+    // number of sets the ids belong to
+    fn count(&mut self, ids: impl Iterator<Item = u32>) -> usize {
+        let mut roots: Vec<u32> = ids.map(|id| self.find(id)).collect();
+        roots.sort_unstable();
+        roots.dedup();
+        roots.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    // a unit cube from 0 to 1 facing outwards, vertex x + 2y + 4z at the corner of those
+    // coordinates. with `split`, each corner of each triangle has its own vertex
+    fn cube(split: bool) -> Mesh {
+        let corners: Vec<[f32; 3]> = (0..8).map(|i| [i & 1, i >> 1 & 1, i >> 2 & 1].map(|bit| bit as f32)).collect();
+        let quads = [[0, 2, 3, 1], [4, 5, 7, 6], [0, 1, 5, 4], [2, 6, 7, 3], [0, 4, 6, 2], [1, 3, 7, 5]];
+        let indices: Vec<u32> = quads.iter().flat_map(|&[a, b, c, d]| [a, b, c, a, c, d]).collect();
+        if !split {
+            return Mesh::from_triangles(&corners, &indices);
+        }
+        let positions: Vec<[f32; 3]> = indices.iter().map(|&index| corners[index as usize]).collect();
+        Mesh::from_triangles(&positions, &(0..indices.len() as u32).collect::<Vec<_>>())
+    }
+
+    // a torus of n x n quads around the y axis
+    fn torus(n: usize) -> Mesh {
+        let angle = |i: usize| 2.0 * PI * (i % n) as f32 / n as f32;
+        let mut positions = Vec::new();
+        for i in 0..n {
+            for j in 0..n {
+                let radius = 2.0 + angle(j).cos();
+                positions.push([radius * angle(i).cos(), angle(j).sin(), radius * angle(i).sin()]);
+            }
+        }
+        let at = |i: usize, j: usize| ((i % n) * n + j % n) as u32;
+        let indices: Vec<u32> = (0..n)
+            .flat_map(|i| (0..n).flat_map(move |j| [at(i, j), at(i, j + 1), at(i + 1, j + 1), at(i, j), at(i + 1, j + 1), at(i + 1, j)]))
+            .collect();
+        Mesh::from_triangles(&positions, &indices)
+    }
+
+    #[test]
+    fn closed_cube() {
+        for split in [false, true] {
+            let stats = compute(&cube(split));
+            assert_eq!(stats.vertices, if split { 36 } else { 8 });
+            assert_eq!(stats.positions, 8);
+            assert_eq!((stats.triangles, stats.edges, stats.components), (12, 18, 1));
+            assert_eq!((stats.boundary_edges, stats.boundary_loops, stats.non_manifold_edges), (0, 0, 0));
+            assert_eq!(stats.genus, Some(0));
+            assert!(stats.volume.is_some_and(|volume| (volume - 1.0).abs() < 1e-9), "{:?}", stats.volume);
+            assert_eq!(stats.area, 6.0);
+            // the split corners are the same white vertex without normal
+            assert_eq!(stats.duplicate_vertices, if split { 28 } else { 0 });
+            assert_eq!(stats.degenerate_triangles, 0);
+        }
+    }
+
+    #[test]
+    fn open_quad() {
+        let positions = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+        let stats = compute(&Mesh::from_triangles(&positions, &[0, 1, 2, 0, 2, 3]));
+        assert_eq!((stats.edges, stats.boundary_edges, stats.boundary_loops), (5, 4, 1));
+        assert_eq!(stats.genus, Some(0));
+        assert_eq!(stats.volume, None);
+        assert_eq!(stats.area, 1.0);
+    }
+
+    #[test]
+    fn torus_has_genus_one() {
+        let stats = compute(&torus(8));
+        assert_eq!((stats.positions, stats.edges, stats.triangles), (64, 192, 128));
+        assert_eq!(stats.boundary_loops, 0);
+        assert_eq!(stats.genus, Some(1));
+        assert!(stats.volume.is_some());
+    }
+
+    #[test]
+    fn non_manifold_edges() {
+        // three triangles on the edge 0 1, and a degenerate one
+        let positions = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, 1.0], [2.0, 0.0, 0.0]];
+        let stats = compute(&Mesh::from_triangles(&positions, &[0, 1, 2, 1, 0, 3, 0, 1, 4, 0, 1, 5]));
+        assert_eq!(stats.non_manifold_edges, 1);
+        assert_eq!(stats.genus, None);
+        assert_eq!(stats.volume, None);
+        assert_eq!(stats.degenerate_triangles, 1);
+        assert_eq!(stats.components, 1);
+    }
+}
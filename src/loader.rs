@@ -0,0 +1,213 @@
+use std::{fmt, path::{Path, PathBuf}, fs::File, io::{self, Read}};
+
+use crate::mesh::Mesh;
+use crate::parse_obj;
+use crate::parse_gltf;
+use crate::parse_off;
+use crate::parse_ply;
+use crate::parse_stl;
+
+// choose a parser for a model file
+// ------------------------------------------------------------------------
+
+// error of every loader, with the file and the line it comes from
+#[derive(Debug)]
+pub enum MeshError {
+    // the file could not be opened or read
+    Io { path: PathBuf, line: usize, source: io::Error },
+    // the line starts with a keyword we don't know
+    UnknownDirective { path: PathBuf, line: usize, text: String },
+    // a coordinate or an index is not a valid number
+    MalformedNumber { path: PathBuf, line: usize, text: String },
+    // a face refers to a v/vt/vn that does not exist
+    IndexOutOfRange { path: PathBuf, line: usize, text: String },
+    // a face with less than 3 vertices, a line with less than 2 or a point element without any
+    DegenerateFace { path: PathBuf, line: usize, text: String },
+    // the file does not follow the layout of its format (json structure, binary chunks...)
+    InvalidData { path: PathBuf, text: String },
+    // no loader recognizes the extension or the content of the file
+    UnsupportedFormat { path: PathBuf },
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            // line 0: the file could not be opened
+            MeshError::Io { path, line: 0, source } =>
+                write!(f, "{}: {}", path.display(), source),
+            MeshError::Io { path, line, source } =>
+                write!(f, "{}:{}: {}", path.display(), line, source),
+            MeshError::UnknownDirective { path, line, text } =>
+                write!(f, "{}:{}: unknown directive\n>> {}", path.display(), line, text),
+            MeshError::MalformedNumber { path, line, text } =>
+                write!(f, "{}:{}: malformed number\n>> {}", path.display(), line, text),
+            MeshError::IndexOutOfRange { path, line, text } =>
+                write!(f, "{}:{}: index out of range\n>> {}", path.display(), line, text),
+            MeshError::DegenerateFace { path, line, text } =>
+                write!(f, "{}:{}: not enough vertices\n>> {}", path.display(), line, text),
+            MeshError::InvalidData { path, text } =>
+                write!(f, "{}: {}", path.display(), text),
+            MeshError::UnsupportedFormat { path } =>
+                write!(f, "{}: unsupported format", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for MeshError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MeshError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+// constructors matching the fields of the line error variants
+pub(crate) fn unknown_directive(path: PathBuf, line: usize, text: String) -> MeshError {
+    MeshError::UnknownDirective { path, line, text }
+}
+
+pub(crate) fn malformed_number(path: PathBuf, line: usize, text: String) -> MeshError {
+    MeshError::MalformedNumber { path, line, text }
+}
+
+pub(crate) fn index_out_of_range(path: PathBuf, line: usize, text: String) -> MeshError {
+    MeshError::IndexOutOfRange { path, line, text }
+}
+
+pub(crate) fn degenerate_face(path: PathBuf, line: usize, text: String) -> MeshError {
+    MeshError::DegenerateFace { path, line, text }
+}
+
+// number of bytes read from the start of a file to recognize its format
+const HEADER_SIZE: u64 = 512;
+
+// a file format that can be turned into a mesh
+pub trait MeshLoader: Sync {
+    // name of the format, for messages
+    fn name(&self) -> &'static str;
+    // lowercase extensions, without the dot
+    fn extensions(&self) -> &'static [&'static str];
+    // true if the start of the file (and its size) looks like this format
+    fn sniff(&self, header: &[u8], size: u64) -> bool;
+    fn load(&self, path: &Path) -> Result<Mesh, MeshError>;
+}
+
+// every known format. formats with a signature come first,
+// obj has none and only recognizes known statements
+static LOADERS: &[&dyn MeshLoader] = &[
+    &parse_gltf::GltfLoader,
+    &parse_ply::PlyLoader,
+    &parse_off::OffLoader,
+    &parse_stl::StlLoader,
+    &parse_obj::ObjLoader,
+];
+
+// parse a model with the loader of its format
+pub fn load_model(path: &Path) -> Result<Mesh, MeshError> {
+    find_loader(path)?.load(path)
+}
+
+// the loader of the extension when the content agrees with it,
+// otherwise the first one that recognizes the content (a .glb saved as .gltf, no extension...)
+// and at last the loader of the extension, so that its parser reports what is wrong
+pub fn find_loader(path: &Path) -> Result<&'static dyn MeshLoader, MeshError> {
+    let io_error = |source| MeshError::Io {
+        path: path.to_path_buf(),
+        line: 0,
+        source,
+    };
+    let mut file = File::open(path).map_err(io_error)?;
+    let size = file.metadata().map_err(io_error)?.len();
+    let mut header = Vec::new();
+    file.by_ref().take(HEADER_SIZE).read_to_end(&mut header).map_err(io_error)?;
+
+    let extension = path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    let by_extension = LOADERS.iter()
+        .find(|loader| extension.as_deref().is_some_and(|extension| loader.extensions().contains(&extension)));
+
+    if let Some(loader) = by_extension {
+        if loader.sniff(&header, size) {
+            return Ok(*loader);
+        }
+    }
+    if let Some(loader) = LOADERS.iter().find(|loader| loader.sniff(&header, size)) {
+        return Ok(*loader);
+    }
+    match by_extension {
+        Some(loader) => {
+            println!("warning: {} does not look like a {} file", path.display(), loader.name());
+            Ok(*loader)
+        }
+        None => Err(MeshError::UnsupportedFormat { path: path.to_path_buf() }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    // a copy of a fixture under another name, loaded by the loader its content matches
+    fn loader_of_copy(fixture: &str, name: &str) -> (&'static str, Result<Mesh, MeshError>) {
+        let path = std::env::temp_dir().join(format!("scop-test-{}-{}", std::process::id(), name));
+        fs::copy(format!("resources/objects/fixtures/{}", fixture), &path).unwrap();
+        let loader = find_loader(&path).map(|loader| loader.name());
+        let mesh = load_model(&path);
+        fs::remove_file(&path).unwrap();
+        (loader.unwrap(), mesh)
+    }
+
+    #[test]
+    fn extensions_choose_the_loader() {
+        for (fixture, expected) in [
+            ("pyramid_ascii.ply", "ply"),
+            ("cube_binary.stl", "stl"),
+            ("cube.off", "off"),
+            ("quads.glb", "gltf"),
+            ("colors.obj", "obj"),
+        ] {
+            let (loader, mesh) = loader_of_copy(fixture, fixture);
+            assert_eq!(loader, expected, "{}", fixture);
+            assert!(mesh.is_ok(), "{}", fixture);
+        }
+    }
+
+    #[test]
+    fn misnamed_files_are_routed_by_their_header() {
+        for (fixture, name, expected) in [
+            ("pyramid_le.ply", "ply.obj", "ply"),
+            ("pyramid_ascii.ply", "ply.stl", "ply"),
+            ("cube_ascii.stl", "stl.off", "stl"),
+            ("cube_binary.stl", "stl.ply", "stl"),
+            ("cube.off", "off.stl", "off"),
+            ("cube_face_colors.off", "off", "off"),
+            ("quads.glb", "glb.gltf", "gltf"),
+            ("colors.obj", "obj.txt", "obj"),
+        ] {
+            let (loader, mesh) = loader_of_copy(fixture, name);
+            assert_eq!(loader, expected, "{} as {}", fixture, name);
+            assert!(mesh.is_ok(), "{} as {}: {}", fixture, name, mesh.err().unwrap());
+        }
+    }
+
+    #[test]
+    fn unknown_files_are_unsupported() {
+        let path = std::env::temp_dir().join(format!("scop-test-{}-unknown.xyz", std::process::id()));
+        fs::write(&path, [0xff, 0xd8, 0xff, 0xe0, 0, 16, b'J', b'F', b'I', b'F', 0]).unwrap();
+        let error = load_model(&path).err();
+        fs::remove_file(&path).unwrap();
+        match error {
+            Some(error @ MeshError::UnsupportedFormat { .. }) => {
+                assert_eq!(error.to_string(), format!("{}: unsupported format", path.display()));
+            }
+            other => panic!("{:?}", other),
+        }
+
+        // a missing file is an io error, not an unsupported format
+        let missing = std::env::temp_dir().join(format!("scop-test-{}-missing.obj", std::process::id()));
+        assert!(matches!(load_model(&missing), Err(MeshError::Io { line: 0, .. })));
+    }
+}
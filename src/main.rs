@@ -4,17 +4,47 @@ use self::glfw::Context;
 extern crate gl;
 use self::gl::types::*;
 
-use std::ptr;
 use std::mem;
 use std::os::raw::c_void;
 use std::ffi::CStr;
 use std::fs::metadata;
-
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+
+#[cfg(feature = "bench")]
+mod bench;
+mod buffer;
+mod convert;
+mod half_edge;
+mod info;
+mod loader;
 mod macros;
+mod mesh;
+mod mesh_cache;
 mod model;
+mod parse_gltf;
+mod parse_mtl;
 mod parse_obj;
+#[cfg(feature = "bench")]
+mod parse_obj_sequential;
+mod parse_off;
+mod parse_ply;
+mod parse_stl;
+mod simplify;
+mod subdivide;
+mod tangent;
 mod texture;
+mod triangulate;
+mod uv;
+mod vector;
+mod weld;
 mod window;
+mod write_gltf;
+mod write_mtl;
+mod write_obj;
+mod write_ply;
+mod write_stl;
 
 mod shader;
 use shader::Shader;
@@ -22,6 +52,8 @@ use shader::Shader;
 mod matrix;
 use matrix::Matrix4;
 
+use window::Requests;
+
 // settings
 const SCR_WIDTH: u32 = 800;
 const SCR_HEIGHT: u32 = 600;
@@ -34,6 +66,34 @@ const MODEL_PATH: &str = "resources/objects/42/42.obj";
 
 fn main() {
 
+    // parser benchmark: scop --bench file.obj..., only in builds with the bench feature
+    #[cfg(feature = "bench")]
+    if std::env::args().nth(1).as_deref() == Some("--bench") {
+        let paths: Vec<String> = std::env::args().skip(2).collect();
+        bench::run(&paths);
+        return;
+    }
+
+    // headless conversion: scop convert [options] input output
+    if std::env::args().nth(1).as_deref() == Some("convert") {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+        if let Err(error) = convert::run(&args) {
+            println!("{}", error);
+            std::process::exit(1)
+        }
+        return;
+    }
+
+    // statistics of a model: scop info [--json] input
+    if std::env::args().nth(1).as_deref() == Some("info") {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+        if let Err(error) = info::run(&args) {
+            println!("{}", error);
+            std::process::exit(1)
+        }
+        return;
+    }
+
     let path;
     if let Some(argument) = std::env::args().nth(1) {
         path = argument;
@@ -62,7 +122,7 @@ fn main() {
     // lock fps to 60
     glfw.set_swap_interval(glfw::SwapInterval::Adaptive);
 
-    let (our_shader, vbo, vao, texture, indices, model) = unsafe {
+    let (our_shader, mut levels, texture, mesh, material_textures, model, mut uv_projection) = unsafe {
         // configure global opengl state
         // -----------------------------
         gl::Enable(gl::DEPTH_TEST);
@@ -73,62 +133,59 @@ fn main() {
             "src/shaders/shader.vs",
             "src/shaders/shader.fs");
 
-        let (vertices, indices) = parse_obj::load_model(&path);
-            
-        let model = model::generate_model_matrix(&vertices);
-
-        let (mut vbo, mut vao, mut ebo) = (0, 0, 0);
-        // vao: vertex array object
-        gl::GenVertexArrays(1, &mut vao);
-        // vbo: vertex buffer (coordinates)
-        gl::GenBuffers(1, &mut vbo);
-        // element buffer (faces)
-        gl::GenBuffers(1, &mut ebo);
-
-        gl::BindVertexArray(vao);
-
-        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
-        gl::BufferData(
-            gl::ARRAY_BUFFER,
-            (vertices.len() * mem::size_of::<GLfloat>()) as GLsizeiptr,
-            &vertices[0] as *const f32 as *const c_void,
-            gl::STATIC_DRAW
-        );
+        let mut mesh = match mesh_cache::load_model(Path::new(&path)) {
+            Ok(mesh) => mesh,
+            Err(error) => {
+                println!("{}", error);
+                std::process::exit(1)
+            }
+        };
+        let model = model::generate_model_matrix(&mesh.vertices);
+
+        // meshes without uvs get projected ones, that can be changed with the keyboard
+        let uv_projection = if mesh.has_tex_coords {
+            None
+        } else {
+            let bounds = model::bounding_box(&mesh.vertices);
+            uv::generate(&mut mesh, uv::PROJECTIONS[0], bounds);
+            Some(uv::PROJECTIONS[0])
+        };
+
+        let buffers = upload_mesh(&mesh);
 
-        gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
-        gl::BufferData(
-            gl::ELEMENT_ARRAY_BUFFER,
-            (indices.len() * mem::size_of::<GLfloat>()) as GLsizeiptr,
-            &indices[0] as *const u32 as *const c_void,    
-            gl::STATIC_DRAW
-        );
-        
-        // stride is the "jump" between vertices in the vbo
-        let stride = 3 * mem::size_of::<GLfloat>() as GLsizei;
-        // position attribute
-        gl::VertexAttribPointer(
-            0, 
-            3, 
-            gl::FLOAT, 
-            gl::FALSE, 
-            stride, 
-            ptr::null()
-        );
-        gl::EnableVertexAttribArray(0);
-        
         let texture = texture::load_texture("resources/textures/ponies.jpg");
         // let texture = texture::load_texture("resources/textures/galaxy.jpg");
+
+        // diffuse, dissolve and normal textures of each material
+        let material_textures: Vec<(Option<u32>, Option<u32>, Option<u32>)> = mesh.materials.iter()
+            .map(|material| (
+                material.diffuse_map.as_ref().and_then(|image| texture::load_material_texture(image)),
+                material.dissolve_map.as_ref().and_then(|image| texture::load_material_texture(image)),
+                material.bump_map.as_ref().and_then(|image| texture::load_material_texture(image)),
+            ))
+            .collect();
         
         // tell opengl for each sampler to which texture unit it belongs to (only has to be done once)
         // -------------------------------------------------------------------------------------------
         our_shader.use_program();
         our_shader.set_int(c_str!("texture1"), 0);
+        our_shader.set_int(c_str!("dissolveMap"), 1);
+        our_shader.set_int(c_str!("normalMap"), 2);
+        // scans are shown in their colors instead of shades of grey
+        our_shader.set_bool(c_str!("hasVertexColor"), mesh.has_colors);
+
+        // materials may be transparent
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
 
         // enable face culling to increase performances
         gl::Enable(gl::CULL_FACE);
         gl::CullFace(gl::BACK);  
 
-        (our_shader, vbo, vao, texture, indices, model)
+        // make "p" elements visible
+        gl::PointSize(4.0);
+
+        (our_shader, vec![(vec![mesh.clone()], vec![buffers])], texture, mesh, material_textures, model, uv_projection)
     };
 
     // needed for fps conter
@@ -166,6 +223,29 @@ fn main() {
     // used as fov (in degrees)
     let mut zoom = 45.0;
 
+    // objects and groups that can be shown / hidden
+    let mut scene = model::Model::new(&mesh);
+    let mut requests = Requests::default();
+
+    // radius of the sphere around the model once it is scaled to fit in [-1, 1]
+    let bounds = model::bounding_box(&mesh.vertices);
+    let (min, max) = bounds;
+    let (min, max) = (model.transform_point(min), model.transform_point(max));
+    let radius = (0..3).map(|i| (max[i] - min[i]).powi(2)).sum::<f32>().sqrt() / 2.0;
+    // coarser versions of the mesh, drawn when the model is small on screen.
+    // they are built in the background, the mesh itself is drawn until they are ready
+    let lods_receiver = {
+        let (sender, receiver) = mpsc::channel();
+        let mesh = mesh.clone();
+        thread::spawn(move || sender.send(simplify::generate_lods(&mesh)));
+        receiver
+    };
+    // level of detail chosen with the keyboard, None to pick it from the size on screen
+    let mut forced_lod: Option<usize> = None;
+    // index in `levels`: the loaded mesh and its levels of detail, then each level
+    // of subdivision (computed when it is first shown)
+    let mut subdivision = 0;
+
     // render loop
     // -----------
     while !window.should_close() {
@@ -209,8 +289,115 @@ fn main() {
             &mut delta_mix,
             &mut view,
             &mut right_mouse_pressed, 
+            &mut scene,
+            &mut requests,
         );
 
+        // save the mesh as it is displayed: centered and scaled to fit in [-1, 1]
+        if requests.export {
+            requests.export = false;
+            let export_path = Path::new(&path).with_extension("export.obj");
+            let mut exported = levels[subdivision].0[0].clone();
+            exported.transform(&model);
+            match write_obj::save_model(&exported, &export_path) {
+                Ok(()) => println!("saved {}", export_path.display()),
+                Err(error) => println!("{}: {}", export_path.display(), error),
+            }
+        }
+
+        if requests.subdivision_step != 0 {
+            let step = requests.subdivision_step;
+            requests.subdivision_step = 0;
+            if step < 0 {
+                subdivision = subdivision.saturating_sub(1);
+            } else if subdivision + 1 < levels.len() {
+                subdivision += 1;
+            } else {
+                let coarse = &levels[subdivision].0[0];
+                if subdivide::subdivided_triangles(coarse) > subdivide::MAX_TRIANGLES {
+                    println!("warning: subdivision level {} would have more than {} triangles", subdivision + 1, subdivide::MAX_TRIANGLES);
+                } else {
+                    let mut fine = subdivide::subdivide(coarse);
+                    if let Some(projection) = uv_projection {
+                        uv::generate(&mut fine, projection, bounds);
+                    }
+                    let buffers = unsafe { upload_mesh(&fine) };
+                    levels.push((vec![fine], vec![buffers]));
+                    subdivision += 1;
+                }
+            }
+            let scheme = match subdivide::scheme(&levels[0].0[0]) {
+                subdivide::Scheme::Loop => "loop",
+                subdivide::Scheme::CatmullClark => "catmull-clark",
+            };
+            println!("subdivision level {} ({}, {} triangles)", subdivision, scheme, levels[subdivision].0[0].indices.len() / 3);
+        }
+        // project the texture another way on every level
+        if requests.uv_change {
+            requests.uv_change = false;
+            match uv_projection {
+                Some(projection) => {
+                    let projection = projection.next();
+                    for (meshes, buffers) in &mut levels {
+                        for (level, buffers) in meshes.iter_mut().zip(buffers.iter_mut()) {
+                            uv::generate(level, projection, bounds);
+                            unsafe {
+                                delete_mesh(*buffers);
+                                *buffers = upload_mesh(level);
+                            }
+                        }
+                    }
+                    uv_projection = Some(projection);
+                    println!("texture projection: {}", projection.name());
+                }
+                None => println!("the model has its own texture coordinates"),
+            }
+        }
+
+        // only the loaded mesh has levels of detail (the first one is the mesh itself)
+        if let Ok(lods) = lods_receiver.try_recv() {
+            for mut lod in lods.into_iter().skip(1) {
+                if let Some(projection) = uv_projection {
+                    uv::generate(&mut lod, projection, bounds);
+                }
+                let buffers = unsafe { upload_mesh(&lod) };
+                levels[0].0.push(lod);
+                levels[0].1.push(buffers);
+            }
+            if levels[0].0.len() > 1 {
+                let counts: Vec<String> = levels[0].0.iter().map(|lod| (lod.indices.len() / 3).to_string()).collect();
+                println!("levels of detail: {} triangles", counts.join(" / "));
+            }
+        }
+        let (lods, lod_buffers) = &levels[subdivision];
+
+        if requests.lod_change {
+            requests.lod_change = false;
+            forced_lod = match forced_lod {
+                None => Some(0),
+                Some(level) if level + 1 < levels[0].0.len() => Some(level + 1),
+                Some(_) => None,
+            };
+            match forced_lod {
+                Some(level) => println!("level of detail {} ({} triangles)", level, levels[0].0[level].indices.len() / 3),
+                None => println!("level of detail: automatic"),
+            }
+        }
+
+        // finest level with a few pixels per triangle: the radius of the model on screen
+        // is its radius over its distance to the camera, scaled by the focal length
+        let lod = forced_lod.map(|level| level.min(lods.len() - 1)).unwrap_or_else(|| {
+            let center = (transformation * view).transform_point([0.0, 0.0, 0.0]);
+            let distance = -center[2];
+            if distance <= radius {
+                return 0;
+            }
+            let (_, height) = window.get_framebuffer_size();
+            let radius_px = radius / distance * projection.y()[1] * height as f32 / 2.0;
+            simplify::select_lod(lods, radius_px)
+        });
+        let (lod_mesh, (vao, _, _)) = (&lods[lod], lod_buffers[lod]);
+
         // render
         // ------
         unsafe {
@@ -218,10 +405,6 @@ fn main() {
             gl::ClearColor(0.2, 0.3, 0.3, 1.0);
             gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
 
-            // bind textures on corresponding texture units
-            gl::ActiveTexture(gl::TEXTURE0);
-            gl::BindTexture(gl::TEXTURE_2D, texture);
-            
             // activate shader
             // our_shader.use_program();
             
@@ -240,13 +423,52 @@ fn main() {
             our_shader.set_mat4(c_str!("view"), &view);
 			our_shader.set_float(c_str!("textureMix"), texture_mix);
 
-            // draw drame
-            gl::DrawElements(
-                gl::TRIANGLES, 
-                indices.len() as i32, 
-                gl::UNSIGNED_INT, 
-                ptr::null()
-            );
+            // draw frame, one call per material and primitive
+            for sub_mesh in &lod_mesh.sub_meshes {
+                if !scene.is_visible(sub_mesh.node) {
+                    continue;
+                }
+                // highlight the selected node (and the groups of a selected object)
+                our_shader.set_bool(
+                    c_str!("highlight"), 
+                    scene.nodes.len() > 1 && scene.is_in_selection(sub_mesh.node)
+                );
+
+                let material = sub_mesh.material.map(|m| &mesh.materials[m]);
+                let (diffuse_map, dissolve_map, normal_map) = sub_mesh.material
+                    .map_or((None, None, None), |m| material_textures[m]);
+
+                let diffuse = material.map_or([1.0, 1.0, 1.0], |m| m.diffuse);
+                our_shader.set_vec3(c_str!("diffuseColor"), diffuse[0], diffuse[1], diffuse[2]);
+                our_shader.set_float(c_str!("dissolve"), material.map_or(1.0, |m| m.dissolve));
+                our_shader.set_bool(c_str!("hasDissolveMap"), dissolve_map.is_some());
+                our_shader.set_bool(c_str!("hasNormalMap"), normal_map.is_some());
+
+                // bind textures on corresponding texture units
+                gl::ActiveTexture(gl::TEXTURE0);
+                gl::BindTexture(gl::TEXTURE_2D, diffuse_map.or(texture).unwrap_or(0));
+                if let Some(dissolve_map) = dissolve_map {
+                    gl::ActiveTexture(gl::TEXTURE1);
+                    gl::BindTexture(gl::TEXTURE_2D, dissolve_map);
+                }
+                if let Some(normal_map) = normal_map {
+                    gl::ActiveTexture(gl::TEXTURE2);
+                    gl::BindTexture(gl::TEXTURE_2D, normal_map);
+                }
+
+                // start of the primitive's list in the element buffer
+                let (mode, first) = match sub_mesh.primitive {
+                    mesh::Primitive::Triangles => (gl::TRIANGLES, 0),
+                    mesh::Primitive::Lines => (gl::LINES, lod_mesh.indices.len()),
+                    mesh::Primitive::Points => (gl::POINTS, lod_mesh.indices.len() + lod_mesh.lines.len()),
+                };
+                gl::DrawElements(
+                    mode, 
+                    sub_mesh.count as i32, 
+                    gl::UNSIGNED_INT, 
+                    ((first + sub_mesh.start) * mem::size_of::<GLuint>()) as *const c_void
+                );
+            }
         }
 
         // glfw: swap buffers and poll IO events (keys pressed/released, mouse moved etc.)
@@ -258,7 +480,102 @@ fn main() {
     // optional: de-allocate all resources once they've outlived their purpose:
     // ------------------------------------------------------------------------
     unsafe {
-        gl::DeleteVertexArrays(1, &vao);
-        gl::DeleteBuffers(1, &vbo);
+        for &buffers in levels.iter().flat_map(|(_, buffers)| buffers) {
+            delete_mesh(buffers);
+        }
     }
 }
+
+// vertex array, vertex buffer and element buffer of a mesh
+unsafe fn upload_mesh(mesh: &mesh::Mesh) -> (GLuint, GLuint, GLuint) {
+    let vertices = &mesh.vertices;
+    // triangles, then line segments, then points in the same element buffer
+    let elements = [&mesh.indices[..], &mesh.lines[..], &mesh.points[..]].concat();
+        
+    let (mut vbo, mut vao, mut ebo) = (0, 0, 0);
+    // vao: vertex array object
+    gl::GenVertexArrays(1, &mut vao);
+    // vbo: vertex buffer (coordinates)
+    gl::GenBuffers(1, &mut vbo);
+    // element buffer (faces)
+    gl::GenBuffers(1, &mut ebo);
+
+    gl::BindVertexArray(vao);
+
+    gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+    gl::BufferData(
+        gl::ARRAY_BUFFER,
+        (vertices.len() * mem::size_of::<GLfloat>()) as GLsizeiptr,
+        vertices.as_ptr() as *const c_void,
+        gl::STATIC_DRAW
+    );
+
+    gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
+    gl::BufferData(
+        gl::ELEMENT_ARRAY_BUFFER,
+        (elements.len() * mem::size_of::<GLuint>()) as GLsizeiptr,
+        elements.as_ptr() as *const c_void,
+        gl::STATIC_DRAW
+    );
+    
+    // stride is the "jump" between vertices in the vbo
+    let stride = (mesh::VERTEX_SIZE * mem::size_of::<GLfloat>()) as GLsizei;
+    // position attribute
+    gl::VertexAttribPointer(
+        0, 
+        3, 
+        gl::FLOAT, 
+        gl::FALSE, 
+        stride, 
+        (mesh::POSITION_OFFSET * mem::size_of::<GLfloat>()) as *const c_void
+    );
+    gl::EnableVertexAttribArray(0);
+    // texture coordinates attribute
+    gl::VertexAttribPointer(
+        1, 
+        2, 
+        gl::FLOAT, 
+        gl::FALSE, 
+        stride, 
+        (mesh::TEX_COORD_OFFSET * mem::size_of::<GLfloat>()) as *const c_void
+    );
+    gl::EnableVertexAttribArray(1);
+    // normal attribute
+    gl::VertexAttribPointer(
+        2, 
+        3, 
+        gl::FLOAT, 
+        gl::FALSE, 
+        stride, 
+        (mesh::NORMAL_OFFSET * mem::size_of::<GLfloat>()) as *const c_void
+    );
+    gl::EnableVertexAttribArray(2);
+    // vertex color attribute
+    gl::VertexAttribPointer(
+        3, 
+        3, 
+        gl::FLOAT, 
+        gl::FALSE, 
+        stride, 
+        (mesh::COLOR_OFFSET * mem::size_of::<GLfloat>()) as *const c_void
+    );
+    gl::EnableVertexAttribArray(3);
+    // tangent attribute (and the sign of the bitangent)
+    gl::VertexAttribPointer(
+        4, 
+        4, 
+        gl::FLOAT, 
+        gl::FALSE, 
+        stride, 
+        (mesh::TANGENT_OFFSET * mem::size_of::<GLfloat>()) as *const c_void
+    );
+    gl::EnableVertexAttribArray(4);
+
+    (vao, vbo, ebo)
+}
+
+unsafe fn delete_mesh((vao, vbo, ebo): (GLuint, GLuint, GLuint)) {
+    gl::DeleteVertexArrays(1, &vao);
+    gl::DeleteBuffers(1, &vbo);
+    gl::DeleteBuffers(1, &ebo);
+}
@@ -157,6 +157,26 @@ impl Matrix4 {
         ])
     }
 
+    // scale with a different factor on each axis
+    pub fn from_scale_xyz(x: f32, y: f32, z: f32) -> Self {
+        Self::new([
+            x,    0.0,  0.0,  0.0,
+            0.0,  y,    0.0,  0.0,
+            0.0,  0.0,  z,    0.0,
+            0.0,  0.0,  0.0,  1.0
+        ])
+    }
+
+    // rotation given by a unit quaternion (x, y, z, w)
+    pub fn from_quaternion(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Self::new([
+            1.0 - 2.0 * (y * y + z * z),  2.0 * (x * y + z * w),        2.0 * (x * z - y * w),        0.0,
+            2.0 * (x * y - z * w),        1.0 - 2.0 * (x * x + z * z),  2.0 * (y * z + x * w),        0.0,
+            2.0 * (x * z + y * w),        2.0 * (y * z - x * w),        1.0 - 2.0 * (x * x + y * y),  0.0,
+            0.0,                          0.0,                          0.0,                          1.0
+        ])
+    }
+
     pub fn from_translation(x: f32, y: f32, z: f32) -> Self {
         Self::new([
             1.0,  0.0,  0.0,  0.0,
@@ -166,6 +186,26 @@ impl Matrix4 {
         ])
     }
 
+    // apply the matrix to a point (w = 1)
+    pub fn transform_point(&self, p: [f32; 3]) -> [f32; 3] {
+        let a = &self.array;
+        [
+            a[0] * p[0] + a[4] * p[1] + a[8] * p[2] + a[12],
+            a[1] * p[0] + a[5] * p[1] + a[9] * p[2] + a[13],
+            a[2] * p[0] + a[6] * p[1] + a[10] * p[2] + a[14],
+        ]
+    }
+
+    // apply the matrix to a direction (w = 0), the translation is ignored
+    pub fn transform_vector(&self, v: [f32; 3]) -> [f32; 3] {
+        let a = &self.array;
+        [
+            a[0] * v[0] + a[4] * v[1] + a[8] * v[2],
+            a[1] * v[0] + a[5] * v[1] + a[9] * v[2],
+            a[2] * v[0] + a[6] * v[1] + a[10] * v[2],
+        ]
+    }
+
     pub fn perspective(
         fovy: f32, // field of view (in degrees) in the y direction
         aspect: f32, // aspect ratio (width/height)
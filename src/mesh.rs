@@ -0,0 +1,198 @@
+#![allow(dead_code)]
+
+use std::{path::PathBuf, sync::Arc};
+
+use crate::buffer::Buffer;
+use crate::matrix::Matrix4;
+use crate::vector;
+
+// layout of one vertex in the interleaved vertex buffer (in floats)
+// ------------------------------------------------------------------
+// position: x, y, z
+pub const POSITION_OFFSET: usize = 0;
+// texture coordinates: u, v
+pub const TEX_COORD_OFFSET: usize = 3;
+// normal: x, y, z
+pub const NORMAL_OFFSET: usize = 5;
+// vertex color: r, g, b (white when the file has none)
+pub const COLOR_OFFSET: usize = 8;
+// tangent: x, y, z, and the sign of the bitangent in w (see tangent::generate)
+pub const TANGENT_OFFSET: usize = 11;
+// total size of a vertex
+pub const VERTEX_SIZE: usize = 15;
+
+#[derive(Clone)]
+pub struct Mesh {
+    // interleaved vertex attributes (see VERTEX_SIZE), in place in the mesh cache when
+    // the mesh comes from it
+    pub vertices: Buffer<f32>,
+    // triangles (3 indices per face), grouped by sub-mesh
+    pub indices: Buffer<u32>,
+    // line segments (2 indices each, "l" elements), grouped by sub-mesh
+    pub lines: Vec<u32>,
+    // points (1 index each, "p" elements), grouped by sub-mesh
+    pub points: Vec<u32>,
+    // corners of the polygons the triangles were cut from, in the same order:
+    // a polygon of n corners gave the next n - 2 triangles of `indices`.
+    // empty when the file only had triangles or the polygons were lost
+    pub polygons: Vec<u32>,
+    // number of corners of each polygon
+    pub polygon_sizes: Vec<u32>,
+    // false when the file had no texture coordinates
+    pub has_tex_coords: bool,
+    // false when the file had no vertex colors
+    pub has_colors: bool,
+    pub materials: Vec<Material>,
+    // .mtl files (or .bin buffers of a .gltf) referenced by the model, found or not,
+    // the mesh cache is rebuilt when one of them changes
+    pub material_libraries: Vec<PathBuf>,
+    // named objects and groups
+    pub nodes: Vec<Node>,
+    // ranges of indices drawn with the same node, material and primitive
+    pub sub_meshes: Vec<SubMesh>,
+}
+
+impl Mesh {
+    // move the vertices, e.g. with the matrix of model::generate_model_matrix
+    // (normals are only rotated, the matrix must not scale unevenly)
+    pub fn transform(&mut self, matrix: &Matrix4) {
+        for vertex in self.vertices.chunks_exact_mut(VERTEX_SIZE) {
+            let p = POSITION_OFFSET;
+            let position = matrix.transform_point([vertex[p], vertex[p + 1], vertex[p + 2]]);
+            vertex[p..p + 3].copy_from_slice(&position);
+
+            let n = NORMAL_OFFSET;
+            // lines and points have no normal, it stays null
+            let normal = matrix.transform_vector([vertex[n], vertex[n + 1], vertex[n + 2]]);
+            vertex[n..n + 3].copy_from_slice(&vector::normalize(normal));
+        }
+    }
+}
+
+#[cfg(test)]
+impl Mesh {
+    // triangles over a list of positions, a white vertex without normal per position
+    pub fn from_triangles(positions: &[[f32; 3]], indices: &[u32]) -> Self {
+        let mut vertices = Vec::with_capacity(positions.len() * VERTEX_SIZE);
+        for position in positions {
+            let mut vertex = [0.0; VERTEX_SIZE];
+            vertex[POSITION_OFFSET..POSITION_OFFSET + 3].copy_from_slice(position);
+            vertex[COLOR_OFFSET..COLOR_OFFSET + 3].copy_from_slice(&[1.0; 3]);
+            vertices.extend_from_slice(&vertex);
+        }
+        Self {
+            vertices: vertices.into(),
+            indices: indices.to_vec().into(),
+            lines: Vec::new(),
+            points: Vec::new(),
+            polygons: Vec::new(),
+            polygon_sizes: Vec::new(),
+            has_tex_coords: false,
+            has_colors: false,
+            materials: Vec::new(),
+            material_libraries: Vec::new(),
+            nodes: vec![Node { name: String::from("test"), kind: NodeKind::Object, parent: None }],
+            sub_meshes: vec![SubMesh { node: 0, material: None, primitive: Primitive::Triangles, start: 0, count: indices.len() }],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NodeKind {
+    // "o" statement
+    Object,
+    // "g" statement
+    Group,
+}
+
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub name: String,
+    pub kind: NodeKind,
+    // index in Mesh::nodes of the object a group belongs to
+    pub parent: Option<usize>,
+}
+
+// kind of element a sub-mesh is made of
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Primitive {
+    // "f", in Mesh::indices
+    Triangles,
+    // "l", in Mesh::lines
+    Lines,
+    // "p", in Mesh::points
+    Points,
+}
+
+#[derive(Clone)]
+pub struct SubMesh {
+    // index in Mesh::nodes
+    pub node: usize,
+    // index in Mesh::materials, None when the faces have no (known) material
+    pub material: Option<usize>,
+    pub primitive: Primitive,
+    // range in the index list of the primitive
+    pub start: usize,
+    pub count: usize,
+}
+
+// surface description, as found in .mtl files
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub name: String,
+    // Ka
+    pub ambient: [f32; 3],
+    // Kd
+    pub diffuse: [f32; 3],
+    // Ks
+    pub specular: [f32; 3],
+    // Ns
+    pub shininess: f32,
+    // d (1.0 is fully opaque)
+    pub dissolve: f32,
+    // illumination model
+    pub illum: u32,
+    // map_Kd
+    pub diffuse_map: Option<TextureImage>,
+    // map_Bump / bump / norm, drawn as a tangent space normal map
+    pub bump_map: Option<TextureImage>,
+    // map_d
+    pub dissolve_map: Option<TextureImage>,
+}
+
+// where the image of a texture map comes from
+#[derive(Clone, Debug, PartialEq)]
+pub enum TextureImage {
+    File(PathBuf),
+    // encoded png or jpeg stored in the model itself (glb buffer or data uri),
+    // shared between the copies of the mesh
+    Embedded(Arc<[u8]>),
+}
+
+impl TextureImage {
+    // extension of a file holding the image
+    pub fn extension(&self) -> &str {
+        match self {
+            TextureImage::File(path) => path.extension().and_then(|extension| extension.to_str()).unwrap_or("png"),
+            TextureImage::Embedded(data) if data.starts_with(&[0xFF, 0xD8]) => "jpg",
+            TextureImage::Embedded(_) => "png",
+        }
+    }
+}
+
+impl Material {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: String::from(name),
+            ambient: [0.0, 0.0, 0.0],
+            diffuse: [1.0, 1.0, 1.0],
+            specular: [0.0, 0.0, 0.0],
+            shininess: 0.0,
+            dissolve: 1.0,
+            illum: 1,
+            diffuse_map: None,
+            bump_map: None,
+            dissolve_map: None,
+        }
+    }
+}
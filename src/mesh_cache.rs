@@ -0,0 +1,596 @@
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    slice,
+    sync::Arc,
+    time::UNIX_EPOCH,
+};
+
+use memmap2::Mmap;
+
+use crate::buffer::{Buffer, Plain};
+use crate::mesh::{Material, Mesh, Node, NodeKind, Primitive, SubMesh, TextureImage, VERTEX_SIZE};
+use crate::loader;
+use crate::tangent;
+use crate::loader::MeshError;
+use crate::weld::{self, WeldOptions};
+
+// binary copy of a parsed mesh, stored next to the model file ("model.obj.scopcache")
+// or in the user cache directory when that is not writable
+// ------------------------------------------------------------------------
+const MAGIC: &[u8; 8] = b"SCOPMESH";
+// bump when the layout of the file or of the mesh changes
+const VERSION: u32 = 9;
+// written in native byte order: a cache made on a machine with another endianness is ignored
+const BYTE_ORDER: u32 = 0x0102_0304;
+// the lists of values start at a multiple of this in the file, so that they can be used
+// where the file is mapped
+const BLOCK_ALIGNMENT: usize = 8;
+const EXTENSION: &str = "scopcache";
+
+// load a mesh from its cache when it is fresh, otherwise parse it and write the cache
+pub fn load_model(path: &Path) -> Result<Mesh, MeshError> {
+    for cache in cache_paths(path) {
+        if let Some(mesh) = read_cache(&cache, path) {
+            return Ok(mesh);
+        }
+    }
+
+    let mut mesh = loader::load_model(path)?;
+    // identical vertices are merged before the mesh is cached and uploaded
+    let merged = weld::weld(&mut mesh, &WeldOptions::exact());
+    if merged > 0 {
+        println!("merged {} duplicate vertices", merged);
+    }
+    tangent::generate(&mut mesh);
+
+    if let Err(error) = write_cache(path, &mesh) {
+        println!("warning: could not write mesh cache: {}", error);
+    }
+
+    Ok(mesh)
+}
+
+// where the cache of a file can be: next to it first, then in the cache directory
+fn cache_paths(path: &Path) -> Vec<PathBuf> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut paths = vec![path.with_file_name(format!("{}.{}", file_name, EXTENSION))];
+
+    let directory = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")));
+    if let Some(directory) = directory {
+        // files with the same name in different directories must not share a cache
+        let absolute = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let key = fnv1a(FNV_OFFSET, absolute.to_string_lossy().as_bytes());
+        paths.push(directory.join("scop").join(format!("{:016x}-{}.{}", key, file_name, EXTENSION)));
+    }
+
+    paths
+}
+
+fn write_cache(path: &Path, mesh: &Mesh) -> io::Result<()> {
+    let source = Stamp::of(path);
+    let hash = hash_file(path)?;
+
+    let mut last_error = None;
+    for cache in cache_paths(path) {
+        if let Some(directory) = cache.parent() {
+            if let Err(error) = fs::create_dir_all(directory) {
+                last_error = Some(error);
+                continue;
+            }
+        }
+
+        // write to a temporary file so that a crash never leaves a truncated cache behind
+        let temporary = cache.with_extension(format!("{}.tmp", EXTENSION));
+        let result = File::create(&temporary)
+            .and_then(|file| {
+                let mut writer = Writer { inner: BufWriter::new(file), position: 0 };
+                writer.mesh(mesh, &source, hash)?;
+                writer.inner.flush()
+            })
+            .and_then(|_| fs::rename(&temporary, &cache));
+
+        match result {
+            Ok(()) => return Ok(()),
+            Err(error) => {
+                let _ = fs::remove_file(&temporary);
+                last_error = Some(error);
+            }
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no cache directory")))
+}
+
+// the cached mesh, or None if the cache is missing, stale or unreadable
+fn read_cache(cache: &Path, path: &Path) -> Option<Mesh> {
+    let file = File::open(cache).ok()?;
+    // the vertices and triangles are used in place. caches are written to a temporary
+    // file then renamed, so a mapped cache is never changed: a new one replaces it
+    let map = unsafe { Mmap::map(&file) }.ok()?;
+    let mut reader = Reader { map: Arc::new(map), position: 0 };
+
+    if reader.bytes(MAGIC.len()).ok()? != MAGIC
+        || reader.u32().ok()? != VERSION
+        || reader.u32().ok()? != BYTE_ORDER
+        || reader.u32().ok()? as usize != VERTEX_SIZE {
+        return None;
+    }
+
+    // same size and modification time as when the cache was written,
+    // or the same content (the file was touched or copied)
+    let source = reader.stamp().ok()?;
+    let hash = reader.u64().ok()?;
+    if source != Stamp::of(path) && hash_file(path).ok()? != hash {
+        return None;
+    }
+
+    // the material libraries must not have changed either
+    let mut material_libraries = Vec::new();
+    for _ in 0..reader.u32().ok()? {
+        let library = reader.path().ok()??;
+        if reader.stamp().ok()? != Stamp::of(&library) {
+            return None;
+        }
+        material_libraries.push(library);
+    }
+
+    reader.mesh(material_libraries).ok()
+}
+
+// size and modification time of a file, all zeros when it does not exist
+#[derive(PartialEq, Eq)]
+struct Stamp {
+    size: u64,
+    seconds: u64,
+    nanos: u32,
+}
+
+impl Stamp {
+    fn of(path: &Path) -> Self {
+        let metadata = fs::metadata(path).ok();
+        let modified = metadata.as_ref()
+            .and_then(|metadata| metadata.modified().ok())
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+
+        Self {
+            size: metadata.map_or(0, |metadata| metadata.len()),
+            seconds: modified.as_secs(),
+            nanos: modified.subsec_nanos(),
+        }
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+fn hash_file(path: &Path) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0; 1 << 20];
+    let mut hash = FNV_OFFSET;
+
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(hash);
+        }
+        hash = fnv1a(hash, &buffer[..read]);
+    }
+}
+
+struct Writer<W: Write> {
+    inner: W,
+    // bytes written so far, to align the blocks
+    position: usize,
+}
+
+impl<W: Write> Writer<W> {
+    fn mesh(&mut self, mesh: &Mesh, source: &Stamp, hash: u64) -> io::Result<()> {
+        self.write(MAGIC)?;
+        self.u32(VERSION)?;
+        self.u32(BYTE_ORDER)?;
+        self.u32(VERTEX_SIZE as u32)?;
+
+        self.stamp(source)?;
+        self.u64(hash)?;
+        self.u32(mesh.material_libraries.len() as u32)?;
+        for library in &mesh.material_libraries {
+            self.path(Some(library))?;
+            self.stamp(&Stamp::of(library))?;
+        }
+
+        self.u8(mesh.has_tex_coords as u8)?;
+        self.u8(mesh.has_colors as u8)?;
+        self.raw(&mesh.vertices)?;
+        self.raw(&mesh.indices)?;
+        self.raw(&mesh.lines)?;
+        self.raw(&mesh.points)?;
+        self.raw(&mesh.polygons)?;
+        self.raw(&mesh.polygon_sizes)?;
+
+        self.u32(mesh.materials.len() as u32)?;
+        for material in &mesh.materials {
+            self.string(&material.name)?;
+            self.raw(&material.ambient)?;
+            self.raw(&material.diffuse)?;
+            self.raw(&material.specular)?;
+            self.raw(&[material.shininess, material.dissolve])?;
+            self.u32(material.illum)?;
+            self.texture(material.diffuse_map.as_ref())?;
+            self.texture(material.bump_map.as_ref())?;
+            self.texture(material.dissolve_map.as_ref())?;
+        }
+
+        self.u32(mesh.nodes.len() as u32)?;
+        for node in &mesh.nodes {
+            self.string(&node.name)?;
+            self.u8(match node.kind {
+                NodeKind::Object => 0,
+                NodeKind::Group => 1,
+            })?;
+            self.optional_index(node.parent)?;
+        }
+
+        self.u32(mesh.sub_meshes.len() as u32)?;
+        for sub_mesh in &mesh.sub_meshes {
+            self.u64(sub_mesh.node as u64)?;
+            self.optional_index(sub_mesh.material)?;
+            self.u8(match sub_mesh.primitive {
+                Primitive::Triangles => 0,
+                Primitive::Lines => 1,
+                Primitive::Points => 2,
+            })?;
+            self.u64(sub_mesh.start as u64)?;
+            self.u64(sub_mesh.count as u64)?;
+        }
+
+        Ok(())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.position += bytes.len();
+        self.inner.write_all(bytes)
+    }
+
+    fn u8(&mut self, value: u8) -> io::Result<()> {
+        self.write(&[value])
+    }
+
+    fn u32(&mut self, value: u32) -> io::Result<()> {
+        self.write(&value.to_ne_bytes())
+    }
+
+    fn u64(&mut self, value: u64) -> io::Result<()> {
+        self.write(&value.to_ne_bytes())
+    }
+
+    // u64::MAX stands for None
+    fn optional_index(&mut self, value: Option<usize>) -> io::Result<()> {
+        self.u64(value.map_or(u64::MAX, |value| value as u64))
+    }
+
+    fn stamp(&mut self, stamp: &Stamp) -> io::Result<()> {
+        self.u64(stamp.size)?;
+        self.u64(stamp.seconds)?;
+        self.u32(stamp.nanos)
+    }
+
+    fn string(&mut self, value: &str) -> io::Result<()> {
+        self.u64(value.len() as u64)?;
+        self.write(value.as_bytes())
+    }
+
+    fn path(&mut self, value: Option<&Path>) -> io::Result<()> {
+        match value {
+            Some(value) => {
+                self.u8(1)?;
+                self.string(&value.to_string_lossy())
+            }
+            None => self.u8(0),
+        }
+    }
+
+    // embedded images are stored whole, the cache doesn't depend on any other file for them
+    fn texture(&mut self, value: Option<&TextureImage>) -> io::Result<()> {
+        match value {
+            Some(TextureImage::File(path)) => self.path(Some(path)),
+            Some(TextureImage::Embedded(data)) => {
+                self.u8(2)?;
+                self.raw(data)
+            }
+            None => self.u8(0),
+        }
+    }
+
+    // length followed by a plain copy of the memory, which starts at a multiple of
+    // BLOCK_ALIGNMENT in the file to be used in place
+    fn raw<T: Plain>(&mut self, values: &[T]) -> io::Result<()> {
+        self.u64(values.len() as u64)?;
+        self.write(&[0; BLOCK_ALIGNMENT][..padding(self.position)])?;
+        let bytes = unsafe {
+            slice::from_raw_parts(values.as_ptr() as *const u8, std::mem::size_of_val(values))
+        };
+        self.write(bytes)
+    }
+}
+
+// reads the cache in its memory mapping
+struct Reader {
+    map: Arc<Mmap>,
+    position: usize,
+}
+
+impl Reader {
+    fn mesh(&mut self, material_libraries: Vec<PathBuf>) -> io::Result<Mesh> {
+        let has_tex_coords = self.u8()? != 0;
+        let has_colors = self.u8()? != 0;
+        let vertices = self.buffer::<f32>()?;
+        let indices = self.buffer::<u32>()?;
+        let lines = self.raw::<u32>()?;
+        let points = self.raw::<u32>()?;
+        let polygons = self.raw::<u32>()?;
+        let polygon_sizes = self.raw::<u32>()?;
+
+        let mut materials = Vec::new();
+        for _ in 0..self.u32()? {
+            let mut material = Material::new(&self.string()?);
+            material.ambient = self.array()?;
+            material.diffuse = self.array()?;
+            material.specular = self.array()?;
+            [material.shininess, material.dissolve] = self.array()?;
+            material.illum = self.u32()?;
+            material.diffuse_map = self.texture()?;
+            material.bump_map = self.texture()?;
+            material.dissolve_map = self.texture()?;
+            materials.push(material);
+        }
+
+        let mut nodes = Vec::new();
+        for _ in 0..self.u32()? {
+            let name = self.string()?;
+            let kind = match self.u8()? {
+                0 => NodeKind::Object,
+                1 => NodeKind::Group,
+                _ => return Err(invalid()),
+            };
+            let parent = self.optional_index()?;
+            nodes.push(Node { name, kind, parent });
+        }
+
+        let mut sub_meshes = Vec::new();
+        for _ in 0..self.u32()? {
+            let node = self.u64()? as usize;
+            let material = self.optional_index()?;
+            let primitive = match self.u8()? {
+                0 => Primitive::Triangles,
+                1 => Primitive::Lines,
+                2 => Primitive::Points,
+                _ => return Err(invalid()),
+            };
+            sub_meshes.push(SubMesh {
+                node,
+                material,
+                primitive,
+                start: self.u64()? as usize,
+                count: self.u64()? as usize,
+            });
+        }
+
+        // a cache pointing outside of its own buffers is corrupted
+        let vertex_count = vertices.len() / VERTEX_SIZE;
+        if indices.iter().chain(&lines).chain(&points).chain(&polygons).any(|&i| i as usize >= vertex_count)
+            || polygon_sizes.iter().map(|&size| size as usize).sum::<usize>() != polygons.len()
+            || nodes.iter().any(|n| matches!(n.parent, Some(p) if p >= nodes.len()))
+            || sub_meshes.iter().any(|s| {
+                s.node >= nodes.len()
+                    || matches!(s.material, Some(m) if m >= materials.len())
+                    || s.start.checked_add(s.count).is_none_or(|end| end > match s.primitive {
+                        Primitive::Triangles => indices.len(),
+                        Primitive::Lines => lines.len(),
+                        Primitive::Points => points.len(),
+                    })
+            }) {
+            return Err(invalid());
+        }
+
+        Ok(Mesh {
+            vertices,
+            indices,
+            lines,
+            points,
+            polygons,
+            polygon_sizes,
+            has_tex_coords,
+            has_colors,
+            materials,
+            material_libraries,
+            nodes,
+            sub_meshes,
+        })
+    }
+
+    // the next `len` bytes
+    fn bytes(&mut self, len: usize) -> io::Result<&[u8]> {
+        let end = self.position.checked_add(len).filter(|&end| end <= self.map.len()).ok_or_else(invalid)?;
+        let bytes = &self.map[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_ne_bytes(self.bytes(4)?.try_into().map_err(|_| invalid())?))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_ne_bytes(self.bytes(8)?.try_into().map_err(|_| invalid())?))
+    }
+
+    fn optional_index(&mut self) -> io::Result<Option<usize>> {
+        let value = self.u64()?;
+        Ok(if value == u64::MAX { None } else { Some(value as usize) })
+    }
+
+    fn stamp(&mut self) -> io::Result<Stamp> {
+        Ok(Stamp {
+            size: self.u64()?,
+            seconds: self.u64()?,
+            nanos: self.u32()?,
+        })
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let len = usize::try_from(self.u64()?).map_err(|_| invalid())?;
+        String::from_utf8(self.bytes(len)?.to_vec()).map_err(|_| invalid())
+    }
+
+    fn path(&mut self) -> io::Result<Option<PathBuf>> {
+        match self.u8()? {
+            0 => Ok(None),
+            _ => Ok(Some(PathBuf::from(self.string()?))),
+        }
+    }
+
+    fn texture(&mut self) -> io::Result<Option<TextureImage>> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(TextureImage::File(PathBuf::from(self.string()?)))),
+            2 => Ok(Some(TextureImage::Embedded(self.raw::<u8>()?.into()))),
+            _ => Err(invalid()),
+        }
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[f32; N]> {
+        let values = self.raw::<f32>()?;
+        values.try_into().map_err(|_| invalid())
+    }
+
+    // a block written by Writer::raw, left in the mapping
+    fn buffer<T: Plain>(&mut self) -> io::Result<Buffer<T>> {
+        let len = usize::try_from(self.u64()?).map_err(|_| invalid())?;
+        self.bytes(padding(self.position))?;
+        let size = len.checked_mul(std::mem::size_of::<T>()).ok_or_else(invalid)?;
+        let offset = self.position;
+        self.bytes(size)?;
+        Buffer::mapped(self.map.clone(), offset, len).ok_or_else(invalid)
+    }
+
+    // a block copied out of the mapping, for the small ones
+    fn raw<T: Plain>(&mut self) -> io::Result<Vec<T>> {
+        Ok(self.buffer()?.into_vec())
+    }
+}
+
+// zeros written before a block at `position` to align it
+fn padding(position: usize) -> usize {
+    (BLOCK_ALIGNMENT - position % BLOCK_ALIGNMENT) % BLOCK_ALIGNMENT
+}
+
+fn invalid() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "corrupted mesh cache")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a copy of the 42 model and its .mtl in a new directory, with its cache written
+    fn cached_model(name: &str) -> (PathBuf, PathBuf, Mesh) {
+        let directory = env::temp_dir().join(format!("scop-test-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&directory).unwrap();
+        for file in ["42.obj", "42.mtl"] {
+            fs::copy(Path::new("resources/objects/42").join(file), directory.join(file)).unwrap();
+        }
+        let path = directory.join("42.obj");
+        let mesh = loader::load_model(&path).unwrap();
+        write_cache(&path, &mesh).unwrap();
+        (directory, path, mesh)
+    }
+
+    #[test]
+    fn cached_mesh_reads_back_in_place() {
+        let (directory, path, mesh) = cached_model("cache");
+        let cache = &cache_paths(&path)[0];
+        let cached = read_cache(cache, &path);
+        fs::remove_dir_all(directory).unwrap();
+
+        let cached = cached.expect("fresh cache rejected");
+        assert!(cached.vertices.is_mapped() && cached.indices.is_mapped());
+        assert_eq!(cached.vertices, mesh.vertices);
+        assert_eq!(cached.indices, mesh.indices);
+        assert_eq!((&cached.lines, &cached.points), (&mesh.lines, &mesh.points));
+        assert_eq!((&cached.polygons, &cached.polygon_sizes), (&mesh.polygons, &mesh.polygon_sizes));
+        assert_eq!((cached.has_tex_coords, cached.has_colors), (mesh.has_tex_coords, mesh.has_colors));
+        assert_eq!(cached.material_libraries, mesh.material_libraries);
+        let names = |mesh: &Mesh| mesh.nodes.iter().map(|node| node.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(&cached), names(&mesh));
+        assert_eq!(cached.materials.len(), mesh.materials.len());
+        for (a, b) in cached.materials.iter().zip(&mesh.materials) {
+            assert_eq!((&a.name, a.diffuse, a.diffuse_map.as_ref()), (&b.name, b.diffuse, b.diffuse_map.as_ref()));
+        }
+        let ranges = |mesh: &Mesh| mesh.sub_meshes.iter()
+            .map(|s| (s.node, s.material, s.primitive, s.start, s.count))
+            .collect::<Vec<_>>();
+        assert_eq!(ranges(&cached), ranges(&mesh));
+    }
+
+    #[test]
+    fn stale_caches_are_rejected() {
+        let (directory, path, _) = cached_model("stale");
+        let cache = cache_paths(&path)[0].clone();
+
+        // the same content with a new modification time is still fresh
+        fs::write(&path, fs::read(&path).unwrap()).unwrap();
+        let touched = read_cache(&cache, &path).is_some();
+        // another .mtl
+        let mtl = directory.join("42.mtl");
+        fs::write(&mtl, fs::read_to_string(&mtl).unwrap() + "\n# changed\n").unwrap();
+        let changed_mtl = read_cache(&cache, &path).is_some();
+        write_cache(&path, &loader::load_model(&path).unwrap()).unwrap();
+        // another .obj
+        fs::write(&path, fs::read_to_string(&path).unwrap() + "\nv 0 0 0\n").unwrap();
+        let changed_obj = read_cache(&cache, &path).is_some();
+        fs::remove_dir_all(directory).unwrap();
+
+        assert!(touched);
+        assert!(!changed_mtl);
+        assert!(!changed_obj);
+    }
+
+    #[test]
+    fn other_versions_and_truncated_caches_are_rejected() {
+        let (directory, path, _) = cached_model("version");
+        let cache = cache_paths(&path)[0].clone();
+        let bytes = fs::read(&cache).unwrap();
+
+        let mut other_version = bytes.clone();
+        other_version[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&(VERSION + 1).to_ne_bytes());
+        fs::write(&cache, other_version).unwrap();
+        let version = read_cache(&cache, &path).is_some();
+        fs::write(&cache, &bytes[..bytes.len() - 1]).unwrap();
+        let truncated = read_cache(&cache, &path).is_some();
+        fs::write(&cache, &bytes).unwrap();
+        let restored = read_cache(&cache, &path).is_some();
+        fs::remove_dir_all(directory).unwrap();
+
+        assert!(!version);
+        assert!(!truncated);
+        assert!(restored);
+    }
+}
@@ -1,6 +1,8 @@
 use crate::matrix::Matrix4;
+use crate::mesh::{Mesh, Node, VERTEX_SIZE};
 
-pub fn generate_model_matrix(vertices: &Vec<f32>) -> Matrix4{
+// smallest and largest coordinates of the vertex positions
+pub fn bounding_box(vertices: &[f32]) -> ([f32; 3], [f32; 3]) {
     let mut i = 0;
     let (mut max_x, mut max_y, mut max_z) = (f32::MIN, f32::MIN, f32::MIN);
     let (mut min_x, mut min_y, mut min_z) = (f32::MAX, f32::MAX, f32::MAX);
@@ -28,13 +30,30 @@ pub fn generate_model_matrix(vertices: &Vec<f32>) -> Matrix4{
             min_z = vertices[i + 2];
         }
 
-        i+=3;
+        i += VERTEX_SIZE;
     }
 
+    ([min_x, min_y, min_z], [max_x, max_y, max_z])
+}
+
+// moves the center of the bounding box to the origin
+pub fn generate_center_matrix(vertices: &[f32]) -> Matrix4 {
+    let (min, max) = bounding_box(vertices);
+    Matrix4::from_translation(
+        -(max[0] + min[0]) / 2.0,
+        -(max[1] + min[1]) / 2.0,
+        -(max[2] + min[2]) / 2.0
+    )
+}
+
+// centers the object and scales it to fit in [-1, 1]
+pub fn generate_model_matrix(vertices: &[f32]) -> Matrix4{
+    let (min, max) = bounding_box(vertices);
+
     let mut scale_vec = Vec::new();
-    scale_vec.push((max_x - min_x) / 2.0);
-    scale_vec.push((max_y - min_y) / 2.0);
-    scale_vec.push((max_z - min_z) / 2.0);
+    scale_vec.push((max[0] - min[0]) / 2.0);
+    scale_vec.push((max[1] - min[1]) / 2.0);
+    scale_vec.push((max[2] - min[2]) / 2.0);
 
     // find the longest side of the object
     let abs_max = scale_vec.iter()
@@ -45,10 +64,80 @@ pub fn generate_model_matrix(vertices: &Vec<f32>) -> Matrix4{
     let scale_matrix = Matrix4::from_scale(1.0 / (*abs_max));
 
     // used to center the object
-    let translation_matrix = Matrix4::from_translation(
-        -(max_x + min_x) / 2.0, 
-        -(max_y + min_y) / 2.0, 
-        -(max_z + min_z) / 2.0
-    );
+    let translation_matrix = generate_center_matrix(vertices);
     translation_matrix * scale_matrix
 }
+
+// objects and groups of a mesh, with what the user chose to show
+pub struct Model {
+    pub nodes: Vec<ModelNode>,
+    // index of the node selected with the keyboard
+    pub selected: usize,
+}
+
+pub struct ModelNode {
+    pub node: Node,
+    pub visible: bool,
+}
+
+impl Model {
+    pub fn new(mesh: &Mesh) -> Self {
+        Self {
+            nodes: mesh.nodes.iter()
+                .map(|node| ModelNode { node: node.clone(), visible: true })
+                .collect(),
+            selected: 0,
+        }
+    }
+
+    // a node is hidden with its object
+    pub fn is_visible(&self, index: usize) -> bool {
+        let node = &self.nodes[index];
+        match node.node.parent {
+            Some(parent) => node.visible && self.is_visible(parent),
+            None => node.visible,
+        }
+    }
+
+    // the selected node or one of its groups
+    pub fn is_in_selection(&self, index: usize) -> bool {
+        index == self.selected || self.nodes[index].node.parent == Some(self.selected)
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.nodes.is_empty() {
+            self.selected = (self.selected + 1) % self.nodes.len();
+            self.print_selected();
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        if !self.nodes.is_empty() {
+            self.selected = (self.selected + self.nodes.len() - 1) % self.nodes.len();
+            self.print_selected();
+        }
+    }
+
+    pub fn toggle_selected(&mut self) {
+        if let Some(node) = self.nodes.get_mut(self.selected) {
+            node.visible = !node.visible;
+            self.print_selected();
+        }
+    }
+
+    // print the tree of objects and groups
+    pub fn list(&self) {
+        for (index, node) in self.nodes.iter().enumerate() {
+            let marker = if index == self.selected { ">" } else { " " };
+            let indent = if node.node.parent.is_some() { "    " } else { "" };
+            let state = if node.visible { "" } else { " (hidden)" };
+            println!("{} {}{:?} {}{}", marker, indent, node.node.kind, node.node.name, state);
+        }
+    }
+
+    fn print_selected(&self) {
+        let node = &self.nodes[self.selected];
+        let state = if node.visible { "visible" } else { "hidden" };
+        println!("selected {:?} {} ({})", node.node.kind, node.node.name, state);
+    }
+}
@@ -0,0 +1,742 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+
+use crate::loader::{MeshLoader, MeshError};
+use crate::matrix::Matrix4;
+use crate::mesh::{
+    Material, Mesh, Node, NodeKind, Primitive, SubMesh, TextureImage,
+    POSITION_OFFSET, TEX_COORD_OFFSET, NORMAL_OFFSET, COLOR_OFFSET, VERTEX_SIZE
+};
+use crate::vector::{cross, dot, normalize, triangle_normal};
+
+// binary container: header, then a json chunk and an optional binary chunk
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const GLB_HEADER_SIZE: usize = 12;
+const CHUNK_JSON: u32 = 0x4E4F_534A;
+const CHUNK_BIN: u32 = 0x004E_4942;
+
+// accessor component types
+const BYTE: u64 = 5120;
+const UNSIGNED_BYTE: u64 = 5121;
+const SHORT: u64 = 5122;
+const UNSIGNED_SHORT: u64 = 5123;
+const UNSIGNED_INT: u64 = 5125;
+const FLOAT: u64 = 5126;
+// largest number of values of an accessor without a buffer view (zeros that the
+// file does not have to store)
+const MAX_ZEROS: usize = 1 << 26;
+
+// primitive modes
+const POINTS: u64 = 0;
+const LINES: u64 = 1;
+const LINE_LOOP: u64 = 2;
+const LINE_STRIP: u64 = 3;
+const TRIANGLES: u64 = 4;
+const TRIANGLE_STRIP: u64 = 5;
+const TRIANGLE_FAN: u64 = 6;
+
+// what the whole file refers to, resolved once
+struct Document<'a> {
+    path: &'a Path,
+    json: Value,
+    buffers: Vec<Vec<u8>>,
+}
+
+pub struct GltfLoader;
+
+impl MeshLoader for GltfLoader {
+    fn name(&self) -> &'static str {
+        "gltf"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["gltf", "glb"]
+    }
+
+    // the magic of a .glb, or a json object
+    fn sniff(&self, header: &[u8], _size: u64) -> bool {
+        let start = header.iter().position(|byte| !byte.is_ascii_whitespace()).unwrap_or(header.len());
+        header.starts_with(GLB_MAGIC) || header[start..].starts_with(b"{")
+    }
+
+    fn load(&self, path: &Path) -> Result<Mesh, MeshError> {
+        load_model(path)
+    }
+}
+
+// read a .gltf (json, with external or base64 buffers) or .glb file.
+// every node of the default scene becomes a node of the mesh (root nodes are objects,
+// their children groups), with its primitives moved by the transforms of its ancestors.
+pub fn load_model(path: &Path) -> Result<Mesh, MeshError> {
+    let bytes = fs::read(path).map_err(|source| MeshError::Io {
+        path: path.to_path_buf(),
+        line: 0,
+        source,
+    })?;
+
+    let (json, binary) = if bytes.starts_with(GLB_MAGIC) {
+        split_glb(path, &bytes)?
+    } else {
+        (&bytes[..], None)
+    };
+    let json: Value = serde_json::from_slice(json)
+        .map_err(|error| invalid(path, format!("invalid json: {}", error)))?;
+
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut external_files = Vec::new();
+    let mut buffers = Vec::new();
+    for (index, buffer) in array(&json, "buffers").iter().enumerate() {
+        let data = match buffer["uri"].as_str() {
+            Some(uri) => read_uri(path, directory, uri, &mut external_files)?,
+            // the binary chunk of a .glb
+            None => binary.map(|binary| binary.to_vec())
+                .ok_or_else(|| invalid(path, format!("buffer {} has no data", index)))?,
+        };
+        buffers.push(data);
+    }
+
+    let document = Document { path, json, buffers };
+    let materials = document.materials(directory, &mut external_files)?;
+
+    let mut mesh = Mesh {
+        vertices: Default::default(),
+        indices: Default::default(),
+        lines: Vec::new(),
+        points: Vec::new(),
+        polygons: Vec::new(),
+        polygon_sizes: Vec::new(),
+        has_tex_coords: false,
+        has_colors: false,
+        materials,
+        material_libraries: external_files,
+        nodes: Vec::new(),
+        sub_meshes: Vec::new(),
+    };
+
+    // the default scene, or every node that has no parent
+    let scene = document.json["scene"].as_u64().unwrap_or(0) as usize;
+    let roots: Vec<usize> = match document.json["scenes"].get(scene) {
+        Some(scene) => indices(&scene["nodes"]),
+        None => {
+            let children: Vec<usize> = array(&document.json, "nodes").iter()
+                .flat_map(|node| indices(&node["children"]))
+                .collect();
+            (0..array(&document.json, "nodes").len())
+                .filter(|node| !children.contains(node))
+                .collect()
+        }
+    };
+
+    // primitives are triangles, lines or points, grouped in that order at the end
+    let mut primitives: Vec<(SubMesh, Vec<u32>)> = Vec::new();
+    for root in roots {
+        document.add_node(root, None, Matrix4::identity(), &mut mesh, &mut primitives, 0)?;
+    }
+    for (mut sub_mesh, mut elements) in primitives {
+        let list = match sub_mesh.primitive {
+            Primitive::Triangles => mesh.indices.to_mut(),
+            Primitive::Lines => &mut mesh.lines,
+            Primitive::Points => &mut mesh.points,
+        };
+        sub_mesh.start = list.len();
+        list.append(&mut elements);
+        mesh.sub_meshes.push(sub_mesh);
+    }
+
+    Ok(mesh)
+}
+
+// json and binary chunks of a .glb
+fn split_glb<'a>(path: &Path, bytes: &'a [u8]) -> Result<(&'a [u8], Option<&'a [u8]>), MeshError> {
+    let u32_at = |offset: usize| bytes.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+
+    let mut json = None;
+    let mut binary = None;
+    let mut offset = GLB_HEADER_SIZE;
+    while let (Some(length), Some(kind)) = (u32_at(offset), u32_at(offset + 4)) {
+        let start = offset + 8;
+        let chunk = bytes.get(start..start + length as usize)
+            .ok_or_else(|| invalid(path, String::from("truncated glb chunk")))?;
+        match kind {
+            CHUNK_JSON if json.is_none() => json = Some(chunk),
+            CHUNK_BIN if binary.is_none() => binary = Some(chunk),
+            // unknown chunks are skipped
+            _ => {}
+        }
+        // chunks are aligned on 4 bytes
+        offset = start + (length as usize).div_ceil(4) * 4;
+    }
+
+    let json = json.ok_or_else(|| invalid(path, String::from("glb without json chunk")))?;
+    Ok((json, binary))
+}
+
+// content of a buffer or image uri: base64 data, or a file relative to the .gltf
+fn read_uri(path: &Path, directory: &Path, uri: &str, files: &mut Vec<PathBuf>) -> Result<Vec<u8>, MeshError> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (_, encoded) = data.split_once(";base64,")
+            .ok_or_else(|| invalid(path, String::from("data uri is not base64")))?;
+        return decode_base64(encoded)
+            .ok_or_else(|| invalid(path, String::from("invalid base64 data")));
+    }
+
+    let file = directory.join(percent_decode(uri));
+    let data = fs::read(&file).map_err(|source| MeshError::Io { path: file.clone(), line: 0, source })?;
+    files.push(file);
+    Ok(data)
+}
+
+impl<'a> Document<'a> {
+    // the node, its children, and their primitives moved by `parent_transform`
+    fn add_node(
+        &self,
+        index: usize,
+        parent: Option<usize>,
+        parent_transform: Matrix4,
+        mesh: &mut Mesh,
+        primitives: &mut Vec<(SubMesh, Vec<u32>)>,
+        depth: usize
+    ) -> Result<(), MeshError> {
+        let node = self.json["nodes"].get(index)
+            .ok_or_else(|| invalid(self.path, format!("node {} does not exist", index)))?;
+        // a node can't be its own ancestor
+        if depth > array(&self.json, "nodes").len() {
+            return Err(invalid(self.path, String::from("cycle in the node hierarchy")));
+        }
+
+        // local transform first, then the one of the parent
+        let transform = local_transform(node) * parent_transform;
+
+        let name = node["name"].as_str()
+            .or_else(|| node["mesh"].as_u64().and_then(|m| self.json["meshes"][m as usize]["name"].as_str()))
+            .map_or_else(|| format!("node {}", index), String::from);
+        let kind = if parent.is_some() { NodeKind::Group } else { NodeKind::Object };
+        let node_index = mesh.nodes.len();
+        mesh.nodes.push(Node { name, kind, parent });
+
+        if let Some(mesh_index) = node["mesh"].as_u64() {
+            let gltf_mesh = self.json["meshes"].get(mesh_index as usize)
+                .ok_or_else(|| invalid(self.path, format!("mesh {} does not exist", mesh_index)))?;
+            for primitive in array(gltf_mesh, "primitives") {
+                if let Some(primitive) = self.add_primitive(primitive, &transform, node_index, mesh)? {
+                    primitives.push(primitive);
+                }
+            }
+        }
+
+        for child in indices(&node["children"]) {
+            self.add_node(child, Some(node_index), transform, mesh, primitives, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    // append the vertices of a primitive, returns its sub-mesh and elements
+    fn add_primitive(
+        &self,
+        primitive: &Value,
+        transform: &Matrix4,
+        node: usize,
+        mesh: &mut Mesh
+    ) -> Result<Option<(SubMesh, Vec<u32>)>, MeshError> {
+        let attributes = &primitive["attributes"];
+        let positions = match attributes["POSITION"].as_u64() {
+            Some(accessor) => self.accessor(accessor as usize)?,
+            // nothing to draw
+            None => return Ok(None),
+        };
+        let count = positions.len() / 3;
+        let normals = self.optional_accessor(&attributes["NORMAL"], count, &[3])?;
+        let tex_coords = self.optional_accessor(&attributes["TEXCOORD_0"], count, &[2])?;
+        // rgb or rgba, alpha is ignored
+        let colors = self.optional_accessor(&attributes["COLOR_0"], count, &[3, 4])?;
+        let color_size = colors.as_ref().map_or(3, |colors| colors.len() / count.max(1));
+
+        // every vertex of the primitive, or 0..count
+        let corners: Vec<u32> = match primitive["indices"].as_u64() {
+            Some(accessor) => {
+                let corners = self.index_accessor(accessor as usize)?;
+                if corners.iter().any(|&i| i as usize >= count) {
+                    return Err(invalid(self.path, format!("index out of range in accessor {}", accessor)));
+                }
+                corners
+            }
+            None => (0..count as u32).collect(),
+        };
+
+        let mode = primitive["mode"].as_u64().unwrap_or(TRIANGLES);
+        let (kind, mut elements) = match mode {
+            POINTS => (Primitive::Points, corners),
+            LINES => (Primitive::Lines, corners[..corners.len() / 2 * 2].to_vec()),
+            LINE_STRIP | LINE_LOOP => {
+                let mut segments: Vec<u32> = corners.windows(2).flatten().copied().collect();
+                if mode == LINE_LOOP && corners.len() > 2 {
+                    segments.extend_from_slice(&[corners[corners.len() - 1], corners[0]]);
+                }
+                (Primitive::Lines, segments)
+            }
+            TRIANGLES => (Primitive::Triangles, corners[..corners.len() / 3 * 3].to_vec()),
+            TRIANGLE_STRIP => (Primitive::Triangles, strip_triangles(&corners)),
+            TRIANGLE_FAN => (Primitive::Triangles, fan_triangles(&corners)),
+            _ => return Err(invalid(self.path, format!("unknown primitive mode {}", mode))),
+        };
+
+        let normal_matrix = normal_matrix(transform);
+        // a mirroring transform turns the faces inside out
+        if normal_matrix.sign < 0.0 && kind == Primitive::Triangles {
+            for triangle in elements.chunks_exact_mut(3) {
+                triangle.swap(1, 2);
+            }
+        }
+
+        let vertex = |i: usize| -> [f32; VERTEX_SIZE] {
+            let mut vertex = [0.0; VERTEX_SIZE];
+            let position = transform.transform_point([positions[i * 3], positions[i * 3 + 1], positions[i * 3 + 2]]);
+            vertex[POSITION_OFFSET..POSITION_OFFSET + 3].copy_from_slice(&position);
+            if let Some(tex_coords) = &tex_coords {
+                // gltf images start at the top, ours at the bottom
+                vertex[TEX_COORD_OFFSET] = tex_coords[i * 2];
+                vertex[TEX_COORD_OFFSET + 1] = 1.0 - tex_coords[i * 2 + 1];
+            }
+            if let Some(normals) = &normals {
+                let normal = transform_normal(&normal_matrix, [normals[i * 3], normals[i * 3 + 1], normals[i * 3 + 2]]);
+                vertex[NORMAL_OFFSET..NORMAL_OFFSET + 3].copy_from_slice(&normal);
+            }
+            match &colors {
+                Some(colors) => vertex[COLOR_OFFSET..COLOR_OFFSET + 3]
+                    .copy_from_slice(&colors[i * color_size..i * color_size + 3]),
+                None => vertex[COLOR_OFFSET..COLOR_OFFSET + 3].fill(1.0),
+            }
+            vertex
+        };
+
+        let first = (mesh.vertices.len() / VERTEX_SIZE) as u32;
+        if normals.is_none() && kind == Primitive::Triangles {
+            // no normals: flat shading, so every corner gets its own vertex
+            for (i, triangle) in elements.chunks_exact_mut(3).enumerate() {
+                let mut corners = [vertex(triangle[0] as usize), vertex(triangle[1] as usize), vertex(triangle[2] as usize)];
+                let p = |k: usize| [corners[k][0], corners[k][1], corners[k][2]];
+                let normal = normalize(triangle_normal(p(0), p(1), p(2)));
+                for (offset, corner) in corners.iter_mut().enumerate() {
+                    corner[NORMAL_OFFSET..NORMAL_OFFSET + 3].copy_from_slice(&normal);
+                    mesh.vertices.to_mut().extend_from_slice(corner);
+                    triangle[offset] = first + (i * 3 + offset) as u32;
+                }
+            }
+        } else {
+            for i in 0..count {
+                mesh.vertices.to_mut().extend_from_slice(&vertex(i));
+            }
+            for index in elements.iter_mut() {
+                *index += first;
+            }
+        }
+
+        mesh.has_tex_coords |= tex_coords.is_some();
+        mesh.has_colors |= colors.is_some();
+
+        let material = primitive["material"].as_u64()
+            .map(|material| material as usize)
+            .filter(|&material| material < mesh.materials.len());
+        Ok(Some((SubMesh { node, material, primitive: kind, start: 0, count: elements.len() }, elements)))
+    }
+
+    // an accessor that may be missing, with one of the `sizes` components per vertex
+    fn optional_accessor(&self, accessor: &Value, count: usize, sizes: &[usize]) -> Result<Option<Vec<f32>>, MeshError> {
+        let accessor = match accessor.as_u64() {
+            Some(accessor) => accessor as usize,
+            None => return Ok(None),
+        };
+        let values = self.accessor(accessor)?;
+        if !sizes.iter().any(|&size| values.len() == count * size) {
+            return Err(invalid(self.path, format!("accessor {} does not match the positions", accessor)));
+        }
+        Ok(Some(values))
+    }
+
+    // all the values of an accessor as floats, integers are scaled to [0, 1]
+    // (or [-1, 1]) when the accessor is normalized
+    fn accessor(&self, index: usize) -> Result<Vec<f32>, MeshError> {
+        self.read_accessor(index, |component_type, b, normalized| match component_type {
+            BYTE => normalize_int(b[0] as i8 as f32, 127.0, normalized),
+            UNSIGNED_BYTE => normalize_int(b[0] as f32, 255.0, normalized),
+            SHORT => normalize_int(i16::from_le_bytes([b[0], b[1]]) as f32, 32767.0, normalized),
+            UNSIGNED_SHORT => normalize_int(u16::from_le_bytes([b[0], b[1]]) as f32, 65535.0, normalized),
+            UNSIGNED_INT => normalize_int(u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32, 4294967295.0, normalized),
+            _ => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        })
+    }
+
+    // indices are read as integers, big ones don't fit in a f32
+    fn index_accessor(&self, index: usize) -> Result<Vec<u32>, MeshError> {
+        self.read_accessor(index, |component_type, b, _| match component_type {
+            UNSIGNED_BYTE | BYTE => b[0] as u32,
+            UNSIGNED_SHORT | SHORT => u16::from_le_bytes([b[0], b[1]]) as u32,
+            UNSIGNED_INT => u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            _ => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as u32,
+        })
+    }
+
+    // every component of an accessor, converted from its bytes by `convert`
+    // (given the component type and whether it is normalized)
+    fn read_accessor<T>(&self, index: usize, convert: impl Fn(u64, &[u8], bool) -> T) -> Result<Vec<T>, MeshError> {
+        let error = |text: &str| invalid(self.path, format!("accessor {}: {}", index, text));
+
+        let accessor = self.json["accessors"].get(index).ok_or_else(|| error("does not exist"))?;
+        let count = accessor["count"].as_u64().ok_or_else(|| error("no count"))? as usize;
+        let components = match accessor["type"].as_str() {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") | Some("MAT2") => 4,
+            Some("MAT3") => 9,
+            Some("MAT4") => 16,
+            _ => return Err(error("unknown type")),
+        };
+        let component_type = accessor["componentType"].as_u64().unwrap_or(0);
+        let component_size = match component_type {
+            BYTE | UNSIGNED_BYTE => 1,
+            SHORT | UNSIGNED_SHORT => 2,
+            UNSIGNED_INT | FLOAT => 4,
+            _ => return Err(error("unknown component type")),
+        };
+        let normalized = accessor["normalized"].as_bool().unwrap_or(false);
+
+        // accessors without a buffer view are all zeros, their size only comes from the count
+        let view = match accessor["bufferView"].as_u64() {
+            Some(view) => view as usize,
+            None => {
+                let size = count.checked_mul(components)
+                    .filter(|&size| size <= MAX_ZEROS)
+                    .ok_or_else(|| error("too large without a buffer view"))?;
+                let zero = [0u8; 4];
+                return Ok((0..size).map(|_| convert(component_type, &zero, normalized)).collect());
+            }
+        };
+        let view = self.json["bufferViews"].get(view).ok_or_else(|| error("missing buffer view"))?;
+        let buffer = self.buffers.get(view["buffer"].as_u64().unwrap_or(u64::MAX) as usize)
+            .ok_or_else(|| error("missing buffer"))?;
+        let element_size = components * component_size;
+        let stride = match view["byteStride"].as_u64() {
+            Some(stride) if stride > 0 => stride as usize,
+            _ => element_size,
+        };
+        // offsets and counts come from the file, they may overflow
+        let start = (view["byteOffset"].as_u64().unwrap_or(0) as usize)
+            .checked_add(accessor["byteOffset"].as_u64().unwrap_or(0) as usize);
+        let end = start.and_then(|start| match count {
+            0 => Some(start),
+            _ => stride.checked_mul(count - 1)?.checked_add(start)?.checked_add(element_size),
+        });
+        let start = match (start, end) {
+            (Some(start), Some(end)) if end <= buffer.len() => start,
+            _ => return Err(error("out of its buffer")),
+        };
+
+        let mut values = Vec::with_capacity(count * components);
+        for element in 0..count {
+            for component in 0..components {
+                let offset = start + element * stride + component * component_size;
+                values.push(convert(component_type, &buffer[offset..offset + component_size], normalized));
+            }
+        }
+        Ok(values)
+    }
+
+    // base color factor and texture of each material
+    fn materials(&self, directory: &Path, files: &mut Vec<PathBuf>) -> Result<Vec<Material>, MeshError> {
+        let mut materials = Vec::new();
+        for (index, gltf_material) in array(&self.json, "materials").iter().enumerate() {
+            let name = gltf_material["name"].as_str()
+                .map_or_else(|| format!("material {}", index), String::from);
+            let mut material = Material::new(&name);
+
+            let pbr = &gltf_material["pbrMetallicRoughness"];
+            if let Some(factor) = pbr["baseColorFactor"].as_array() {
+                let factor: Vec<f32> = factor.iter().map(|v| v.as_f64().unwrap_or(1.0) as f32).collect();
+                if let [r, g, b, a] = factor[..] {
+                    material.diffuse = [r, g, b];
+                    // alpha is only used in blend mode
+                    if gltf_material["alphaMode"].as_str() == Some("BLEND") {
+                        material.dissolve = a;
+                    }
+                }
+            }
+            material.diffuse_map = self.texture(&pbr["baseColorTexture"], directory, files)?;
+            material.bump_map = self.texture(&gltf_material["normalTexture"], directory, files)?;
+
+            materials.push(material);
+        }
+        Ok(materials)
+    }
+
+    // file of the image of a texture; images stored in the file are written to
+    // the temporary directory, so they can be loaded like any other texture
+    fn texture(&self, info: &Value, directory: &Path, files: &mut Vec<PathBuf>) -> Result<Option<TextureImage>, MeshError> {
+        let image = info["index"].as_u64()
+            .and_then(|texture| self.json["textures"][texture as usize]["source"].as_u64())
+            .and_then(|image| self.json["images"].get(image as usize));
+        let image = match image {
+            Some(image) => image,
+            None => return Ok(None),
+        };
+
+        // embedded images are kept in memory and decoded when the texture is created
+        let data = match (image["uri"].as_str(), image["bufferView"].as_u64()) {
+            (Some(uri), _) if !uri.starts_with("data:") => {
+                return Ok(Some(TextureImage::File(directory.join(percent_decode(uri)))));
+            }
+            (Some(uri), _) => read_uri(self.path, directory, uri, files)?,
+            (None, Some(view)) => {
+                let view = &self.json["bufferViews"][view as usize];
+                let buffer = self.buffers.get(view["buffer"].as_u64().unwrap_or(u64::MAX) as usize);
+                let start = view["byteOffset"].as_u64().unwrap_or(0) as usize;
+                let range = start.checked_add(view["byteLength"].as_u64().unwrap_or(0) as usize)
+                    .map(|end| start..end);
+                buffer.zip(range)
+                    .and_then(|(buffer, range)| buffer.get(range))
+                    .ok_or_else(|| invalid(self.path, String::from("image out of its buffer")))?
+                    .to_vec()
+            }
+            (None, None) => return Ok(None),
+        };
+        Ok(Some(TextureImage::Embedded(data.into())))
+    }
+}
+
+// matrix, or translation / rotation / scale of a node (scaled first, translated last)
+fn local_transform(node: &Value) -> Matrix4 {
+    let floats = |key: &str| -> Option<Vec<f32>> {
+        node[key].as_array().map(|values| values.iter().map(|v| v.as_f64().unwrap_or(0.0) as f32).collect())
+    };
+
+    if let Some(matrix) = floats("matrix") {
+        if let Ok(matrix) = <[f32; 16]>::try_from(matrix) {
+            // same column-major order as Matrix4
+            return Matrix4::new(matrix);
+        }
+    }
+
+    let mut transform = Matrix4::identity();
+    if let Some([x, y, z]) = floats("scale").as_deref() {
+        transform = transform * Matrix4::from_scale_xyz(*x, *y, *z);
+    }
+    if let Some([x, y, z, w]) = floats("rotation").as_deref() {
+        transform = transform * Matrix4::from_quaternion(*x, *y, *z, *w);
+    }
+    if let Some([x, y, z]) = floats("translation").as_deref() {
+        transform = transform * Matrix4::from_translation(*x, *y, *z);
+    }
+    transform
+}
+
+// inverse transpose of the 3x3 part of a transform, up to a factor
+struct NormalMatrix {
+    // columns: the cofactor matrix is the inverse transpose times the determinant
+    columns: [[f32; 3]; 3],
+    // sign of the determinant, negative when the transform mirrors
+    sign: f32,
+}
+
+fn normal_matrix(transform: &Matrix4) -> NormalMatrix {
+    // columns of the 3x3 part
+    let (a, b, c) = (transform.x(), transform.y(), transform.z());
+    let a = [a[0], a[1], a[2]];
+    let b = [b[0], b[1], b[2]];
+    let c = [c[0], c[1], c[2]];
+    let columns = [cross(b, c), cross(c, a), cross(a, b)];
+    let sign = if dot(a, columns[0]) < 0.0 { -1.0 } else { 1.0 };
+    NormalMatrix { columns, sign }
+}
+
+fn transform_normal(matrix: &NormalMatrix, v: [f32; 3]) -> [f32; 3] {
+    let mut result = [0.0; 3];
+    for (column, value) in matrix.columns.iter().zip(v) {
+        for i in 0..3 {
+            result[i] += column[i] * value * matrix.sign;
+        }
+    }
+    normalize(result)
+}
+
+fn normalize_int(value: f32, max: f32, normalized: bool) -> f32 {
+    if normalized {
+        return (value / max).max(-1.0);
+    }
+    value
+}
+
+// the triangles of a strip, every other one is reversed to keep the winding
+fn strip_triangles(corners: &[u32]) -> Vec<u32> {
+    (2..corners.len())
+        .flat_map(|i| if i % 2 == 0 {
+            [corners[i - 2], corners[i - 1], corners[i]]
+        } else {
+            [corners[i - 1], corners[i - 2], corners[i]]
+        })
+        .collect()
+}
+
+// the triangles of a fan around the first corner
+fn fan_triangles(corners: &[u32]) -> Vec<u32> {
+    (2..corners.len())
+        .flat_map(|i| [corners[0], corners[i - 1], corners[i]])
+        .collect()
+}
+
+// "a%20b.png" is "a b.png"
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let value = |c: u8| -> Option<u32> {
+        Some(match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return None,
+        } as u32)
+    };
+
+    let text = text.trim_end_matches('=').as_bytes();
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    for group in text.chunks(4) {
+        let mut bits = 0;
+        for (i, &c) in group.iter().enumerate() {
+            bits |= value(c)? << (18 - 6 * i);
+        }
+        let length = group.len() * 6 / 8;
+        bytes.extend_from_slice(&[(bits >> 16) as u8, (bits >> 8) as u8, bits as u8][..length]);
+    }
+    Some(bytes)
+}
+
+// indices listed in a json array ("children", "nodes")
+fn indices(value: &Value) -> Vec<usize> {
+    value.as_array()
+        .map(|values| values.iter().filter_map(|v| v.as_u64()).map(|v| v as usize).collect())
+        .unwrap_or_default()
+}
+
+// a top level array of the document, empty when missing
+fn array<'v>(value: &'v Value, key: &str) -> &'v [Value] {
+    value[key].as_array().map_or(&[], |values| &values[..])
+}
+
+fn invalid(path: &Path, text: String) -> MeshError {
+    MeshError::InvalidData { path: path.to_path_buf(), text }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::{POSITION_OFFSET, VERTEX_SIZE};
+
+    fn load(name: &str) -> Mesh {
+        load_model(Path::new(&format!("resources/objects/fixtures/{}", name))).unwrap()
+    }
+
+    // positions used by the sub-meshes of the node with that name, sorted
+    fn node_positions(mesh: &Mesh, name: &str) -> Vec<[f32; 3]> {
+        let node = mesh.nodes.iter().position(|node| node.name == name).unwrap();
+        let mut positions: Vec<[f32; 3]> = mesh.sub_meshes.iter()
+            .filter(|sub_mesh| sub_mesh.node == node && sub_mesh.primitive == Primitive::Triangles)
+            .flat_map(|sub_mesh| &mesh.indices[sub_mesh.start..sub_mesh.start + sub_mesh.count])
+            .map(|&index| {
+                let p = index as usize * VERTEX_SIZE + POSITION_OFFSET;
+                // -0.0 and rounding errors of the rotation
+                [0, 1, 2].map(|i| (mesh.vertices[p + i] * 1e4).round() / 1e4 + 0.0)
+            })
+            .collect();
+        positions.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        positions.dedup();
+        positions
+    }
+
+    #[test]
+    fn buffers_load_the_same_from_every_container() {
+        let external = load("quads.gltf");
+        for name in ["quads.glb", "quads_embedded.gltf"] {
+            let mesh = load(name);
+            assert!(mesh.vertices == external.vertices, "{}: vertices differ", name);
+            assert_eq!(mesh.indices, external.indices, "{}", name);
+            let names = |mesh: &Mesh| mesh.nodes.iter().map(|node| (node.name.clone(), node.parent)).collect::<Vec<_>>();
+            assert_eq!(names(&mesh), names(&external), "{}", name);
+            let ranges = |mesh: &Mesh| mesh.sub_meshes.iter()
+                .map(|s| (s.node, s.material, s.start, s.count))
+                .collect::<Vec<_>>();
+            assert_eq!(ranges(&mesh), ranges(&external), "{}", name);
+            assert_eq!(mesh.materials.len(), external.materials.len(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn nodes_are_placed_by_their_transforms() {
+        for name in ["quads.gltf", "quads.glb", "quads_embedded.gltf"] {
+            let mesh = load(name);
+            // the unit quad moved to z = -1
+            assert_eq!(node_positions(&mesh, "base"),
+                [[0.0, 0.0, -1.0], [0.0, 1.0, -1.0], [1.0, 0.0, -1.0], [1.0, 1.0, -1.0]], "{}", name);
+            // turned a quarter around y, moved 2 along x, then by its parent
+            assert_eq!(node_positions(&mesh, "rotated"),
+                [[2.0, 0.0, -2.0], [2.0, 0.0, -1.0], [2.0, 1.0, -2.0], [2.0, 1.0, -1.0]], "{}", name);
+            // mirrored along x under its parent
+            assert_eq!(node_positions(&mesh, "mirrored"),
+                [[-1.0, 0.0, -1.0], [-1.0, 1.0, -1.0], [0.0, 0.0, -1.0], [0.0, 1.0, -1.0]], "{}", name);
+        }
+    }
+
+    #[test]
+    fn base64() {
+        assert_eq!(decode_base64("TWFu").unwrap(), b"Man");
+        assert_eq!(decode_base64("TWE=").unwrap(), b"Ma");
+        assert_eq!(decode_base64("TQ==").unwrap(), b"M");
+        assert_eq!(decode_base64("").unwrap(), b"");
+        // url safe alphabet
+        assert_eq!(decode_base64("-_8").unwrap(), [0xFB, 0xFF]);
+        assert_eq!(decode_base64("+/8").unwrap(), [0xFB, 0xFF]);
+        assert!(decode_base64("TW*u").is_none());
+    }
+
+    #[test]
+    fn percent_encoded_uris() {
+        assert_eq!(percent_decode("a%20b.png"), "a b.png");
+        assert_eq!(percent_decode("%C3%A9t%C3%A9.jpg"), "été.jpg");
+        // not an escape
+        assert_eq!(percent_decode("100%.png"), "100%.png");
+        assert_eq!(percent_decode("%zz.png"), "%zz.png");
+    }
+
+    #[test]
+    fn strips_and_fans() {
+        assert_eq!(strip_triangles(&[0, 1, 2, 3, 4]), [0, 1, 2, 2, 1, 3, 2, 3, 4]);
+        assert_eq!(fan_triangles(&[0, 1, 2, 3]), [0, 1, 2, 0, 2, 3]);
+        assert!(strip_triangles(&[0, 1]).is_empty());
+        assert!(fan_triangles(&[0, 1]).is_empty());
+    }
+}
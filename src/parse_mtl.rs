@@ -0,0 +1,98 @@
+use std::{path::Path, io, fs::File};
+
+use crate::mesh::{Material, TextureImage};
+use crate::loader::MeshError;
+
+// read all the materials of a .mtl file
+pub fn load_materials(path: &Path) -> Result<Vec<Material>, MeshError> {
+    let file = File::open(path).map_err(|source| MeshError::Io {
+        path: path.to_path_buf(),
+        line: 0,
+        source,
+    })?;
+
+    // texture paths are relative to the .mtl file
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut materials: Vec<Material> = Vec::new();
+
+    for (line_index, line) in io::BufRead::lines(io::BufReader::new(file)).enumerate() {
+        let line_number = line_index + 1;
+        let mtl_string = line.map_err(|source| MeshError::Io {
+            path: path.to_path_buf(),
+            line: line_number,
+            source,
+        })?;
+
+        let malformed_number = || MeshError::MalformedNumber {
+            path: path.to_path_buf(),
+            line: line_number,
+            text: mtl_string.clone(),
+        };
+
+        let mut tokens = mtl_string.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+
+        if keyword == "newmtl" {
+            let name: Vec<&str> = tokens.collect();
+            materials.push(Material::new(&name.join(" ")));
+            continue;
+        }
+
+        // every other statement describes the last declared material
+        let material = match materials.last_mut() {
+            Some(material) => material,
+            None => continue,
+        };
+
+        match keyword {
+            // colors given as a spectral curve or as CIE XYZ values are not supported
+            "Ka" | "Kd" | "Ks" if matches!(tokens.clone().next(), Some("spectral" | "xyz")) => {
+                println!("warning: {}:{}: ignored \"{}\", only rgb colors are supported",
+                    path.display(), line_number, mtl_string.trim());
+            }
+            "Ka" => material.ambient = parse_color(tokens).ok_or_else(malformed_number)?,
+            "Kd" => material.diffuse = parse_color(tokens).ok_or_else(malformed_number)?,
+            "Ks" => material.specular = parse_color(tokens).ok_or_else(malformed_number)?,
+            "Ns" => material.shininess = parse_float(tokens).ok_or_else(malformed_number)?,
+            "d" => material.dissolve = parse_float(tokens).ok_or_else(malformed_number)?,
+            // transparency, the opposite of dissolve
+            "Tr" => material.dissolve = 1.0 - parse_float(tokens).ok_or_else(malformed_number)?,
+            "illum" => material.illum = tokens.next()
+                .and_then(|x| x.parse::<u32>().ok())
+                .ok_or_else(malformed_number)?,
+            "map_Kd" => material.diffuse_map = parse_map(directory, tokens),
+            "map_Bump" | "map_bump" | "bump" | "norm" => material.bump_map = parse_map(directory, tokens),
+            "map_d" => material.dissolve_map = parse_map(directory, tokens),
+            // comments and statements we don't use (Ni, Ke, Tf, map_Ks...)
+            _ => {}
+        }
+    }
+
+    Ok(materials)
+}
+
+fn parse_float<'a>(mut tokens: impl Iterator<Item = &'a str>) -> Option<f32> {
+    tokens.next()?.parse::<f32>().ok()
+}
+
+// r g b, or a single value used for the 3 channels
+fn parse_color<'a>(tokens: impl Iterator<Item = &'a str>) -> Option<[f32; 3]> {
+    let values = tokens
+        .map(|x| x.parse::<f32>().ok())
+        .collect::<Option<Vec<f32>>>()?;
+
+    match values[..] {
+        [value] => Some([value, value, value]),
+        [r, g, b, ..] => Some([r, g, b]),
+        _ => None,
+    }
+}
+
+// the file name is the last token, options (-bm 1.0, -clamp on...) are ignored
+fn parse_map<'a>(directory: &Path, tokens: impl Iterator<Item = &'a str>) -> Option<TextureImage> {
+    tokens.last().map(|name| TextureImage::File(directory.join(name)))
+}
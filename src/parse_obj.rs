@@ -1,79 +1,900 @@
-use std::{path::Path, io, fs::File};
+use std::{fmt, path::{Path, PathBuf}, fs, collections::HashMap, borrow::Cow};
 
-fn read_lines(filename: &Path) -> io::Result<io::Lines<io::BufReader<File>>> {
-    let file = File::open(filename);
-    Ok(io::BufRead::lines(io::BufReader::new(file.expect("Not a valid file"))))
+use rayon::prelude::*;
+
+use crate::loader::{MeshLoader, MeshError, unknown_directive, malformed_number, index_out_of_range, degenerate_face};
+use crate::mesh::{Mesh, Material, Node, NodeKind, Primitive, SubMesh, VERTEX_SIZE};
+use crate::parse_mtl;
+use crate::triangulate;
+use crate::vector::{newell_normal, normalize, normalize_color};
+
+// one corner of a face: 1-based v/vt/vn indices (0 when missing)
+#[derive(Clone, Copy)]
+struct Corner {
+    v: u32,
+    vt: u32,
+    vn: u32,
+}
+
+struct Face {
+    // range in the corner list
+    first: usize,
+    count: usize,
+    // line of the face in the file, used for error messages
+    line: usize,
+    // smoothing group, 0 means flat shading
+    smoothing: u32,
+    // index in the list of names given to usemtl
+    material: Option<usize>,
+    // object or group the face belongs to
+    node: usize,
+    // polygon ("f"), polyline ("l") or points ("p")
+    primitive: Primitive,
+}
+
+impl fmt::Display for Corner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.v)?;
+        match (self.vt, self.vn) {
+            (0, 0) => Ok(()),
+            (vt, 0) => write!(f, "/{}", vt),
+            (0, vn) => write!(f, "//{}", vn),
+            (vt, vn) => write!(f, "/{}/{}", vt, vn),
+        }
+    }
+}
+
+// elements of one node, material and primitive
+struct Bucket {
+    node: usize,
+    material: Option<usize>,
+    primitive: Primitive,
+    elements: Vec<u32>,
+    // the polygons the triangles come from: corners, then number of corners
+    polygons: Vec<u32>,
+    polygon_sizes: Vec<u32>,
+}
+
+// where the normal of a vertex comes from
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum NormalSource {
+    // vn index from the file
+    File(u32),
+    // averaged over the faces of a smoothing group
+    Smooth(u32),
+    // normal of a single face (index in the face list)
+    Flat(usize),
+    // lines and points have no normal
+    None,
+}
+
+// the file is split in chunks of whole lines that are parsed in parallel
+const MIN_CHUNK_SIZE: usize = 1 << 20;
+
+// faces and vertex data of one chunk
+#[derive(Default)]
+struct Chunk<'a> {
+    positions: Vec<f32>,
+    // one rgb color per position, white when the file has none
+    colors: Vec<f32>,
+    // true if at least one vertex had a color
+    has_colors: bool,
+    tex_coords: Vec<f32>,
+    normals: Vec<f32>,
+    corners: Vec<Corner>,
+    faces: Vec<ChunkFace>,
+    // statements changing the state of the following faces, in file order
+    statements: Vec<Statement<'a>>,
+}
+
+struct ChunkFace {
+    // range in the corner list of the chunk
+    first: usize,
+    count: usize,
+    line: usize,
+    // number of statements of the chunk that come before the face
+    statements: usize,
+    primitive: Primitive,
+}
+
+enum Statement<'a> {
+    // "s", 0 means off
+    Smoothing(u32),
+    // the rest of the line after the keyword
+    UseMtl(Cow<'a, str>),
+    MtlLib(Cow<'a, str>),
+    Object(Cow<'a, str>),
+    Group(Cow<'a, str>),
+}
+
+// lines and vertices of a chunk, used to number lines and resolve relative indices
+#[derive(Clone, Copy, Default)]
+struct Counts {
+    lines: usize,
+    positions: usize,
+    tex_coords: usize,
+    normals: usize,
+}
+
+// state of the statements, while the chunks are merged in file order
+struct Merge<'a> {
+    path: &'a Path,
+    materials: Vec<Material>,
+    material_libraries: Vec<PathBuf>,
+    // names given to usemtl, resolved once all the libraries are loaded
+    material_names: Vec<String>,
+    material_map: HashMap<String, usize>,
+    // objects ("o") and groups ("g"), groups are children of the current object
+    nodes: Vec<Node>,
+    node_map: HashMap<(NodeKind, Option<usize>, String), usize>,
+    smoothing: u32,
+    material: Option<usize>,
+    object: Option<usize>,
+    node: Option<usize>,
+}
+
+pub struct ObjLoader;
+
+// statements an .obj file can start with (after its comments)
+const STATEMENTS: &[&str] = &[
+    "v", "vt", "vn", "vp", "f", "l", "p", "o", "g", "s", "mtllib", "usemtl",
+];
+
+impl MeshLoader for ObjLoader {
+    fn name(&self) -> &'static str {
+        "obj"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["obj"]
+    }
+
+    // .obj files have no signature: the first statement must be a known one
+    fn sniff(&self, header: &[u8], _size: u64) -> bool {
+        let text = String::from_utf8_lossy(header);
+        let statement = text.lines()
+            .map(|line| line.split('#').next().unwrap_or("").trim())
+            .find(|line| !line.is_empty())
+            .and_then(|line| line.split_ascii_whitespace().next());
+        match statement {
+            Some(statement) => STATEMENTS.contains(&statement),
+            // only comments so far
+            None => !header.is_empty() && !header.contains(&0),
+        }
+    }
+
+    fn load(&self, path: &Path) -> Result<Mesh, MeshError> {
+        load_model(path)
+    }
 }
 
-pub unsafe fn load_model(path: &String) -> (Vec<f32>, Vec<u32>) {
-    let path = Path::new(path);
+pub fn load_model(path: &Path) -> Result<Mesh, MeshError> {
+    let bytes = fs::read(path).map_err(|source| MeshError::Io {
+        path: path.to_path_buf(),
+        line: 0,
+        source,
+    })?;
+    // only comments could hold something else than ascii
+    let text = String::from_utf8_lossy(&bytes);
+
+    let chunks = split_chunks(&text);
+
+    // count the lines and vertices of every chunk first,
+    // so that all the chunks can then be parsed at the same time
+    let chunk_counts: Vec<Counts> = chunks.par_iter()
+        .map(|chunk| count_chunk(chunk))
+        .collect();
+    let mut offsets = Vec::with_capacity(chunks.len());
+    let mut total = Counts::default();
+    for counts in &chunk_counts {
+        offsets.push(total);
+        total.lines += counts.lines;
+        total.positions += counts.positions;
+        total.tex_coords += counts.tex_coords;
+        total.normals += counts.normals;
+    }
+
+    let parsed: Vec<Result<Chunk, MeshError>> = chunks.par_iter()
+        .zip(offsets.par_iter())
+        .map(|(chunk, offset)| parse_chunk(path, chunk, *offset))
+        .collect();
+
+    let mut positions: Vec<f32> = Vec::with_capacity(total.positions * 3);
+    let mut colors: Vec<f32> = Vec::with_capacity(total.positions * 3);
+    let mut has_colors = false;
+    let mut tex_coords: Vec<f32> = Vec::with_capacity(total.tex_coords * 2);
+    let mut normals: Vec<f32> = Vec::with_capacity(total.normals * 3);
+    let mut corners: Vec<Corner> = Vec::new();
+    let mut faces: Vec<Face> = Vec::new();
+
+    let mut merge = Merge {
+        path,
+        materials: Vec::new(),
+        material_libraries: Vec::new(),
+        material_names: Vec::new(),
+        material_map: HashMap::new(),
+        nodes: Vec::new(),
+        node_map: HashMap::new(),
+        // files without any "s" line get smooth normals
+        smoothing: 1,
+        material: None,
+        object: None,
+        node: None,
+    };
+
+    // the first error of the file is reported
+    for chunk in parsed {
+        let chunk = chunk?;
+
+        positions.extend_from_slice(&chunk.positions);
+        colors.extend_from_slice(&chunk.colors);
+        has_colors |= chunk.has_colors;
+        tex_coords.extend_from_slice(&chunk.tex_coords);
+        normals.extend_from_slice(&chunk.normals);
+        let corner_offset = corners.len();
+        corners.extend_from_slice(&chunk.corners);
+
+        let mut statements = chunk.statements.iter();
+        let mut applied = 0;
 
-    let mut positions: Vec<f32> = Vec::new();
+        for face in &chunk.faces {
+            for statement in statements.by_ref().take(face.statements - applied) {
+                merge.apply(statement)?;
+            }
+            applied = face.statements;
+
+            faces.push(Face {
+                first: corner_offset + face.first,
+                count: face.count,
+                line: face.line,
+                smoothing: merge.smoothing,
+                material: merge.material,
+                node: merge.current_node(),
+                primitive: face.primitive,
+            });
+        }
+
+        // statements after the last face of the chunk
+        for statement in statements {
+            merge.apply(statement)?;
+        }
+    }
+
+    let Merge { materials, material_libraries, material_names, nodes, .. } = merge;
+
+    // faces may refer to vertices declared further down the file,
+    // so the upper bound can only be checked once everything is read
+    for face in &faces {
+        for corner in &corners[face.first..face.first + face.count] {
+            if corner.v as usize > positions.len() / 3 ||
+                corner.vt as usize > tex_coords.len() / 2 ||
+                corner.vn as usize > normals.len() / 3 {
+                    return Err(MeshError::IndexOutOfRange {
+                        path: path.to_path_buf(),
+                        line: face.line,
+                        text: corner.to_string(),
+                    });
+            }
+        }
+    }
+
+    let face_normals: Vec<[f32; 3]> = faces.par_iter()
+        .map(|face| face_normal(&positions, &corners[face.first..face.first + face.count]))
+        .collect();
+
+    // sum of the (area weighted) normals of the faces around a position, per smoothing group
+    let mut smooth_normals: HashMap<(u32, u32), [f32; 3]> = HashMap::new();
+    for (face, normal) in faces.iter().zip(&face_normals) {
+        if face.smoothing == 0 || face.primitive != Primitive::Triangles {
+            continue;
+        }
+        for corner in corners[face.first..face.first + face.count].iter().filter(|c| c.vn == 0) {
+            let sum = smooth_normals.entry((corner.v, face.smoothing))
+                .or_insert([0.0; 3]);
+            for i in 0..3 {
+                sum[i] += normal[i];
+            }
+        }
+    }
+
+    // usemtl names to indices in the material list (None when the material is unknown)
+    let material_names: Vec<Option<usize>> = material_names.iter()
+        .map(|name| materials.iter().position(|m| m.name == *name))
+        .collect();
+
+    let mut vertices: Vec<f32> = Vec::new();
+    // triangles, segments or points of each node and material, in order of first use
+    let mut buckets: Vec<Bucket> = Vec::new();
+    let mut bucket_map: HashMap<(usize, Option<usize>, Primitive), usize> = HashMap::new();
+
+    // every distinct combination of position, uv and normal becomes one vertex of the vertex buffer
+    let mut vertex_map: HashMap<(u32, u32, NormalSource), u32> = HashMap::new();
+
+    // reused for every face
+    let mut polygon = Vec::new();
+    let mut polygon_positions = Vec::new();
+
+    for (face_index, face) in faces.iter().enumerate() {
+        polygon.clear();
+        polygon_positions.clear();
+
+        for corner in &corners[face.first..face.first + face.count] {
+            let source = if face.primitive != Primitive::Triangles {
+                NormalSource::None
+            } else if corner.vn > 0 {
+                NormalSource::File(corner.vn)
+            } else if face.smoothing > 0 {
+                NormalSource::Smooth(face.smoothing)
+            } else {
+                NormalSource::Flat(face_index)
+            };
+
+            let index = *vertex_map.entry((corner.v, corner.vt, source)).or_insert_with(|| {
+                let normal = match source {
+                    NormalSource::File(vn) => {
+                        let vn = (vn as usize - 1) * 3;
+                        [normals[vn], normals[vn + 1], normals[vn + 2]]
+                    }
+                    NormalSource::Smooth(group) => normalize(smooth_normals[&(corner.v, group)]),
+                    NormalSource::Flat(_) => normalize(face_normals[face_index]),
+                    NormalSource::None => [0.0; 3],
+                };
+                let index = (vertices.len() / VERTEX_SIZE) as u32;
+                push_vertex(&mut vertices, &positions, &colors, &tex_coords, corner, normal);
+                index
+            });
+            polygon.push(index);
+            polygon_positions.push(position(&positions, corner.v));
+        }
+
+        let material = face.material.and_then(|m| material_names[m]);
+        let bucket = *bucket_map.entry((face.node, material, face.primitive)).or_insert_with(|| {
+            buckets.push(Bucket {
+                node: face.node,
+                material,
+                primitive: face.primitive,
+                elements: Vec::new(),
+                polygons: Vec::new(),
+                polygon_sizes: Vec::new(),
+            });
+            buckets.len() - 1
+        });
+        let bucket = &mut buckets[bucket];
+        let out = &mut bucket.elements;
+        match face.primitive {
+            Primitive::Triangles => {
+                triangulate::triangulate(&polygon, &polygon_positions, face_normals[face_index], out);
+                bucket.polygons.extend_from_slice(&polygon);
+                bucket.polygon_sizes.push(polygon.len() as u32);
+            }
+            // a polyline is drawn as separate segments
+            Primitive::Lines => {
+                for segment in polygon.windows(2) {
+                    out.extend_from_slice(segment);
+                }
+            }
+            Primitive::Points => out.extend_from_slice(&polygon),
+        }
+    }
+
+    // one sub-mesh per node, material and primitive
     let mut indices: Vec<u32> = Vec::new();
+    let mut lines: Vec<u32> = Vec::new();
+    let mut points: Vec<u32> = Vec::new();
+    let mut polygons: Vec<u32> = Vec::new();
+    let mut polygon_sizes: Vec<u32> = Vec::new();
+    let mut sub_meshes: Vec<SubMesh> = Vec::new();
+    for mut bucket in buckets {
+        let list = match bucket.primitive {
+            Primitive::Triangles => &mut indices,
+            Primitive::Lines => &mut lines,
+            Primitive::Points => &mut points,
+        };
+        sub_meshes.push(SubMesh {
+            node: bucket.node,
+            material: bucket.material,
+            primitive: bucket.primitive,
+            start: list.len(),
+            count: bucket.elements.len(),
+        });
+        list.append(&mut bucket.elements);
+        polygons.append(&mut bucket.polygons);
+        polygon_sizes.append(&mut bucket.polygon_sizes);
+    }
 
-    if let Ok(lines) = read_lines(path) {
-        // Consumes the iterator, returns an (Optional) String
-        for line in lines {
-            if let Ok(obj_string) = line {
-                // vertex line (3 floats)
-                if obj_string.starts_with("v ") {
-                    obj_string.split(" ")
-                        .for_each(|x| {
-                            if let Ok(y) = x.parse::<f32>() {
-                                positions.push(y);
-                            }
-                        });
-                // face line (at least 3 integers, may have '/' used for normals (we ignore them))
-                } else if obj_string.starts_with("f ") {
-                    let mut polygon = Vec::new();
-                    obj_string.split(" ")
-                        .for_each(|x| {
-                            let test = x.split("/").next().expect("obj is wrong");
-                            if let Ok(y) = test.parse::<u32>() {
-                                polygon.push(y);
+    Ok(Mesh {
+        vertices: vertices.into(),
+        indices: indices.into(),
+        lines,
+        points,
+        polygons,
+        polygon_sizes,
+        has_tex_coords: !tex_coords.is_empty(),
+        has_colors,
+        materials,
+        material_libraries,
+        nodes,
+        sub_meshes,
+    })
+}
+
+// cut the text in chunks of whole lines, a few per thread
+fn split_chunks(text: &str) -> Vec<&str> {
+    let size = (text.len() / (rayon::current_num_threads() * 4)).max(MIN_CHUNK_SIZE);
+
+    let mut chunks = Vec::new();
+    let mut rest = text;
+    while rest.len() > size {
+        match line_end(rest, size) {
+            Some(end) => {
+                let (chunk, tail) = rest.split_at(end + 1);
+                chunks.push(chunk);
+                rest = tail;
+            }
+            None => break,
+        }
+    }
+    chunks.push(rest);
+
+    chunks
+}
+
+// position of the first line break after `from` that is not escaped by a backslash
+fn line_end(text: &str, from: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut position = from;
+
+    loop {
+        let end = position + bytes[position..].iter().position(|&byte| byte == b'\n')?;
+        let line = text[..end].strip_suffix('\r').unwrap_or(&text[..end]);
+        if !line.ends_with('\\') {
+            return Some(end);
+        }
+        position = end + 1;
+    }
+}
+
+fn count_chunk(text: &str) -> Counts {
+    let mut counts = Counts::default();
+
+    for (_, line) in lines(text, 0) {
+        match line.split_ascii_whitespace().next() {
+            Some("v") => counts.positions += 1,
+            Some("vt") => counts.tex_coords += 1,
+            Some("vn") => counts.normals += 1,
+            _ => {}
+        }
+    }
+    // every chunk but the last one ends with a line break
+    counts.lines = text.bytes().filter(|&byte| byte == b'\n').count();
+
+    counts
+}
+
+fn parse_chunk<'a>(path: &Path, text: &'a str, offset: Counts) -> Result<Chunk<'a>, MeshError> {
+    let mut chunk = Chunk::default();
+
+    for (line_number, obj_string) in lines(text, offset.lines + 1) {
+        // build an error pointing at the current line
+        let error = |kind: fn(PathBuf, usize, String) -> MeshError| {
+            kind(path.to_path_buf(), line_number, obj_string.to_string())
+        };
+
+        let mut tokens = obj_string.split_ascii_whitespace();
+
+        match tokens.next() {
+            // vertex line: x y z, with an optional weight (x y z w, ignored)
+            // or color (x y z r g b, the alpha of x y z r g b a is ignored)
+            Some("v") => {
+                let mut values = [0.0; 7];
+                let count = parse_all_floats(tokens, &mut values)
+                    .ok_or_else(|| error(malformed_number))?;
+                let color = match count {
+                    3 | 4 => [1.0, 1.0, 1.0],
+                    6 | 7 => {
+                        chunk.has_colors = true;
+                        normalize_color([values[3], values[4], values[5]])
+                    }
+                    _ => return Err(error(malformed_number)),
+                };
+                chunk.positions.extend_from_slice(&values[..3]);
+                chunk.colors.extend_from_slice(&color);
+            }
+            // texture coordinates line: u [v [w]], w is ignored
+            Some("vt") => {
+                let mut values = [0.0; 3];
+                let count = parse_all_floats(tokens, &mut values)
+                    .ok_or_else(|| error(malformed_number))?;
+                if count == 0 {
+                    return Err(error(malformed_number));
+                }
+                chunk.tex_coords.extend_from_slice(&values[..2]);
+            }
+            // normal line (3 floats)
+            Some("vn") => {
+                let normal: [f32; 3] = parse_floats(tokens)
+                    .ok_or_else(|| error(malformed_number))?;
+                chunk.normals.extend_from_slice(&normal);
+            }
+            // smoothing group ("off" and 0 disable smoothing)
+            Some("s") => {
+                let group = match tokens.next() {
+                    Some("off") => 0,
+                    Some(group) => group.parse::<u32>()
+                        .map_err(|_| error(malformed_number))?,
+                    None => return Err(error(malformed_number)),
+                };
+                chunk.statements.push(Statement::Smoothing(group));
+            }
+            // face line (at least 3 v/vt/vn groups), polyline (at least 2 v/vt groups)
+            // or points (at least one v)
+            Some(keyword @ ("f" | "l" | "p")) => {
+                let (primitive, min_count) = match keyword {
+                    "f" => (Primitive::Triangles, 3),
+                    "l" => (Primitive::Lines, 2),
+                    _ => (Primitive::Points, 1),
+                };
+                let first = chunk.corners.len();
+                // elements declared so far, for relative indices
+                let positions = offset.positions + chunk.positions.len() / 3;
+                let tex_coords = offset.tex_coords + chunk.tex_coords.len() / 2;
+                let normals = offset.normals + chunk.normals.len() / 3;
+
+                for group in tokens {
+                    let mut group = group.split('/');
+                    let mut next = |count: usize| -> Result<u32, MeshError> {
+                        match group.next() {
+                            // missing or empty ("1//3") index
+                            None | Some("") => Ok(0),
+                            Some(i) => {
+                                let i = i.parse::<i64>()
+                                    .map_err(|_| error(malformed_number))?;
+                                resolve_index(i, count)
+                                    .ok_or_else(|| error(index_out_of_range))
                             }
-                        });
-                    indices.append(&mut to_triangles(polygon));
-                // ignored lines
-                } else if obj_string.starts_with("#") || 
-                    obj_string.starts_with("vt ") ||
-                    obj_string.starts_with("vn ") ||
-                    obj_string.starts_with("usemtl ") ||
-                    obj_string.starts_with("s ") ||
-                    obj_string.starts_with("mtllib ") ||
-                    obj_string.starts_with("o ") ||
-                    obj_string.starts_with("g ") ||
-                    obj_string.is_empty() {
-                        // skip line
-                } else {
-                    println!("Wrong obj line:\n>> {}", obj_string);
-                    // panic!("Wrong obj file");
-                    std::process::exit(1)
+                        }
+                    };
+                    let v = next(positions)?;
+                    let vt = next(tex_coords)?;
+                    let vn = next(normals)?;
+                    if v == 0 {
+                        return Err(error(malformed_number));
+                    }
+                    chunk.corners.push(Corner { v, vt, vn });
                 }
 
+                let count = chunk.corners.len() - first;
+                if count < min_count {
+                    return Err(error(degenerate_face));
+                }
+                chunk.faces.push(ChunkFace {
+                    first,
+                    count,
+                    line: line_number,
+                    statements: chunk.statements.len(),
+                    primitive,
+                });
+            }
+            // material libraries, loaded while merging
+            Some("mtllib") => chunk.statements.push(Statement::MtlLib(rest_of_line(&obj_string))),
+            Some("usemtl") => chunk.statements.push(Statement::UseMtl(rest_of_line(&obj_string))),
+            Some("o") => chunk.statements.push(Statement::Object(rest_of_line(&obj_string))),
+            Some("g") => chunk.statements.push(Statement::Group(rest_of_line(&obj_string))),
+            // ignored lines, and the parameter space vertices of free-form geometry
+            // (which is not drawn)
+            None | Some("vp") => {}
+            Some(comment) if comment.starts_with('#') => {}
+            Some(_) => return Err(error(unknown_directive)),
+        }
+    }
+
+    Ok(chunk)
+}
+
+impl<'a> Merge<'a> {
+    fn apply(&mut self, statement: &Statement) -> Result<(), MeshError> {
+        match statement {
+            Statement::Smoothing(group) => self.smoothing = *group,
+            Statement::UseMtl(name) => {
+                let name = statement_name(name);
+                let names = &mut self.material_names;
+                self.material = Some(*self.material_map.entry(String::from(name)).or_insert_with(|| {
+                    names.push(String::from(name));
+                    names.len() - 1
+                }));
+            }
+            Statement::MtlLib(libraries) => self.load_libraries(libraries)?,
+            Statement::Object(name) => {
+                self.object = Some(self.find_node(NodeKind::Object, None, statement_name(name)));
+                self.node = self.object;
+            }
+            Statement::Group(name) => {
+                self.node = Some(self.find_node(NodeKind::Group, self.object, statement_name(name)));
+            }
+        }
+        Ok(())
+    }
+
+    // material libraries, relative to the obj file
+    fn load_libraries(&mut self, libraries: &str) -> Result<(), MeshError> {
+        let directory = self.path.parent().unwrap_or_else(|| Path::new(""));
+
+        for library in libraries.split_whitespace() {
+            let library = directory.join(library);
+            let result = parse_mtl::load_materials(&library);
+            self.material_libraries.push(library);
+            match result {
+                Ok(mut library) => self.materials.append(&mut library),
+                // a missing library is common enough to only be a warning
+                Err(error @ MeshError::Io { .. }) => println!("warning: {}", error),
+                Err(error) => return Err(error),
             }
         }
+
+        Ok(())
+    }
+
+    // node of the next face, faces before any "o" or "g" line go to a default object
+    fn current_node(&mut self) -> usize {
+        match self.node {
+            Some(node) => node,
+            None => {
+                let node = self.find_node(NodeKind::Object, None, "default");
+                self.node = Some(node);
+                node
+            }
+        }
+    }
+
+    // index of the node with this kind, parent and name, created if needed
+    fn find_node(&mut self, kind: NodeKind, parent: Option<usize>, name: &str) -> usize {
+        let nodes = &mut self.nodes;
+        *self.node_map.entry((kind, parent, String::from(name))).or_insert_with(|| {
+            nodes.push(Node { name: String::from(name), kind, parent });
+            nodes.len() - 1
+        })
+    }
+}
+
+// lines of the text with their number (starting at `first`),
+// a line ending with a backslash continues on the next one
+fn lines(text: &str, first: usize) -> impl Iterator<Item = (usize, Cow<'_, str>)> {
+    let mut physical = text.split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line));
+    let mut number = first;
+
+    std::iter::from_fn(move || {
+        let start = number;
+        let mut line = Cow::Borrowed(physical.next()?);
+        number += 1;
+
+        while line.ends_with('\\') {
+            let next = match physical.next() {
+                Some(next) => next,
+                None => break,
+            };
+            number += 1;
+            // only continued lines are copied
+            let joined = line.to_mut();
+            joined.pop();
+            joined.truncate(joined.trim_end().len());
+            joined.push(' ');
+            joined.push_str(next.trim_start());
+        }
+
+        Some((start, line))
+    })
+}
+
+// what follows the keyword of a line
+fn rest_of_line<'a>(line: &Cow<'a, str>) -> Cow<'a, str> {
+    fn rest(line: &str) -> &str {
+        let line = line.trim_start();
+        let keyword = line.find(|c: char| c.is_ascii_whitespace()).unwrap_or(line.len());
+        &line[keyword..]
+    }
+
+    match line {
+        Cow::Borrowed(line) => Cow::Borrowed(rest(line)),
+        Cow::Owned(line) => Cow::Owned(String::from(rest(line))),
+    }
+}
+
+// names may contain spaces, unnamed objects and groups are called "default"
+fn statement_name(name: &str) -> &str {
+    let name = name.trim();
+    if name.is_empty() {
+        return "default";
+    }
+    name
+}
+
+// turn a 1-based or negative (relative to the `count` elements read so far) index
+// into a 1-based one, None for 0 and for relative indices going before the first element
+fn resolve_index(index: i64, count: usize) -> Option<u32> {
+    let index = if index < 0 {
+        count as i64 + 1 + index
+    } else {
+        index
+    };
+    if index < 1 || index > u32::MAX as i64 {
+        return None;
+    }
+    Some(index as u32)
+}
+
+// parse all the tokens as floats into `values`, returns how many there were
+// (None if a token is not a float or if there are too many)
+fn parse_all_floats<'a>(tokens: impl Iterator<Item = &'a str>, values: &mut [f32]) -> Option<usize> {
+    let mut count = 0;
+    for token in tokens {
+        *values.get_mut(count)? = token.parse::<f32>().ok()?;
+        count += 1;
+    }
+    Some(count)
+}
+
+// parse the first N tokens as floats, extra tokens are ignored
+fn parse_floats<'a, const N: usize>(mut tokens: impl Iterator<Item = &'a str>) -> Option<[f32; N]> {
+    let mut values = [0.0; N];
+    for value in values.iter_mut() {
+        *value = tokens.next()?.parse::<f32>().ok()?;
+    }
+    Some(values)
+}
+
+// append the position, texture coordinates (both 1-based), normal and color of a corner to the vertex buffer
+fn push_vertex(
+    vertices: &mut Vec<f32>,
+    positions: &[f32],
+    colors: &[f32],
+    tex_coords: &[f32],
+    corner: &Corner,
+    normal: [f32; 3]
+) {
+    let v = (corner.v as usize - 1) * 3;
+    vertices.extend_from_slice(&positions[v..v + 3]);
+
+    if corner.vt > 0 {
+        let vt = (corner.vt as usize - 1) * 2;
+        vertices.extend_from_slice(&tex_coords[vt..vt + 2]);
+    } else {
+        vertices.extend_from_slice(&[0.0, 0.0]);
     }
-    (positions, indices)
+
+    vertices.extend_from_slice(&normal);
+    vertices.extend_from_slice(&colors[v..v + 3]);
+    // tangent, computed once the mesh is indexed
+    vertices.extend_from_slice(&[0.0; 4]);
+}
+
+// newell's method: the length of the result is twice the area of the polygon
+fn face_normal(positions: &[f32], corners: &[Corner]) -> [f32; 3] {
+    newell_normal(corners.iter().map(|corner| position(positions, corner.v)))
+}
+
+// position of a 1-based v index
+fn position(positions: &[f32], v: u32) -> [f32; 3] {
+    let v = (v as usize - 1) * 3;
+    [positions[v], positions[v + 1], positions[v + 2]]
 }
 
-// transform any polygon to triangles
-fn to_triangles(polygon: Vec<u32>) -> Vec<u32> {
-    let mut out = Vec::<u32>::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::{COLOR_OFFSET, POSITION_OFFSET, TEX_COORD_OFFSET};
+
+    fn load(name: &str) -> Mesh {
+        load_model(Path::new(&format!("resources/objects/fixtures/{}.obj", name))).unwrap()
+    }
+
+    fn attribute<const N: usize>(mesh: &Mesh, offset: usize) -> Vec<[f32; N]> {
+        mesh.vertices.chunks_exact(VERTEX_SIZE)
+            .map(|vertex| std::array::from_fn(|i| vertex[offset + i]))
+            .collect()
+    }
+
+    // positions of the corners of each triangle
+    fn triangles(mesh: &Mesh) -> Vec<[[f32; 3]; 3]> {
+        let positions = attribute::<3>(mesh, POSITION_OFFSET);
+        mesh.indices.chunks_exact(3)
+            .map(|triangle| [0, 1, 2].map(|k| positions[triangle[k] as usize]))
+            .collect()
+    }
+
+    const SQUARE: [[f32; 3]; 4] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+
+    #[test]
+    fn tabs_separate_tokens() {
+        let mesh = load("tabs");
+        assert_eq!(mesh.vertices.len() / VERTEX_SIZE, 4);
+        assert_eq!(mesh.indices.len(), 6);
+        assert!(mesh.has_tex_coords);
+        assert_eq!(attribute::<3>(&mesh, POSITION_OFFSET), SQUARE);
+        assert_eq!(attribute::<2>(&mesh, TEX_COORD_OFFSET), [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]);
+    }
 
-    let mut len = polygon.len();
+    #[test]
+    fn repeated_spaces_are_ignored() {
+        let mesh = load("spaces");
+        assert_eq!(mesh.vertices.len() / VERTEX_SIZE, 4);
+        assert_eq!(mesh.indices.len(), 6);
+        assert_eq!(attribute::<3>(&mesh, POSITION_OFFSET), SQUARE);
+        assert_eq!(triangles(&mesh), triangles(&load("tabs")));
+    }
 
-    if len < 3 {
-        panic!("obj file is wrong!")
+    #[test]
+    fn continued_lines_are_joined() {
+        let mesh = load("continuation");
+        // the face is the quad 1 2 3 4, the last vertex is 0.0 1.0 0.0
+        assert_eq!(mesh.vertices.len() / VERTEX_SIZE, 4);
+        assert_eq!(mesh.indices.len(), 6);
+        assert_eq!(attribute::<3>(&mesh, POSITION_OFFSET), SQUARE);
+        assert!(mesh.nodes.iter().any(|node| node.name == "long name"));
     }
 
-    while len >= 3 {
-        out.push(polygon[0] - 1);
-        out.push(polygon[polygon.len() - len + 1] - 1);
-        out.push(polygon[polygon.len() - len + 2] - 1);
+    #[test]
+    fn crlf_reads_like_lf() {
+        let crlf = fs::read_to_string("resources/objects/fixtures/crlf.obj").unwrap();
+        assert!(crlf.contains("\r\n"));
+        let lf_path = std::env::temp_dir().join(format!("scop-test-{}-lf.obj", std::process::id()));
+        fs::write(&lf_path, crlf.replace("\r\n", "\n")).unwrap();
+        let lf = load_model(&lf_path);
+        fs::remove_file(&lf_path).unwrap();
+        let (lf, crlf) = (lf.unwrap(), load("crlf"));
 
-        len -= 1;
+        assert_eq!(crlf.vertices.len() / VERTEX_SIZE, 3);
+        assert_eq!(crlf.indices.len(), 3);
+        assert_eq!(crlf.vertices, lf.vertices);
+        assert_eq!(crlf.indices, lf.indices);
+        assert_eq!(
+            crlf.nodes.iter().map(|node| &node.name).collect::<Vec<_>>(),
+            lf.nodes.iter().map(|node| &node.name).collect::<Vec<_>>()
+        );
     }
 
-    out
+    #[test]
+    fn parameter_vertices_are_ignored() {
+        let text = "vp 0.5 0.5\nv 0 0 0\nv 1 0 0\nv 1 1 0\nvp 0.25\nf 1 2 3\n";
+        // the sniffer and the parser agree on a file starting with vp
+        assert!(ObjLoader.sniff(text.as_bytes(), text.len() as u64));
+        let path = std::env::temp_dir().join(format!("scop-test-{}-vp.obj", std::process::id()));
+        fs::write(&path, text).unwrap();
+        let mesh = load_model(&path);
+        fs::remove_file(&path).unwrap();
+        let mesh = mesh.unwrap();
+        assert_eq!(mesh.vertices.len() / VERTEX_SIZE, 3);
+        assert_eq!(mesh.indices.len(), 3);
+    }
+
+    #[test]
+    fn weights_are_ignored() {
+        let mesh = load("weight");
+        assert_eq!(mesh.vertices.len() / VERTEX_SIZE, 4);
+        assert_eq!(mesh.indices.len(), 6);
+        // positions are not divided by w, and a missing v coordinate is 0
+        assert_eq!(attribute::<3>(&mesh, POSITION_OFFSET), SQUARE);
+        assert_eq!(attribute::<2>(&mesh, TEX_COORD_OFFSET), [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]);
+    }
+
+    #[test]
+    fn vertex_colors() {
+        let mesh = load("colors");
+        assert!(mesh.has_colors);
+        assert_eq!(mesh.indices.len(), 12);
+        // [0, 255] colors are scaled down, the alpha of the last vertex is ignored
+        let expected = |position: [f32; 3]| match position {
+            [0.0, 0.0, 0.0] => [1.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0] => [0.0, 1.0, 0.0],
+            [1.0, 1.0, 0.0] => [0.0, 0.0, 1.0],
+            [0.0, 1.0, 0.0] => [1.0, 1.0, 0.0],
+            _ => [1.0, 1.0, 1.0],
+        };
+        let positions = attribute::<3>(&mesh, POSITION_OFFSET);
+        let colors = attribute::<3>(&mesh, COLOR_OFFSET);
+        assert_eq!(positions.len(), colors.len());
+        for (position, color) in positions.into_iter().zip(colors) {
+            assert_eq!(color, expected(position), "color of {:?}", position);
+        }
+    }
 }
@@ -0,0 +1,377 @@
+use std::{path::{Path, PathBuf}, io, fs::File, collections::HashMap};
+
+use crate::loader::{MeshError, unknown_directive, malformed_number, index_out_of_range, degenerate_face};
+use crate::mesh::{Mesh, Material, Node, NodeKind, Primitive, SubMesh, VERTEX_SIZE};
+use crate::parse_mtl;
+use crate::triangulate;
+use crate::vector::{newell_normal, normalize};
+
+// the line by line .obj parser that parse_obj replaced, kept as a baseline for the benchmark
+// (only built with the bench feature)
+// ------------------------------------------------------------------------
+// it reads the file through BufRead::lines (a String per line) on one thread, and only
+// knows the statements of the bundled models: v, vt, vn, f, s, o, g, mtllib and usemtl
+
+// one corner of a face: 1-based v/vt/vn indices (0 when missing)
+#[derive(Clone, Copy)]
+struct Corner {
+    v: u32,
+    vt: u32,
+    vn: u32,
+}
+
+struct Face {
+    corners: Vec<Corner>,
+    // line of the face in the file, used for error messages
+    line: usize,
+    // smoothing group, 0 means flat shading
+    smoothing: u32,
+    // index in the list of names given to usemtl
+    material: Option<usize>,
+    // object or group the face belongs to
+    node: usize,
+}
+
+// where the normal of a vertex comes from
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum NormalSource {
+    // vn index from the file
+    File(u32),
+    // averaged over the faces of a smoothing group
+    Smooth(u32),
+    // normal of a single face (index in the face list)
+    Flat(usize),
+}
+
+pub fn load_model(path: &Path) -> Result<Mesh, MeshError> {
+    let file = File::open(path).map_err(|source| MeshError::Io {
+        path: path.to_path_buf(),
+        line: 0,
+        source,
+    })?;
+
+    let mut positions: Vec<f32> = Vec::new();
+    let mut tex_coords: Vec<f32> = Vec::new();
+    let mut normals: Vec<f32> = Vec::new();
+    let mut faces: Vec<Face> = Vec::new();
+
+    let mut materials: Vec<Material> = Vec::new();
+    let mut material_libraries: Vec<PathBuf> = Vec::new();
+    // names given to usemtl, resolved once all the libraries are loaded
+    let mut material_names: Vec<String> = Vec::new();
+
+    // objects ("o") and groups ("g"), groups are children of the current object
+    let mut nodes: Vec<Node> = Vec::new();
+    let mut node_map: HashMap<(NodeKind, Option<usize>, String), usize> = HashMap::new();
+
+    // files without any "s" line get smooth normals
+    let mut smoothing = 1;
+    let mut material = None;
+    let mut object = None;
+    let mut node = None;
+
+    for (line_index, line) in io::BufRead::lines(io::BufReader::new(file)).enumerate() {
+        let line_number = line_index + 1;
+        let obj_string = line.map_err(|source| MeshError::Io {
+            path: path.to_path_buf(),
+            line: line_number,
+            source,
+        })?;
+
+        // build an error pointing at the current line
+        let error = |kind: fn(PathBuf, usize, String) -> MeshError| {
+            kind(path.to_path_buf(), line_number, obj_string.clone())
+        };
+
+        let mut tokens = obj_string.split(' ').filter(|x| !x.is_empty());
+
+        match tokens.next() {
+            // vertex line (3 floats)
+            Some("v") => {
+                let position = parse_floats(tokens, 3)
+                    .ok_or_else(|| error(malformed_number))?;
+                positions.extend_from_slice(&position);
+            }
+            // texture coordinates line (2 floats, optional third one is ignored)
+            Some("vt") => {
+                let uv = parse_floats(tokens, 2)
+                    .ok_or_else(|| error(malformed_number))?;
+                tex_coords.extend_from_slice(&uv);
+            }
+            // normal line (3 floats)
+            Some("vn") => {
+                let normal = parse_floats(tokens, 3)
+                    .ok_or_else(|| error(malformed_number))?;
+                normals.extend_from_slice(&normal);
+            }
+            // smoothing group ("off" and 0 disable smoothing)
+            Some("s") => {
+                smoothing = match tokens.next() {
+                    Some("off") => 0,
+                    Some(group) => group.parse::<u32>()
+                        .map_err(|_| error(malformed_number))?,
+                    None => return Err(error(malformed_number)),
+                };
+            }
+            // face line (at least 3 v/vt/vn groups)
+            Some("f") => {
+                let mut corners = Vec::new();
+                for group in tokens {
+                    let mut group = group.split('/');
+                    let mut next = |count: usize| -> Result<u32, MeshError> {
+                        match group.next() {
+                            // missing or empty ("1//3") index
+                            None | Some("") => Ok(0),
+                            Some(i) => {
+                                let i = i.parse::<i64>()
+                                    .map_err(|_| error(malformed_number))?;
+                                resolve_index(i, count)
+                                    .ok_or_else(|| error(index_out_of_range))
+                            }
+                        }
+                    };
+                    let v = next(positions.len() / 3)?;
+                    let vt = next(tex_coords.len() / 2)?;
+                    let vn = next(normals.len() / 3)?;
+                    if v == 0 {
+                        return Err(error(malformed_number));
+                    }
+                    corners.push(Corner { v, vt, vn });
+                }
+                if corners.len() < 3 {
+                    return Err(error(degenerate_face));
+                }
+                // faces before any "o" or "g" line go to a default object
+                let node = *node.get_or_insert_with(|| {
+                    find_node(&mut nodes, &mut node_map, NodeKind::Object, None, "default")
+                });
+                faces.push(Face { corners, line: line_number, smoothing, material, node });
+            }
+            // material libraries, relative to the obj file
+            Some("mtllib") => {
+                let directory = path.parent().unwrap_or_else(|| Path::new(""));
+                for library in tokens {
+                    let library = directory.join(library);
+                    let result = parse_mtl::load_materials(&library);
+                    material_libraries.push(library);
+                    match result {
+                        Ok(mut library) => materials.append(&mut library),
+                        // a missing library is common enough to only be a warning
+                        Err(error @ MeshError::Io { .. }) => println!("warning: {}", error),
+                        Err(error) => return Err(error),
+                    }
+                }
+            }
+            Some("usemtl") => {
+                let name = statement_name(tokens);
+                material = match material_names.iter().position(|x| *x == name) {
+                    Some(index) => Some(index),
+                    None => {
+                        material_names.push(name);
+                        Some(material_names.len() - 1)
+                    }
+                };
+            }
+            Some("o") => {
+                let name = statement_name(tokens);
+                object = Some(find_node(&mut nodes, &mut node_map, NodeKind::Object, None, &name));
+                node = object;
+            }
+            Some("g") => {
+                let name = statement_name(tokens);
+                node = Some(find_node(&mut nodes, &mut node_map, NodeKind::Group, object, &name));
+            }
+            // ignored lines
+            None => {}
+            Some(comment) if comment.starts_with('#') => {}
+            Some(_) => return Err(error(unknown_directive)),
+        }
+    }
+
+    // faces may refer to vertices declared further down the file,
+    // so the upper bound can only be checked once everything is read
+    for face in &faces {
+        for corner in &face.corners {
+            if corner.v as usize > positions.len() / 3 ||
+                corner.vt as usize > tex_coords.len() / 2 ||
+                corner.vn as usize > normals.len() / 3 {
+                    return Err(index_out_of_range(
+                        path.to_path_buf(),
+                        face.line,
+                        format!("{}/{}/{}", corner.v, corner.vt, corner.vn)
+                    ));
+            }
+        }
+    }
+
+    let face_normals: Vec<[f32; 3]> = faces.iter()
+        .map(|face| newell_normal(face.corners.iter().map(|corner| position(&positions, corner.v))))
+        .collect();
+
+    // sum of the (area weighted) normals of the faces around a position, per smoothing group
+    let mut smooth_normals: HashMap<(u32, u32), [f32; 3]> = HashMap::new();
+    for (face, normal) in faces.iter().zip(&face_normals) {
+        if face.smoothing == 0 {
+            continue;
+        }
+        for corner in face.corners.iter().filter(|c| c.vn == 0) {
+            let sum = smooth_normals.entry((corner.v, face.smoothing))
+                .or_insert([0.0; 3]);
+            for i in 0..3 {
+                sum[i] += normal[i];
+            }
+        }
+    }
+
+    // usemtl names to indices in the material list (None when the material is unknown)
+    let material_names: Vec<Option<usize>> = material_names.iter()
+        .map(|name| materials.iter().position(|m| m.name == *name))
+        .collect();
+
+    let mut vertices: Vec<f32> = Vec::new();
+    // triangles of each node and material, in order of first use
+    let mut buckets: Vec<(usize, Option<usize>, Vec<u32>)> = Vec::new();
+    let mut bucket_map: HashMap<(usize, Option<usize>), usize> = HashMap::new();
+
+    // every distinct combination of position, uv and normal becomes one vertex of the vertex buffer
+    let mut vertex_map: HashMap<(u32, u32, NormalSource), u32> = HashMap::new();
+
+    for (face_index, face) in faces.iter().enumerate() {
+        let mut polygon = Vec::new();
+        let mut polygon_positions = Vec::new();
+
+        for corner in &face.corners {
+            let source = if corner.vn > 0 {
+                NormalSource::File(corner.vn)
+            } else if face.smoothing > 0 {
+                NormalSource::Smooth(face.smoothing)
+            } else {
+                NormalSource::Flat(face_index)
+            };
+
+            let index = *vertex_map.entry((corner.v, corner.vt, source)).or_insert_with(|| {
+                let normal = match source {
+                    NormalSource::File(vn) => {
+                        let vn = (vn as usize - 1) * 3;
+                        [normals[vn], normals[vn + 1], normals[vn + 2]]
+                    }
+                    NormalSource::Smooth(group) => normalize(smooth_normals[&(corner.v, group)]),
+                    NormalSource::Flat(_) => normalize(face_normals[face_index]),
+                };
+                let index = (vertices.len() / VERTEX_SIZE) as u32;
+                push_vertex(&mut vertices, &positions, &tex_coords, corner, normal);
+                index
+            });
+            polygon.push(index);
+            polygon_positions.push(position(&positions, corner.v));
+        }
+
+        let material = face.material.and_then(|m| material_names[m]);
+        let bucket = *bucket_map.entry((face.node, material)).or_insert_with(|| {
+            buckets.push((face.node, material, Vec::new()));
+            buckets.len() - 1
+        });
+        triangulate::triangulate(&polygon, &polygon_positions, face_normals[face_index], &mut buckets[bucket].2);
+    }
+
+    // one sub-mesh per node and material
+    let mut indices: Vec<u32> = Vec::new();
+    let mut sub_meshes: Vec<SubMesh> = Vec::new();
+    for (node, material, mut triangles) in buckets {
+        sub_meshes.push(SubMesh {
+            node,
+            material,
+            primitive: Primitive::Triangles,
+            start: indices.len(),
+            count: triangles.len(),
+        });
+        indices.append(&mut triangles);
+    }
+
+    Ok(Mesh {
+        vertices: vertices.into(),
+        indices: indices.into(),
+        lines: Vec::new(),
+        points: Vec::new(),
+        polygons: Vec::new(),
+        polygon_sizes: Vec::new(),
+        has_tex_coords: !tex_coords.is_empty(),
+        has_colors: false,
+        materials,
+        material_libraries,
+        nodes,
+        sub_meshes,
+    })
+}
+
+// index of the node with this kind, parent and name, created if needed
+fn find_node(
+    nodes: &mut Vec<Node>,
+    node_map: &mut HashMap<(NodeKind, Option<usize>, String), usize>,
+    kind: NodeKind,
+    parent: Option<usize>,
+    name: &str
+) -> usize {
+    *node_map.entry((kind, parent, String::from(name))).or_insert_with(|| {
+        nodes.push(Node { name: String::from(name), kind, parent });
+        nodes.len() - 1
+    })
+}
+
+// names may contain spaces, unnamed objects and groups are called "default"
+fn statement_name<'a>(tokens: impl Iterator<Item = &'a str>) -> String {
+    let name: Vec<&str> = tokens.collect();
+    if name.is_empty() {
+        return String::from("default");
+    }
+    name.join(" ")
+}
+
+// turn a 1-based or negative (relative to the `count` elements read so far) index
+// into a 1-based one, None for 0 and for relative indices going before the first element
+fn resolve_index(index: i64, count: usize) -> Option<u32> {
+    let index = if index < 0 {
+        count as i64 + 1 + index
+    } else {
+        index
+    };
+    if index < 1 || index > u32::MAX as i64 {
+        return None;
+    }
+    Some(index as u32)
+}
+
+// parse the first `count` tokens as floats, extra tokens are ignored
+fn parse_floats<'a>(tokens: impl Iterator<Item = &'a str>, count: usize) -> Option<Vec<f32>> {
+    let values = tokens.take(count)
+        .map(|x| x.parse::<f32>().ok())
+        .collect::<Option<Vec<f32>>>()?;
+    if values.len() < count {
+        return None;
+    }
+    Some(values)
+}
+
+// append the position, texture coordinates (both 1-based) and normal of a corner
+// to the vertex buffer, with a white color and no tangent
+fn push_vertex(vertices: &mut Vec<f32>, positions: &[f32], tex_coords: &[f32], corner: &Corner, normal: [f32; 3]) {
+    let v = (corner.v as usize - 1) * 3;
+    vertices.extend_from_slice(&positions[v..v + 3]);
+
+    if corner.vt > 0 {
+        let vt = (corner.vt as usize - 1) * 2;
+        vertices.extend_from_slice(&tex_coords[vt..vt + 2]);
+    } else {
+        vertices.extend_from_slice(&[0.0, 0.0]);
+    }
+
+    vertices.extend_from_slice(&normal);
+    vertices.extend_from_slice(&[1.0, 1.0, 1.0]);
+    vertices.extend_from_slice(&[0.0; 4]);
+}
+
+// position of a 1-based v index
+fn position(positions: &[f32], v: u32) -> [f32; 3] {
+    let v = (v as usize - 1) * 3;
+    [positions[v], positions[v + 1], positions[v + 2]]
+}
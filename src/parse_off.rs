@@ -0,0 +1,360 @@
+use std::{path::{Path, PathBuf}, fs, collections::HashMap};
+
+use crate::loader::{MeshLoader, MeshError, unknown_directive, malformed_number, index_out_of_range, degenerate_face};
+use crate::mesh::{
+    Mesh, Node, NodeKind, Primitive, SubMesh,
+    POSITION_OFFSET, TEX_COORD_OFFSET, NORMAL_OFFSET, COLOR_OFFSET, VERTEX_SIZE
+};
+use crate::triangulate;
+use crate::vector::{newell_normal, normalize, normalize_color};
+
+// optional parts of the vertices, given by the prefixes of the "OFF" keyword
+#[derive(Default)]
+struct Header {
+    // "ST": texture coordinates
+    tex_coords: bool,
+    // "C": color
+    colors: bool,
+    // "N": normal
+    normals: bool,
+}
+
+pub struct OffLoader;
+
+impl MeshLoader for OffLoader {
+    fn name(&self) -> &'static str {
+        "off"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["off"]
+    }
+
+    // the first word (after the comments) is the keyword, maybe followed by the counts
+    fn sniff(&self, header: &[u8], _size: u64) -> bool {
+        let text = String::from_utf8_lossy(header);
+        let keyword = text.lines()
+            .map(|line| line.split('#').next().unwrap_or("").trim())
+            .find(|line| !line.is_empty())
+            .and_then(|line| line.split_ascii_whitespace().next())
+            .unwrap_or("");
+        match keyword.find("OFF") {
+            Some(end) => parse_keyword(&keyword[..end + 3]).is_some()
+                && keyword[end + 3..].bytes().all(|byte| byte.is_ascii_digit()),
+            None => false,
+        }
+    }
+
+    fn load(&self, path: &Path) -> Result<Mesh, MeshError> {
+        load_model(path)
+    }
+}
+
+// read an ascii .off file, with the COFF / NOFF / STOFF variants (and their combinations)
+// and optional per-face colors. faces with a color get their own vertices.
+pub fn load_model(path: &Path) -> Result<Mesh, MeshError> {
+    let bytes = fs::read(path).map_err(|source| MeshError::Io {
+        path: path.to_path_buf(),
+        line: 0,
+        source,
+    })?;
+    let text = String::from_utf8_lossy(&bytes);
+
+    // tokens of every line that is not empty or a comment
+    let mut lines = text.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line, line.split('#').next().unwrap_or("")))
+        .filter(|(_, _, content)| !content.trim().is_empty());
+
+    let error = |kind: fn(PathBuf, usize, String) -> MeshError, line: usize, text: &str| {
+        kind(path.to_path_buf(), line, String::from(text))
+    };
+    let end_of_file = || MeshError::InvalidData {
+        path: path.to_path_buf(),
+        text: String::from("unexpected end of file"),
+    };
+
+    // keyword, optionally followed by the counts on the same line
+    let (mut line_number, mut line, content) = lines.next().ok_or_else(end_of_file)?;
+    let mut tokens: Vec<&str> = content.split_ascii_whitespace().collect();
+    // some ModelNet files have no space after the keyword ("OFF490 518 0")
+    let end = tokens[0].find("OFF").ok_or_else(|| error(unknown_directive, line_number, line))? + 3;
+    let header = parse_keyword(&tokens[0][..end]).ok_or_else(|| error(unknown_directive, line_number, line))?;
+    if end < tokens[0].len() {
+        tokens[0] = &tokens[0][end..];
+    } else {
+        tokens.remove(0);
+    }
+    if tokens.is_empty() {
+        let content;
+        (line_number, line, content) = lines.next().ok_or_else(end_of_file)?;
+        tokens = content.split_ascii_whitespace().collect();
+    }
+    // vertex, face and edge counts (edges are not used)
+    let counts: Vec<usize> = tokens.iter()
+        .take(2)
+        .map(|count| count.parse::<usize>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| error(malformed_number, line_number, line))?;
+    let (vertex_count, face_count) = match counts[..] {
+        [vertices, faces] => (vertices, faces),
+        _ => return Err(error(malformed_number, line_number, line)),
+    };
+
+    // the counts are not trusted to preallocate, a file shorter than they say
+    // ends with an "unexpected end of file" error
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+    let mut tex_coords: Vec<[f32; 2]> = Vec::new();
+    let mut colors: Vec<[f32; 3]> = Vec::new();
+
+    for _ in 0..vertex_count {
+        let (line_number, line, content) = lines.next().ok_or_else(end_of_file)?;
+        let values = parse_floats(content).ok_or_else(|| error(malformed_number, line_number, line))?;
+        // colors may have an alpha, the number of values tells whether it is there
+        let expected = 3 + 3 * header.normals as usize + 3 * header.colors as usize + 2 * header.tex_coords as usize;
+        let has_alpha = header.colors && values.len() > expected;
+        let mut values = values.iter().copied();
+        let mut next = || values.next().ok_or_else(|| error(malformed_number, line_number, line));
+
+        positions.push([next()?, next()?, next()?]);
+        if header.normals {
+            normals.push([next()?, next()?, next()?]);
+        }
+        if header.colors {
+            let color = normalize_color([next()?, next()?, next()?]);
+            colors.push(color);
+            // the alpha is ignored
+            if has_alpha {
+                next()?;
+            }
+        }
+        if header.tex_coords {
+            tex_coords.push([next()?, next()?]);
+        }
+    }
+
+    // polygons, as (first, count) in `corners`, with their color
+    let mut corners: Vec<u32> = Vec::new();
+    let mut faces: Vec<(usize, usize, Option<[f32; 3]>)> = Vec::new();
+
+    for _ in 0..face_count {
+        let (line_number, line, content) = lines.next().ok_or_else(end_of_file)?;
+        let mut tokens = content.split_ascii_whitespace();
+        let count = tokens.next()
+            .and_then(|count| count.parse::<usize>().ok())
+            .ok_or_else(|| error(malformed_number, line_number, line))?;
+        if count < 3 {
+            return Err(error(degenerate_face, line_number, line));
+        }
+
+        let first = corners.len();
+        for _ in 0..count {
+            let index = tokens.next()
+                .and_then(|index| index.parse::<usize>().ok())
+                .ok_or_else(|| error(malformed_number, line_number, line))?;
+            if index >= vertex_count {
+                return Err(error(index_out_of_range, line_number, line));
+            }
+            corners.push(index as u32);
+        }
+
+        // r g b [a] after the indices (a single value would be a color map index, not supported)
+        let rest: Vec<&str> = tokens.collect();
+        let color = parse_floats(&rest.join(" "))
+            .ok_or_else(|| error(malformed_number, line_number, line))?;
+        let color = match color[..] {
+            [r, g, b, ..] => Some(normalize_color([r, g, b])),
+            _ => None,
+        };
+        faces.push((first, count, color));
+    }
+
+    // area weighted normals of the positions when the file has none
+    let mut smooth_normals = vec![[0.0f32; 3]; positions.len()];
+    let mut face_normals = Vec::with_capacity(faces.len());
+    for &(first, count, _) in &faces {
+        let polygon: Vec<[f32; 3]> = corners[first..first + count].iter().map(|&i| positions[i as usize]).collect();
+        let normal = newell_normal(polygon.iter().copied());
+        for &index in &corners[first..first + count] {
+            let sum = &mut smooth_normals[index as usize];
+            for i in 0..3 {
+                sum[i] += normal[i];
+            }
+        }
+        face_normals.push((normal, polygon));
+    }
+
+    let has_face_colors = faces.iter().any(|(_, _, color)| color.is_some());
+    let mut vertices: Vec<f32> = Vec::new();
+    let mut indices: Vec<u32> = Vec::new();
+    // a vertex per position, and per face color it is used with
+    let mut vertex_map: HashMap<(u32, Option<[u32; 3]>), u32> = HashMap::new();
+    let mut polygon = Vec::new();
+    // kept for the subdivision of quad meshes
+    let mut polygons = Vec::new();
+    let mut polygon_sizes = Vec::new();
+
+    for (&(first, count, color), (normal, polygon_positions)) in faces.iter().zip(&face_normals) {
+        polygon.clear();
+        for &corner in &corners[first..first + count] {
+            let key = (corner, color.map(|c| [c[0].to_bits(), c[1].to_bits(), c[2].to_bits()]));
+            let index = *vertex_map.entry(key).or_insert_with(|| {
+                let i = corner as usize;
+                let mut vertex = [0.0; VERTEX_SIZE];
+                vertex[POSITION_OFFSET..POSITION_OFFSET + 3].copy_from_slice(&positions[i]);
+                if header.tex_coords {
+                    vertex[TEX_COORD_OFFSET..TEX_COORD_OFFSET + 2].copy_from_slice(&tex_coords[i]);
+                }
+                let normal = if header.normals { normals[i] } else { normalize(smooth_normals[i]) };
+                vertex[NORMAL_OFFSET..NORMAL_OFFSET + 3].copy_from_slice(&normal);
+                // the face color wins over the vertex color
+                let vertex_color = if header.colors { colors[i] } else { [1.0; 3] };
+                vertex[COLOR_OFFSET..COLOR_OFFSET + 3].copy_from_slice(&color.unwrap_or(vertex_color));
+
+                vertices.extend_from_slice(&vertex);
+                (vertices.len() / VERTEX_SIZE - 1) as u32
+            });
+            polygon.push(index);
+        }
+        triangulate::triangulate(&polygon, polygon_positions, *normal, &mut indices);
+        polygons.extend_from_slice(&polygon);
+        polygon_sizes.push(polygon.len() as u32);
+    }
+
+    let name = path.file_stem().unwrap_or_default().to_string_lossy();
+    Ok(Mesh {
+        vertices: vertices.into(),
+        sub_meshes: vec![SubMesh {
+            node: 0,
+            material: None,
+            primitive: Primitive::Triangles,
+            start: 0,
+            count: indices.len(),
+        }],
+        indices: indices.into(),
+        lines: Vec::new(),
+        points: Vec::new(),
+        polygons,
+        polygon_sizes,
+        has_tex_coords: header.tex_coords,
+        has_colors: header.colors || has_face_colors,
+        materials: Vec::new(),
+        material_libraries: Vec::new(),
+        nodes: vec![Node { name: name.into_owned(), kind: NodeKind::Object, parent: None }],
+    })
+}
+
+// "OFF", "COFF", "NOFF", "STCNOFF"... (the prefixes are in that order)
+fn parse_keyword(keyword: &str) -> Option<Header> {
+    let mut header = Header::default();
+    let mut prefixes = keyword.strip_suffix("OFF")?;
+    if let Some(rest) = prefixes.strip_prefix("ST") {
+        header.tex_coords = true;
+        prefixes = rest;
+    }
+    if let Some(rest) = prefixes.strip_prefix('C') {
+        header.colors = true;
+        prefixes = rest;
+    }
+    if let Some(rest) = prefixes.strip_prefix('N') {
+        header.normals = true;
+        prefixes = rest;
+    }
+    // 4D and nD files are not 3d meshes
+    if !prefixes.is_empty() {
+        return None;
+    }
+    Some(header)
+}
+
+fn parse_floats(text: &str) -> Option<Vec<f32>> {
+    text.split_ascii_whitespace()
+        .map(|token| token.parse::<f32>().ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str) -> Mesh {
+        load_model(Path::new(&format!("resources/objects/fixtures/{}.off", name))).unwrap()
+    }
+
+    fn attribute(mesh: &Mesh, index: u32, offset: usize) -> [f32; 3] {
+        let p = index as usize * VERTEX_SIZE + offset;
+        [mesh.vertices[p], mesh.vertices[p + 1], mesh.vertices[p + 2]]
+    }
+
+    #[test]
+    fn quads_are_kept_as_polygons() {
+        let mesh = load("cube");
+        assert_eq!(mesh.vertices.len() / VERTEX_SIZE, 8);
+        assert_eq!(mesh.indices.len() / 3, 12);
+        assert_eq!(mesh.polygon_sizes, [4; 6]);
+        assert!(!mesh.has_colors && !mesh.has_tex_coords);
+    }
+
+    #[test]
+    fn vertex_colors() {
+        let mesh = load("square_vertex_colors");
+        assert!(mesh.has_colors);
+        assert_eq!(mesh.vertices.len() / VERTEX_SIZE, 4);
+        let colors: Vec<[f32; 3]> = (0..4).map(|i| attribute(&mesh, i, COLOR_OFFSET)).collect();
+        // the alpha is dropped
+        assert_eq!(colors, [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0], [1.0, 1.0, 1.0]]);
+    }
+
+    #[test]
+    fn vertex_normals() {
+        let mesh = load("triangle_normals");
+        assert_eq!(mesh.indices.len(), 3);
+        for i in 0..3 {
+            assert_eq!(attribute(&mesh, i, NORMAL_OFFSET), [0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn face_colors_split_vertices() {
+        // also has the counts glued to the keyword: "OFF8 6 0"
+        let mesh = load("cube_face_colors");
+        assert!(mesh.has_colors);
+        // each corner of the cube is used by three faces of different colors
+        assert_eq!(mesh.vertices.len() / VERTEX_SIZE, 24);
+        assert_eq!(mesh.indices.len() / 3, 12);
+        let face_colors: Vec<[f32; 3]> = mesh.indices.chunks_exact(6)
+            .map(|face| {
+                let color = attribute(&mesh, face[0], COLOR_OFFSET);
+                assert!(face.iter().all(|&index| attribute(&mesh, index, COLOR_OFFSET) == color));
+                color
+            })
+            .collect();
+        assert_eq!(face_colors, [
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+            // given as floats
+            [1.0, 1.0, 0.0],
+            // with an alpha
+            [0.0, 1.0, 1.0],
+            [1.0, 0.0, 1.0],
+        ]);
+    }
+
+    #[test]
+    fn counts_glued_to_the_keyword() {
+        let path = std::env::temp_dir().join(format!("scop-test-{}-modelnet.off", std::process::id()));
+        fs::write(&path, "OFF3 1 0\n0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n").unwrap();
+        let glued = load_model(&path);
+        fs::write(&path, "COFF3 1 0\n0 0 0 255 0 0\n1 0 0 255 0 0\n0 1 0 255 0 0\n3 0 1 2\n").unwrap();
+        let colored = load_model(&path);
+        fs::write(&path, "OFF3 x 0\n0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n").unwrap();
+        let malformed = load_model(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(glued.unwrap().indices.len(), 3);
+        assert!(colored.unwrap().has_colors);
+        assert!(matches!(malformed, Err(MeshError::MalformedNumber { .. })));
+    }
+}
@@ -0,0 +1,474 @@
+use std::{path::{Path, PathBuf}, io, fs};
+
+use crate::loader::{MeshLoader, MeshError, unknown_directive, malformed_number, index_out_of_range, degenerate_face};
+use crate::mesh::{
+    Mesh, Node, NodeKind, Primitive, SubMesh,
+    POSITION_OFFSET, TEX_COORD_OFFSET, NORMAL_OFFSET, COLOR_OFFSET, VERTEX_SIZE
+};
+use crate::triangulate;
+use crate::vector::{newell_normal, normalize};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+// scalar types of the properties
+#[derive(Clone, Copy)]
+enum Type {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+enum Property {
+    Scalar { name: String, kind: Type },
+    // a count followed by that many items ("property list uchar int vertex_indices")
+    List { name: String, count: Type, item: Type },
+}
+
+// "element vertex 8" and the properties declared after it
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+// values of the body, read in the order given by the header
+enum Body<'a> {
+    // one element per line: `line` and `text` are the current one,
+    // `tokens` what is left of it (reversed), `header` the number of lines before the body
+    Ascii {
+        lines: std::iter::Enumerate<std::str::Lines<'a>>,
+        tokens: Vec<&'a str>,
+        header: usize,
+        line: usize,
+        text: &'a str,
+    },
+    Binary { bytes: &'a [u8], position: usize, big_endian: bool },
+}
+
+pub struct PlyLoader;
+
+impl MeshLoader for PlyLoader {
+    fn name(&self) -> &'static str {
+        "ply"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["ply"]
+    }
+
+    // "ply" on the first line
+    fn sniff(&self, header: &[u8], _size: u64) -> bool {
+        header.starts_with(b"ply\n") || header.starts_with(b"ply\r\n")
+    }
+
+    fn load(&self, path: &Path) -> Result<Mesh, MeshError> {
+        load_model(path)
+    }
+}
+
+// read an ascii or binary (little or big endian) .ply file.
+// vertices keep their normal, texture coordinates and color when the file has them,
+// faces are triangulated.
+pub fn load_model(path: &Path) -> Result<Mesh, MeshError> {
+    let bytes = fs::read(path).map_err(|source| MeshError::Io {
+        path: path.to_path_buf(),
+        line: 0,
+        source,
+    })?;
+
+    let (format, elements, header_size, header_lines) = parse_header(path, &bytes)?;
+
+    let data = &bytes[header_size..];
+    let mut body = match format {
+        Format::Ascii => {
+            let text = std::str::from_utf8(data).map_err(|_| MeshError::Io {
+                path: path.to_path_buf(),
+                line: 0,
+                source: io::Error::new(io::ErrorKind::InvalidData, "ascii ply body is not valid utf-8"),
+            })?;
+            Body::Ascii { lines: text.lines().enumerate(), tokens: Vec::new(), header: header_lines, line: 0, text: "" }
+        }
+        _ => Body::Binary { bytes: data, position: 0, big_endian: format == Format::BigEndian },
+    };
+
+    // attributes of the vertices, in the file order
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+    let mut tex_coords: Vec<[f32; 2]> = Vec::new();
+    let mut colors: Vec<[f32; 3]> = Vec::new();
+    // polygons, as (first, count) in `corners`
+    let mut corners: Vec<u32> = Vec::new();
+    let mut faces: Vec<(usize, usize)> = Vec::new();
+    let vertex_count = elements.iter()
+        .find(|element| element.name == "vertex")
+        .map_or(0, |element| element.count);
+
+    let mut has_normals = false;
+    let mut has_tex_coords = false;
+    let mut has_colors = false;
+
+    for element in &elements {
+        let is_vertex = element.name == "vertex";
+        let is_face = element.name == "face";
+
+        if is_vertex {
+            let has = |names: &[&str]| element.properties.iter().any(|p| names.contains(&p.name()));
+            has_normals = has(&["nx"]);
+            has_tex_coords = has(&["s", "u", "texture_u", "texture_s"]);
+            has_colors = has(&["red", "r", "diffuse_red"]);
+        }
+
+        for _ in 0..element.count {
+            body.next_element(path)?;
+
+            let mut position = [0.0; 3];
+            let mut normal = [0.0; 3];
+            let mut tex_coord = [0.0; 2];
+            let mut color = [1.0; 3];
+
+            for property in &element.properties {
+                match property {
+                    Property::Scalar { name, kind } => {
+                        let value = body.value(path, *kind)?;
+                        if !is_vertex {
+                            continue;
+                        }
+                        match name.as_str() {
+                            "x" => position[0] = value as f32,
+                            "y" => position[1] = value as f32,
+                            "z" => position[2] = value as f32,
+                            "nx" => normal[0] = value as f32,
+                            "ny" => normal[1] = value as f32,
+                            "nz" => normal[2] = value as f32,
+                            "s" | "u" | "texture_u" | "texture_s" => tex_coord[0] = value as f32,
+                            "t" | "v" | "texture_v" | "texture_t" => tex_coord[1] = value as f32,
+                            "red" | "r" | "diffuse_red" => color[0] = channel(value, *kind),
+                            "green" | "g" | "diffuse_green" => color[1] = channel(value, *kind),
+                            "blue" | "b" | "diffuse_blue" => color[2] = channel(value, *kind),
+                            _ => {}
+                        }
+                    }
+                    Property::List { name, count, item } => {
+                        let count = body.value(path, *count)? as usize;
+                        let is_polygon = is_face && (name == "vertex_indices" || name == "vertex_index");
+                        let first = corners.len();
+                        for _ in 0..count {
+                            let value = body.value(path, *item)?;
+                            if is_polygon {
+                                // checked before the cast, which would turn a negative index into 0
+                                if !(0.0..vertex_count as f64).contains(&value) {
+                                    return Err(body.error(path, index_out_of_range));
+                                }
+                                corners.push(value as u32);
+                            }
+                        }
+                        if is_polygon {
+                            if count < 3 {
+                                return Err(body.error(path, degenerate_face));
+                            }
+                            faces.push((first, count));
+                        }
+                    }
+                }
+            }
+
+            if is_vertex {
+                positions.push(position);
+                normals.push(normal);
+                tex_coords.push(tex_coord);
+                colors.push(color);
+            }
+        }
+    }
+
+    // files without normals get smooth ones, weighted by the area of the faces
+    let mut triangles: Vec<u32> = Vec::new();
+    let mut polygon_positions = Vec::new();
+    let mut smooth_normals = vec![[0.0f32; 3]; positions.len()];
+    for &(first, count) in &faces {
+        let polygon = &corners[first..first + count];
+        polygon_positions.clear();
+        polygon_positions.extend(polygon.iter().map(|&i| positions[i as usize]));
+
+        let normal = newell_normal(polygon_positions.iter().copied());
+        for &index in polygon {
+            let sum = &mut smooth_normals[index as usize];
+            for i in 0..3 {
+                sum[i] += normal[i];
+            }
+        }
+        triangulate::triangulate(polygon, &polygon_positions, normal, &mut triangles);
+    }
+
+    let mut vertices: Vec<f32> = Vec::with_capacity(positions.len() * VERTEX_SIZE);
+    for i in 0..positions.len() {
+        let normal = if has_normals { normals[i] } else { normalize(smooth_normals[i]) };
+        let mut vertex = [0.0; VERTEX_SIZE];
+        vertex[POSITION_OFFSET..POSITION_OFFSET + 3].copy_from_slice(&positions[i]);
+        vertex[TEX_COORD_OFFSET..TEX_COORD_OFFSET + 2].copy_from_slice(&tex_coords[i]);
+        vertex[NORMAL_OFFSET..NORMAL_OFFSET + 3].copy_from_slice(&normal);
+        vertex[COLOR_OFFSET..COLOR_OFFSET + 3].copy_from_slice(&colors[i]);
+        vertices.extend_from_slice(&vertex);
+    }
+
+    let name = path.file_stem().unwrap_or_default().to_string_lossy();
+    Ok(Mesh {
+        vertices: vertices.into(),
+        sub_meshes: vec![SubMesh {
+            node: 0,
+            material: None,
+            primitive: Primitive::Triangles,
+            start: 0,
+            count: triangles.len(),
+        }],
+        indices: triangles.into(),
+        lines: Vec::new(),
+        points: Vec::new(),
+        // the vertex buffer is the list of vertices of the file, so are the polygon corners
+        polygon_sizes: faces.iter().map(|&(_, count)| count as u32).collect(),
+        polygons: corners,
+        has_tex_coords,
+        has_colors,
+        materials: Vec::new(),
+        material_libraries: Vec::new(),
+        nodes: vec![Node { name: name.into_owned(), kind: NodeKind::Object, parent: None }],
+    })
+}
+
+// the header is ascii text ending with "end_header", returns its size in bytes and lines
+fn parse_header(path: &Path, bytes: &[u8]) -> Result<(Format, Vec<Element>, usize, usize), MeshError> {
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut position = 0;
+    let mut line_number = 0;
+
+    loop {
+        line_number += 1;
+        let end = match bytes[position..].iter().position(|&byte| byte == b'\n') {
+            Some(end) => position + end,
+            None => return Err(MeshError::Io {
+                path: path.to_path_buf(),
+                line: line_number,
+                source: io::Error::new(io::ErrorKind::UnexpectedEof, "missing end_header"),
+            }),
+        };
+        let line = String::from_utf8_lossy(&bytes[position..end]);
+        position = end + 1;
+
+        let error = |kind: fn(PathBuf, usize, String) -> MeshError| {
+            kind(path.to_path_buf(), line_number, line.trim_end().to_string())
+        };
+
+        let tokens: Vec<&str> = line.split_ascii_whitespace().collect();
+        match tokens[..] {
+            ["ply"] if line_number == 1 => {}
+            _ if line_number == 1 => return Err(error(unknown_directive)),
+            ["format", kind, _] => format = Some(match kind {
+                "ascii" => Format::Ascii,
+                "binary_little_endian" => Format::LittleEndian,
+                "binary_big_endian" => Format::BigEndian,
+                _ => return Err(error(unknown_directive)),
+            }),
+            ["element", name, count] => elements.push(Element {
+                name: String::from(name),
+                count: count.parse().map_err(|_| error(malformed_number))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => {
+                let count = parse_type(count).ok_or_else(|| error(unknown_directive))?;
+                let item = parse_type(item).ok_or_else(|| error(unknown_directive))?;
+                let element = elements.last_mut().ok_or_else(|| error(unknown_directive))?;
+                element.properties.push(Property::List { name: String::from(name), count, item });
+            }
+            ["property", kind, name] => {
+                let kind = parse_type(kind).ok_or_else(|| error(unknown_directive))?;
+                let element = elements.last_mut().ok_or_else(|| error(unknown_directive))?;
+                element.properties.push(Property::Scalar { name: String::from(name), kind });
+            }
+            ["end_header"] => {
+                let format = format.ok_or_else(|| error(unknown_directive))?;
+                return Ok((format, elements, position, line_number));
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(error(unknown_directive)),
+        }
+    }
+}
+
+fn parse_type(name: &str) -> Option<Type> {
+    Some(match name {
+        "char" | "int8" => Type::I8,
+        "uchar" | "uint8" => Type::U8,
+        "short" | "int16" => Type::I16,
+        "ushort" | "uint16" => Type::U16,
+        "int" | "int32" => Type::I32,
+        "uint" | "uint32" => Type::U32,
+        "float" | "float32" => Type::F32,
+        "double" | "float64" => Type::F64,
+        _ => return None,
+    })
+}
+
+impl Type {
+    fn size(self) -> usize {
+        match self {
+            Type::I8 | Type::U8 => 1,
+            Type::I16 | Type::U16 => 2,
+            Type::I32 | Type::U32 | Type::F32 => 4,
+            Type::F64 => 8,
+        }
+    }
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar { name, .. } | Property::List { name, .. } => name,
+        }
+    }
+}
+
+impl<'a> Body<'a> {
+    // ascii elements start on a new line
+    fn next_element(&mut self, path: &Path) -> Result<(), MeshError> {
+        if let Body::Ascii { lines, tokens, header, line, text } = self {
+            if !tokens.is_empty() {
+                return Err(malformed_number(path.to_path_buf(), *line, String::from(*text)));
+            }
+            // empty lines are skipped
+            for (index, next) in lines.by_ref() {
+                if !next.trim().is_empty() {
+                    *line = *header + index + 1;
+                    *text = next;
+                    tokens.extend(next.split_ascii_whitespace().rev());
+                    return Ok(());
+                }
+            }
+            return Err(MeshError::Io {
+                path: path.to_path_buf(),
+                line: 0,
+                source: io::Error::new(io::ErrorKind::UnexpectedEof, "missing ply elements"),
+            });
+        }
+        Ok(())
+    }
+
+    fn value(&mut self, path: &Path, kind: Type) -> Result<f64, MeshError> {
+        match self {
+            Body::Ascii { tokens, line, text, .. } => tokens.pop()
+                .and_then(|token| token.parse::<f64>().ok())
+                .ok_or_else(|| malformed_number(path.to_path_buf(), *line, String::from(*text))),
+            Body::Binary { bytes, position, big_endian } => {
+                let size = kind.size();
+                let raw = bytes.get(*position..*position + size).ok_or_else(|| MeshError::Io {
+                    path: path.to_path_buf(),
+                    line: 0,
+                    source: io::Error::new(io::ErrorKind::UnexpectedEof, "truncated binary ply"),
+                })?;
+                *position += size;
+
+                let mut buffer = [0u8; 8];
+                buffer[..size].copy_from_slice(raw);
+                if *big_endian {
+                    buffer[..size].reverse();
+                }
+                Ok(match kind {
+                    Type::I8 => buffer[0] as i8 as f64,
+                    Type::U8 => buffer[0] as f64,
+                    Type::I16 => i16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+                    Type::U16 => u16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+                    Type::I32 => i32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64,
+                    Type::U32 => u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64,
+                    Type::F32 => f32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64,
+                    Type::F64 => f64::from_le_bytes(buffer),
+                })
+            }
+        }
+    }
+
+    fn error(&self, path: &Path, kind: fn(PathBuf, usize, String) -> MeshError) -> MeshError {
+        match self {
+            Body::Ascii { line, text, .. } => kind(path.to_path_buf(), *line, String::from(*text)),
+            Body::Binary { position, .. } => kind(path.to_path_buf(), 0, format!("byte {}", position)),
+        }
+    }
+}
+
+// colors stored as integers go from 0 to the max of their type, floats from 0 to 1
+fn channel(value: f64, kind: Type) -> f32 {
+    let max = match kind {
+        Type::U8 | Type::I8 => 255.0,
+        Type::U16 | Type::I16 => 65535.0,
+        Type::U32 | Type::I32 => 4294967295.0,
+        Type::F32 | Type::F64 => 1.0,
+    };
+    (value / max) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str) -> Result<Mesh, MeshError> {
+        load_model(Path::new(&format!("resources/objects/fixtures/{}.ply", name)))
+    }
+
+    // a file written for the test, removed once loaded
+    fn load_bytes(name: &str, bytes: &[u8]) -> Result<Mesh, MeshError> {
+        let path = std::env::temp_dir().join(format!("scop-test-{}-{}.ply", std::process::id(), name));
+        fs::write(&path, bytes).unwrap();
+        let mesh = load_model(&path);
+        fs::remove_file(&path).unwrap();
+        mesh
+    }
+
+    #[test]
+    fn encodings_load_the_same() {
+        let ascii = load("pyramid_ascii").unwrap();
+        assert_eq!(ascii.vertices.len() / VERTEX_SIZE, 5);
+        // the square base is cut in two
+        assert_eq!(ascii.indices.len() / 3, 6);
+        assert!(ascii.has_colors);
+        assert_eq!(&ascii.vertices[COLOR_OFFSET..COLOR_OFFSET + 3], &[1.0, 0.0, 0.0]);
+        assert_eq!(&ascii.vertices[VERTEX_SIZE * 4 + POSITION_OFFSET..VERTEX_SIZE * 4 + POSITION_OFFSET + 3], &[0.5, 0.5, 1.0]);
+
+        for name in ["pyramid_le", "pyramid_be"] {
+            let binary = load(name).unwrap();
+            assert!(binary.vertices == ascii.vertices, "{}: vertices differ", name);
+            assert_eq!(binary.indices, ascii.indices, "{}", name);
+            assert_eq!((&binary.polygons, &binary.polygon_sizes), (&ascii.polygons, &ascii.polygon_sizes), "{}", name);
+            assert_eq!(binary.has_colors, ascii.has_colors, "{}", name);
+        }
+    }
+
+    #[test]
+    fn negative_face_indices_are_rejected() {
+        let text = fs::read_to_string("resources/objects/fixtures/pyramid_ascii.ply").unwrap();
+        let text = text.replace("3 0 1 4", "3 0 -1 4");
+        assert!(matches!(load_bytes("negative", text.as_bytes()), Err(MeshError::IndexOutOfRange { .. })));
+
+        let mut bytes = b"ply\nformat binary_little_endian 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n".to_vec();
+        for value in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.push(3);
+        for index in [0i32, 1, -2] {
+            bytes.extend_from_slice(&index.to_le_bytes());
+        }
+        assert!(matches!(load_bytes("negative_binary", &bytes), Err(MeshError::IndexOutOfRange { .. })));
+        // the same file with a valid index loads
+        let end = bytes.len();
+        bytes[end - 4..].copy_from_slice(&2i32.to_le_bytes());
+        assert_eq!(load_bytes("positive_binary", &bytes).unwrap().indices.len(), 3);
+    }
+}
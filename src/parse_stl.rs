@@ -0,0 +1,315 @@
+use std::{path::{Path, PathBuf}, io, fs, collections::HashMap};
+
+use crate::loader::{MeshLoader, MeshError, unknown_directive, malformed_number, degenerate_face};
+use crate::mesh::{Mesh, Node, NodeKind, Primitive, SubMesh, POSITION_OFFSET, NORMAL_OFFSET, COLOR_OFFSET, VERTEX_SIZE};
+use crate::triangulate;
+use crate::vector::{length, newell_normal, normalize, scale, triangle_normal};
+use crate::weld::position_key;
+
+// binary stl: 80 bytes of header, the number of triangles,
+// then 50 bytes per triangle (normal, 3 vertices, attribute)
+const HEADER_SIZE: usize = 84;
+const TRIANGLE_SIZE: usize = 50;
+
+// triangles of one "solid"
+struct Solid {
+    name: String,
+    // facet normal and corner positions of every triangle
+    triangles: Vec<([f32; 3], [[f32; 3]; 3])>,
+}
+
+pub struct StlLoader;
+
+impl MeshLoader for StlLoader {
+    fn name(&self) -> &'static str {
+        "stl"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["stl"]
+    }
+
+    // a file read as binary must have the size its triangle count gives,
+    // the others start with "solid"
+    fn sniff(&self, header: &[u8], size: u64) -> bool {
+        !is_binary(header, size) || has_binary_size(header, size)
+    }
+
+    fn load(&self, path: &Path) -> Result<Mesh, MeshError> {
+        load_model(path)
+    }
+}
+
+// read an ascii or binary .stl file, vertices with the same position
+// and normal are shared so the result is an indexed mesh
+pub fn load_model(path: &Path) -> Result<Mesh, MeshError> {
+    let bytes = fs::read(path).map_err(|source| MeshError::Io {
+        path: path.to_path_buf(),
+        line: 0,
+        source,
+    })?;
+
+    let name = path.file_stem().unwrap_or_default().to_string_lossy();
+    let solids = if is_binary(&bytes, bytes.len() as u64) {
+        vec![parse_binary(path, &bytes, &name)?]
+    } else {
+        // a binary file with a header starting with "solid" and a few bytes more than its
+        // triangle count says fails as text, or reads as one line without any facet
+        match parse_ascii(path, &String::from_utf8_lossy(&bytes)) {
+            Ok(solids) if is_text(&bytes) || solids.iter().any(|solid| !solid.triangles.is_empty()) => solids,
+            Err(error) if is_text(&bytes) => return Err(error),
+            _ => vec![parse_binary(path, &bytes, &name)?],
+        }
+    };
+
+    Ok(build_mesh(solids))
+}
+
+// binary files may also start with "solid", the size tells them apart.
+// `header` is the start of a file of `size` bytes
+fn is_binary(header: &[u8], size: u64) -> bool {
+    let start = header.iter().position(|byte| !byte.is_ascii_whitespace()).unwrap_or(header.len());
+    has_binary_size(header, size) || !header[start..].starts_with(b"solid")
+}
+
+// the size the triangle count of a binary file gives
+fn has_binary_size(header: &[u8], size: u64) -> bool {
+    header.len() >= HEADER_SIZE && {
+        let count = u32::from_le_bytes([header[80], header[81], header[82], header[83]]) as u64;
+        size == HEADER_SIZE as u64 + count * TRIANGLE_SIZE as u64
+    }
+}
+
+// ascii files hold nothing but text
+fn is_text(bytes: &[u8]) -> bool {
+    std::str::from_utf8(bytes)
+        .is_ok_and(|text| text.chars().all(|c| !c.is_control() || c.is_ascii_whitespace()))
+}
+
+fn parse_binary(path: &Path, bytes: &[u8], name: &str) -> Result<Solid, MeshError> {
+    let truncated = || MeshError::Io {
+        path: path.to_path_buf(),
+        line: 0,
+        source: io::Error::new(io::ErrorKind::UnexpectedEof, "truncated binary stl"),
+    };
+
+    if bytes.len() < HEADER_SIZE {
+        return Err(truncated());
+    }
+    let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+    let body = &bytes[HEADER_SIZE..];
+    if body.len() < count * TRIANGLE_SIZE {
+        return Err(truncated());
+    }
+
+    let float = |offset: usize| f32::from_le_bytes([
+        body[offset], body[offset + 1], body[offset + 2], body[offset + 3]
+    ]);
+    let vector = |offset: usize| [float(offset), float(offset + 4), float(offset + 8)];
+
+    let triangles = (0..count)
+        .map(|i| {
+            let offset = i * TRIANGLE_SIZE;
+            (vector(offset), [vector(offset + 12), vector(offset + 24), vector(offset + 36)])
+        })
+        .collect();
+
+    Ok(Solid { name: String::from(name), triangles })
+}
+
+fn parse_ascii(path: &Path, text: &str) -> Result<Vec<Solid>, MeshError> {
+    let mut solids: Vec<Solid> = Vec::new();
+    // normal and corners of the facet being read
+    let mut normal = [0.0; 3];
+    let mut corners: Vec<[f32; 3]> = Vec::new();
+
+    for (line_index, line) in text.lines().enumerate() {
+        let error = |kind: fn(PathBuf, usize, String) -> MeshError| {
+            kind(path.to_path_buf(), line_index + 1, String::from(line))
+        };
+
+        let mut tokens = line.split_ascii_whitespace();
+        match tokens.next() {
+            Some("solid") => {
+                let name: Vec<&str> = tokens.collect();
+                solids.push(Solid { name: name.join(" "), triangles: Vec::new() });
+            }
+            Some("facet") => {
+                if tokens.next() != Some("normal") {
+                    return Err(error(unknown_directive));
+                }
+                normal = parse_vector(tokens).ok_or_else(|| error(malformed_number))?;
+                corners.clear();
+            }
+            Some("vertex") => {
+                corners.push(parse_vector(tokens).ok_or_else(|| error(malformed_number))?);
+            }
+            Some("endfacet") => {
+                if corners.len() < 3 {
+                    return Err(error(degenerate_face));
+                }
+                // facets are triangles, but some exporters write bigger polygons.
+                // they are split in their own plane, the facet normal may be wrong
+                let polygon: Vec<u32> = (0..corners.len() as u32).collect();
+                let mut triangles = Vec::new();
+                triangulate::triangulate(&polygon, &corners, newell_normal(corners.iter().copied()), &mut triangles);
+
+                // facets before any "solid" line still get a solid
+                if solids.is_empty() {
+                    solids.push(Solid { name: String::new(), triangles: Vec::new() });
+                }
+                let solid = solids.last_mut().unwrap();
+                for triangle in triangles.chunks_exact(3) {
+                    solid.triangles.push((normal, [
+                        corners[triangle[0] as usize],
+                        corners[triangle[1] as usize],
+                        corners[triangle[2] as usize],
+                    ]));
+                }
+            }
+            Some("outer") | Some("endloop") | Some("endsolid") | None => {}
+            Some(_) => return Err(error(unknown_directive)),
+        }
+    }
+
+    Ok(solids)
+}
+
+// vertices are shared between triangles when both their position and their normal match,
+// so facets keep their flat look
+fn build_mesh(solids: Vec<Solid>) -> Mesh {
+    let mut vertices: Vec<f32> = Vec::new();
+    let mut indices: Vec<u32> = Vec::new();
+    let mut nodes = Vec::new();
+    let mut sub_meshes = Vec::new();
+    let mut vertex_map: HashMap<([u32; 3], [u32; 3]), u32> = HashMap::new();
+
+    for solid in solids {
+        let start = indices.len();
+
+        for (normal, corners) in &solid.triangles {
+            let normal = facet_normal(*normal, corners);
+            for corner in corners {
+                let key = (position_key(*corner), position_key(normal));
+                let index = *vertex_map.entry(key).or_insert_with(|| {
+                    let index = (vertices.len() / VERTEX_SIZE) as u32;
+                    let mut vertex = [0.0; VERTEX_SIZE];
+                    vertex[POSITION_OFFSET..POSITION_OFFSET + 3].copy_from_slice(corner);
+                    vertex[NORMAL_OFFSET..NORMAL_OFFSET + 3].copy_from_slice(&normal);
+                    // white vertex color
+                    vertex[COLOR_OFFSET..COLOR_OFFSET + 3].fill(1.0);
+                    vertices.extend_from_slice(&vertex);
+                    index
+                });
+                indices.push(index);
+            }
+        }
+
+        sub_meshes.push(SubMesh {
+            node: nodes.len(),
+            material: None,
+            primitive: Primitive::Triangles,
+            start,
+            count: indices.len() - start,
+        });
+        let name = match solid.name.trim() {
+            "" => String::from("default"),
+            name => String::from(name),
+        };
+        nodes.push(Node { name, kind: NodeKind::Object, parent: None });
+    }
+
+    Mesh {
+        vertices: vertices.into(),
+        indices: indices.into(),
+        lines: Vec::new(),
+        points: Vec::new(),
+        polygons: Vec::new(),
+        polygon_sizes: Vec::new(),
+        has_tex_coords: false,
+        has_colors: false,
+        materials: Vec::new(),
+        material_libraries: Vec::new(),
+        nodes,
+        sub_meshes,
+    }
+}
+
+// the normal of the file, or the one of the triangle when the file has none (0 0 0)
+fn facet_normal(normal: [f32; 3], corners: &[[f32; 3]; 3]) -> [f32; 3] {
+    let length = length(normal);
+    if length > 0.0 && length.is_finite() {
+        return scale(normal, 1.0 / length);
+    }
+    normalize(triangle_normal(corners[0], corners[1], corners[2]))
+}
+
+fn parse_vector<'a>(mut tokens: impl Iterator<Item = &'a str>) -> Option<[f32; 3]> {
+    let mut vector = [0.0; 3];
+    for value in vector.iter_mut() {
+        *value = tokens.next()?.parse::<f32>().ok()?;
+    }
+    Some(vector)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASCII: &str = "resources/objects/fixtures/cube_ascii.stl";
+    const BINARY: &str = "resources/objects/fixtures/cube_binary.stl";
+
+    // corner positions of each triangle
+    fn triangles(mesh: &Mesh) -> Vec<[[f32; 3]; 3]> {
+        let position = |index: u32| -> [f32; 3] {
+            let p = index as usize * VERTEX_SIZE + POSITION_OFFSET;
+            [mesh.vertices[p], mesh.vertices[p + 1], mesh.vertices[p + 2]]
+        };
+        mesh.indices.chunks_exact(3).map(|t| [position(t[0]), position(t[1]), position(t[2])]).collect()
+    }
+
+    #[test]
+    fn ascii_and_binary_are_told_apart() {
+        let ascii = fs::read(ASCII).unwrap();
+        let binary = fs::read(BINARY).unwrap();
+        // both start with "solid"
+        assert!(ascii.starts_with(b"solid") && binary.starts_with(b"solid"));
+        assert!(!is_binary(&ascii, ascii.len() as u64));
+        assert!(is_binary(&binary, binary.len() as u64));
+
+        // the loader only gives the start of the file
+        assert!(StlLoader.sniff(&ascii[..HEADER_SIZE], ascii.len() as u64));
+        assert!(StlLoader.sniff(&binary[..HEADER_SIZE], binary.len() as u64));
+        let obj = fs::read("resources/objects/fixtures/tabs.obj").unwrap();
+        assert!(!StlLoader.sniff(&obj, obj.len() as u64));
+    }
+
+    #[test]
+    fn shared_corners_are_merged() {
+        for path in [ASCII, BINARY] {
+            let mesh = load_model(Path::new(path)).unwrap();
+            assert_eq!(mesh.indices.len() / 3, 12, "{}", path);
+            // 4 corners with the normal of each side
+            assert_eq!(mesh.vertices.len() / VERTEX_SIZE, 24, "{}", path);
+        }
+        let ascii = load_model(Path::new(ASCII)).unwrap();
+        let binary = load_model(Path::new(BINARY)).unwrap();
+        assert_eq!(triangles(&ascii), triangles(&binary));
+        assert_eq!(ascii.vertices, binary.vertices);
+    }
+
+    #[test]
+    fn binary_with_solid_header_and_trailing_bytes() {
+        // some exporters pad binary files, the size doesn't match the triangle count anymore
+        let mut bytes = fs::read(BINARY).unwrap();
+        bytes.extend_from_slice(&[0, 0]);
+        assert!(!is_binary(&bytes, bytes.len() as u64));
+        let path = std::env::temp_dir().join(format!("scop-test-{}-padded.stl", std::process::id()));
+        fs::write(&path, &bytes).unwrap();
+        let mesh = load_model(&path);
+        fs::remove_file(&path).unwrap();
+
+        let mesh = mesh.unwrap();
+        assert_eq!(triangles(&mesh), triangles(&load_model(Path::new(BINARY)).unwrap()));
+    }
+}
@@ -2,19 +2,58 @@
 out vec4 FragColor;
 
 in vec2 TexCoord;
+in vec3 Normal;
+in vec3 VertexColor;
+in vec3 Tangent;
+in vec3 Bitangent;
 
 // texture sampler
 uniform sampler2D texture1;
 uniform float textureMix;
 
+// material
+uniform vec3 diffuseColor;
+uniform float dissolve;
+uniform bool hasDissolveMap;
+uniform sampler2D dissolveMap;
+// tangent space normal map
+uniform bool hasNormalMap;
+uniform sampler2D normalMap;
+
+// color of the vertices instead of shades of grey
+uniform bool hasVertexColor;
+
+// part of the selected object / group
+uniform bool highlight;
+
 void main()
 {
 	// color from the texture
 	vec4 texturedColor = texture(texture1, TexCoord);
 	float id = mod(float(gl_PrimitiveID), 3.0);
 	float val = (0.25 * id) + 0.25;
-	// grey colored face
-	vec4 greyColor = vec4(val, val, val, 1.0);
+	// normal of the surface, bent by the normal map
+	vec3 normal = Normal;
+	if (hasNormalMap && length(Tangent) > 0.0) {
+		vec3 mapped = texture(normalMap, TexCoord).rgb * 2.0 - 1.0;
+		normal = mat3(normalize(Tangent), normalize(Bitangent), normalize(Normal)) * mapped;
+	}
+	// light coming from the camera (lines and points have no normal)
+	float light = 1.0;
+	if (length(normal) > 0.0) {
+		light = 0.5 + 0.5 * abs(normalize(normal).z);
+	}
+	// grey colored face (or vertex colors), tinted by the material
+	vec3 baseColor = hasVertexColor ? VertexColor : vec3(val);
+	vec4 greyColor = vec4(baseColor * light * diffuseColor, 1.0);
 	// mix based on textureMix
 	FragColor = mix(texturedColor, greyColor, textureMix);
+	if (highlight) {
+		FragColor.rgb = mix(FragColor.rgb, vec3(1.0, 0.8, 0.0), 0.3);
+	}
+	// transparency
+	FragColor.a = dissolve;
+	if (hasDissolveMap) {
+		FragColor.a *= texture(dissolveMap, TexCoord).r;
+	}
 }
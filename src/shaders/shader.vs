@@ -1,8 +1,15 @@
 #version 330 core
 layout (location = 0) in vec3 aPos;
-// layout (location = 1) in vec2 aTexCoord;
+layout (location = 1) in vec2 aTexCoord;
+layout (location = 2) in vec3 aNormal;
+layout (location = 3) in vec3 aColor;
+layout (location = 4) in vec4 aTangent;
 
 out vec2 TexCoord;
+out vec3 Normal;
+out vec3 VertexColor;
+out vec3 Tangent;
+out vec3 Bitangent;
 
 uniform mat4 model;
 uniform mat4 view;
@@ -13,7 +20,13 @@ void main()
 {
 	// normalized model
 	vec4 modelView = model * vec4(aPos, 1.0);
-	// apply texture based on model z and y
-	TexCoord = vec2((modelView.z / 2.0 + 0.5), (modelView.y / 2.0 + 0.5));
+	TexCoord = aTexCoord;
+	// normal and tangent space in camera space (model only scales uniformly)
+	mat3 normalMatrix = mat3(view * transformation);
+	Normal = normalMatrix * aNormal;
+	Tangent = normalMatrix * aTangent.xyz;
+	// w holds the handedness of the texture coordinates (mikktspace)
+	Bitangent = aTangent.w * cross(Normal, Tangent);
+	VertexColor = aColor;
 	gl_Position = projection * view * transformation * modelView ;
-}
\ No newline at end of file
+}
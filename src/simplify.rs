@@ -0,0 +1,517 @@
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use crate::half_edge::HalfEdgeMesh;
+use crate::mesh::{Mesh, Primitive, POSITION_OFFSET, NORMAL_OFFSET, VERTEX_SIZE};
+use crate::tangent;
+use crate::vector::{add, cross, dot, normalize, sub, triangle_normal};
+
+// quadric error metric decimation (garland & heckbert) and levels of detail
+// ------------------------------------------------------------------------
+
+// each level has about half the triangles of the previous one
+const LOD_RATIO: f32 = 0.5;
+const MAX_LODS: usize = 6;
+// meshes (or levels) smaller than this are not simplified further
+const MIN_LOD_TRIANGLES: usize = 1000;
+// how many pixels of the model on screen a triangle should cover
+const PIXELS_PER_TRIANGLE: f32 = 4.0;
+// planes added along the boundaries, so that open edges keep their place
+const BOUNDARY_WEIGHT: f64 = 1000.0;
+
+// symmetric 4x4 matrix: a2 ab ac ad b2 bc bd c2 cd d2
+#[derive(Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    // squared distance to the plane ax + by + cz + d = 0 (unit normal), times `weight`
+    fn from_plane([a, b, c, d]: [f64; 4], weight: f64) -> Self {
+        Self([a * a, a * b, a * c, a * d, b * b, b * c, b * d, c * c, c * d, d * d].map(|v| v * weight))
+    }
+
+    fn add(&self, other: &Quadric) -> Quadric {
+        let mut sum = *self;
+        for (value, other) in sum.0.iter_mut().zip(other.0) {
+            *value += other;
+        }
+        sum
+    }
+
+    fn error(&self, [x, y, z]: [f64; 3]) -> f64 {
+        let q = &self.0;
+        q[0] * x * x + 2.0 * q[1] * x * y + 2.0 * q[2] * x * z + 2.0 * q[3] * x
+            + q[4] * y * y + 2.0 * q[5] * y * z + 2.0 * q[6] * y
+            + q[7] * z * z + 2.0 * q[8] * z
+            + q[9]
+    }
+
+    // the point of smallest error, None when the quadric is singular (flat or linear area)
+    fn minimum(&self) -> Option<[f64; 3]> {
+        let q = &self.0;
+        let m = [[q[0], q[1], q[2]], [q[1], q[4], q[5]], [q[2], q[5], q[7]]];
+        let rhs = [-q[3], -q[6], -q[8]];
+        let det = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+        let scale = q[0].abs() + q[4].abs() + q[7].abs();
+        if det.abs() <= 1e-12 * scale * scale * scale || scale == 0.0 {
+            return None;
+        }
+        // cramer's rule
+        let column = |i: usize| -> f64 {
+            let mut n = m;
+            for row in 0..3 {
+                n[row][i] = rhs[row];
+            }
+            n[0][0] * (n[1][1] * n[2][2] - n[1][2] * n[2][1])
+                - n[0][1] * (n[1][0] * n[2][2] - n[1][2] * n[2][0])
+                + n[0][2] * (n[1][0] * n[2][1] - n[1][1] * n[2][0])
+        };
+        Some([column(0) / det, column(1) / det, column(2) / det])
+    }
+}
+
+// an edge that can be collapsed, with the versions of its vertices when it was measured
+struct Candidate {
+    cost: f64,
+    a: u32,
+    b: u32,
+    position: [f64; 3],
+    versions: (u32, u32),
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cost == other.cost
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// reversed, so that the heap gives the cheapest collapse first
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+// the mesh while it is simplified. a collapse merges vertices instead of editing the
+// half-edge mesh: each merged vertex stands for the vertices of the half-edge mesh it
+// swallowed, and its faces and neighbours are theirs.
+struct Decimator<'a> {
+    topology: &'a HalfEdgeMesh,
+    positions: Vec<[f64; 3]>,
+    quadrics: Vec<Quadric>,
+    alive: Vec<bool>,
+    // the vertex each vertex of the half-edge mesh was merged into (itself at first)
+    merged_into: Vec<u32>,
+    // the vertices of the half-edge mesh merged into each vertex
+    members: Vec<Vec<u32>>,
+    // vertices whose faces are not all reached by turning around them (non-manifold),
+    // they never move
+    locked: Vec<bool>,
+    // bumped when a vertex moves, to recognize outdated candidates
+    versions: Vec<u32>,
+    heap: BinaryHeap<Candidate>,
+}
+
+// levels of detail of a mesh: the mesh itself first, then coarser and coarser versions
+pub fn generate_lods(mesh: &Mesh) -> Vec<Mesh> {
+    let mut lods = vec![mesh.clone()];
+    while lods.len() < MAX_LODS {
+        let previous = &lods[lods.len() - 1];
+        let triangles = previous.indices.len() / 3;
+        let target = (triangles as f32 * LOD_RATIO) as usize;
+        if target < MIN_LOD_TRIANGLES {
+            break;
+        }
+        let lod = simplify(previous, target);
+        // nothing more can be collapsed
+        if lod.indices.len() / 3 > triangles * 9 / 10 {
+            break;
+        }
+        lods.push(lod);
+    }
+    lods
+}
+
+// the level to draw: the most detailed one that still has about PIXELS_PER_TRIANGLE pixels
+// per triangle, for a model covering a disc of `radius` pixels on screen
+pub fn select_lod(lods: &[Mesh], radius: f32) -> usize {
+    let budget = (std::f32::consts::PI * radius * radius / PIXELS_PER_TRIANGLE) as usize;
+    lods.iter()
+        .position(|lod| lod.indices.len() / 3 <= budget)
+        .unwrap_or(lods.len() - 1)
+}
+
+// collapse edges, cheapest first, until the mesh has at most `target` triangles
+// (or no edge can be collapsed without folding the surface).
+// vertices keep their texture coordinates and color, lines and points are kept as is.
+// the corners of the triangles around a collapsed edge get the normal of their new triangles.
+pub fn simplify(mesh: &Mesh, target: usize) -> Mesh {
+    let topology = HalfEdgeMesh::new(mesh);
+    let mut decimator = Decimator::new(&topology);
+    decimator.run(target);
+
+    // move the corners to the position of their vertex, and keep the living triangles
+    let mut vertices = mesh.vertices.to_vec();
+    let mut indices = Vec::new();
+    let mut sub_meshes = mesh.sub_meshes.clone();
+    // sum of the normals of the triangles using each corner, and the corners to update
+    let mut normals = vec![[0.0f32; 3]; mesh.vertices.len() / VERTEX_SIZE];
+    let mut changed = vec![false; normals.len()];
+    for sub_mesh in &mut sub_meshes {
+        if sub_mesh.primitive != Primitive::Triangles {
+            continue;
+        }
+        let start = indices.len();
+        for face in sub_mesh.start / 3..(sub_mesh.start + sub_mesh.count) / 3 {
+            if !decimator.alive[face] {
+                continue;
+            }
+            let triangle = decimator.face(face);
+            let [a, b, c] = triangle.map(|vertex| decimator.positions[vertex as usize].map(|value| value as f32));
+            let n = triangle_normal(a, b, c);
+            // a face that moved or whose neighbours changed
+            let moved = triangle.iter().any(|&vertex| decimator.versions[vertex as usize] != 0);
+            for (k, position) in [a, b, c].into_iter().enumerate() {
+                let corner = topology.half_edges[face * 3 + k].corner as usize;
+                vertices[corner * VERTEX_SIZE + POSITION_OFFSET..corner * VERTEX_SIZE + POSITION_OFFSET + 3]
+                    .copy_from_slice(&position);
+                indices.push(corner as u32);
+                normals[corner] = add(normals[corner], n);
+                changed[corner] |= moved;
+            }
+        }
+        sub_mesh.start = start;
+        sub_mesh.count = indices.len() - start;
+    }
+    // corners split by a hard edge or a seam each keep their own normal
+    for (corner, n) in normals.into_iter().enumerate() {
+        if changed[corner] && dot(n, n) > 0.0 {
+            vertices[corner * VERTEX_SIZE + NORMAL_OFFSET..corner * VERTEX_SIZE + NORMAL_OFFSET + 3]
+                .copy_from_slice(&normalize(n));
+        }
+    }
+
+    let mut simplified = Mesh {
+        vertices: vertices.into(),
+        indices: indices.into(),
+        lines: mesh.lines.clone(),
+        points: mesh.points.clone(),
+        // the polygons don't match the remaining triangles anymore
+        polygons: Vec::new(),
+        polygon_sizes: Vec::new(),
+        has_tex_coords: mesh.has_tex_coords,
+        has_colors: mesh.has_colors,
+        materials: mesh.materials.clone(),
+        material_libraries: mesh.material_libraries.clone(),
+        nodes: mesh.nodes.clone(),
+        sub_meshes,
+    };
+    remove_unused_vertices(&mut simplified);
+    tangent::generate(&mut simplified);
+    simplified
+}
+
+impl<'a> Decimator<'a> {
+    fn new(topology: &'a HalfEdgeMesh) -> Self {
+        let count = topology.vertex_count();
+        let positions: Vec<[f64; 3]> = topology.positions.iter().map(|p| p.map(|value| value as f64)).collect();
+        let faces: Vec<[u32; 3]> = (0..topology.face_count()).map(|face| topology.triangle(face)).collect();
+        // triangles with a repeated vertex are dropped
+        let alive: Vec<bool> = faces.iter().map(|f| f[0] != f[1] && f[1] != f[2] && f[2] != f[0]).collect();
+
+        let mut quadrics = vec![Quadric::default(); count];
+        for (face, vertices) in faces.iter().enumerate() {
+            if !alive[face] {
+                continue;
+            }
+            let [a, b, c] = vertices.map(|v| positions[v as usize]);
+            let n = cross(sub(b, a), sub(c, a));
+            let length = dot(n, n).sqrt();
+            if length > 0.0 {
+                let n = n.map(|value| value / length);
+                // weighted by the area of the triangle
+                let quadric = Quadric::from_plane([n[0], n[1], n[2], -dot(n, a)], length / 2.0);
+                for &v in vertices {
+                    quadrics[v as usize] = quadrics[v as usize].add(&quadric);
+                }
+
+                // a plane through each boundary edge, perpendicular to the triangle
+                for k in 0..3 {
+                    if !topology.is_boundary_edge(face * 3 + k) {
+                        continue;
+                    }
+                    let (p, q) = (positions[vertices[k] as usize], positions[vertices[(k + 1) % 3] as usize]);
+                    let edge = sub(q, p);
+                    let side = cross(edge, n);
+                    let side_length = dot(side, side).sqrt();
+                    if side_length > 0.0 {
+                        let side = side.map(|value| value / side_length);
+                        let quadric = Quadric::from_plane([side[0], side[1], side[2], -dot(side, p)], BOUNDARY_WEIGHT * dot(edge, edge));
+                        for v in [vertices[k], vertices[(k + 1) % 3]] {
+                            quadrics[v as usize] = quadrics[v as usize].add(&quadric);
+                        }
+                    }
+                }
+            }
+        }
+
+        // a vertex is locked when more faces start from it than the turn around it visits
+        let mut corners = vec![0usize; count];
+        for h in 0..topology.half_edges.len() {
+            corners[topology.origin(h) as usize] += 1;
+        }
+        let locked = (0..count as u32).map(|v| topology.vertex_faces(v).count() != corners[v as usize]).collect();
+
+        let mut decimator = Self {
+            topology,
+            positions,
+            quadrics,
+            alive,
+            merged_into: (0..count as u32).collect(),
+            members: (0..count as u32).map(|v| vec![v]).collect(),
+            locked,
+            versions: vec![0; count],
+            heap: BinaryHeap::new(),
+        };
+        // each edge once
+        for v in 0..count as u32 {
+            for n in topology.one_ring(v).filter(|&n| v < n).collect::<Vec<_>>() {
+                decimator.push(v, n);
+            }
+        }
+        decimator
+    }
+
+    // the vertices of a face, once merged
+    fn face(&self, face: usize) -> [u32; 3] {
+        self.topology.triangle(face).map(|v| self.merged_into[v as usize])
+    }
+
+    // living faces around a vertex
+    fn vertex_faces(&self, v: u32) -> Vec<usize> {
+        self.members[v as usize].iter()
+            .flat_map(|&member| self.topology.vertex_faces(member))
+            .filter(|&face| self.alive[face])
+            .collect()
+    }
+
+    fn run(&mut self, target: usize) {
+        let mut alive = self.alive.iter().filter(|&&alive| alive).count();
+        while alive > target {
+            let candidate = match self.heap.pop() {
+                Some(candidate) => candidate,
+                None => break,
+            };
+            let (a, b) = (candidate.a, candidate.b);
+            if candidate.versions != (self.versions[a as usize], self.versions[b as usize]) {
+                continue;
+            }
+            if let Some(removed) = self.collapse(a, b, candidate.position) {
+                alive -= removed;
+            }
+        }
+    }
+
+    // vertices linked to v by an edge, sorted. edges whose faces all died around an
+    // earlier collapse are still listed, which only makes the link condition stricter.
+    fn neighbours(&self, v: u32) -> Vec<u32> {
+        let mut list: Vec<u32> = self.members[v as usize].iter()
+            .flat_map(|&member| self.topology.one_ring(member))
+            .map(|n| self.merged_into[n as usize])
+            .filter(|&n| n != v)
+            .collect();
+        list.sort_unstable();
+        list.dedup();
+        list
+    }
+
+    // a boundary vertex stays one when other vertices are merged into it
+    fn is_boundary(&self, v: u32) -> bool {
+        self.members[v as usize].iter().any(|&member| self.topology.is_boundary_vertex(member))
+    }
+
+    // measure the collapse of an edge and add it to the heap
+    fn push(&mut self, a: u32, b: u32) {
+        if self.locked[a as usize] || self.locked[b as usize] {
+            return;
+        }
+        let quadric = self.quadrics[a as usize].add(&self.quadrics[b as usize]);
+        let (pa, pb) = (self.positions[a as usize], self.positions[b as usize]);
+        let middle = [0, 1, 2].map(|i| (pa[i] + pb[i]) / 2.0);
+        let position = quadric.minimum()
+            // far away minimums come from nearly flat areas, the ends of the edge are safer
+            .filter(|p| dot(sub(*p, middle), sub(*p, middle)) <= dot(sub(pa, pb), sub(pa, pb)) * 4.0)
+            .unwrap_or_else(|| {
+                [pa, pb, middle].into_iter()
+                    .min_by(|p, q| quadric.error(*p).total_cmp(&quadric.error(*q)))
+                    .unwrap_or(middle)
+            });
+        self.heap.push(Candidate {
+            cost: quadric.error(position).max(0.0),
+            a,
+            b,
+            position,
+            versions: (self.versions[a as usize], self.versions[b as usize]),
+        });
+    }
+
+    // merge b into a at `position`, returns the number of removed faces,
+    // None when the collapse would change the topology or fold a triangle
+    fn collapse(&mut self, a: u32, b: u32, position: [f64; 3]) -> Option<usize> {
+        let shared: Vec<usize> = self.vertex_faces(b).into_iter()
+            .filter(|&face| self.face(face).contains(&a))
+            .collect();
+        if shared.is_empty() || shared.len() > 2 {
+            return None;
+        }
+        // link condition: a and b may only have the opposite corners of the shared faces in common
+        let (around_a, around_b) = (self.neighbours(a), self.neighbours(b));
+        let common = around_a.iter().filter(|n| around_b.binary_search(n).is_ok()).count();
+        if common != shared.len() {
+            return None;
+        }
+        // an inner edge between two boundary vertices would pinch the surface
+        if shared.len() == 2 && self.is_boundary(a) && self.is_boundary(b) {
+            return None;
+        }
+
+        // the faces that move must keep their orientation
+        for &v in &[a, b] {
+            for face in self.vertex_faces(v) {
+                if shared.contains(&face) {
+                    continue;
+                }
+                let corners = self.face(face).map(|c| self.positions[c as usize]);
+                let moved = self.face(face).map(|c| if c == a || c == b { position } else { self.positions[c as usize] });
+                let before = cross(sub(corners[1], corners[0]), sub(corners[2], corners[0]));
+                let after = cross(sub(moved[1], moved[0]), sub(moved[2], moved[0]));
+                if dot(before, after) <= 0.0 {
+                    return None;
+                }
+            }
+        }
+
+        for &face in &shared {
+            self.alive[face] = false;
+        }
+        for member in std::mem::take(&mut self.members[b as usize]) {
+            self.merged_into[member as usize] = a;
+            self.members[a as usize].push(member);
+        }
+        self.positions[a as usize] = position;
+        self.quadrics[a as usize] = self.quadrics[a as usize].add(&self.quadrics[b as usize]);
+        self.versions[a as usize] += 1;
+        self.versions[b as usize] += 1;
+
+        for n in self.neighbours(a) {
+            self.push(a, n);
+        }
+        Some(shared.len())
+    }
+}
+
+// drop the vertices no element uses anymore
+fn remove_unused_vertices(mesh: &mut Mesh) {
+    let count = mesh.vertices.len() / VERTEX_SIZE;
+    let mut remap = vec![u32::MAX; count];
+    let mut vertices = Vec::new();
+    for list in [mesh.indices.to_mut(), &mut mesh.lines, &mut mesh.points] {
+        for index in list.iter_mut() {
+            if remap[*index as usize] == u32::MAX {
+                remap[*index as usize] = (vertices.len() / VERTEX_SIZE) as u32;
+                let i = *index as usize;
+                vertices.extend_from_slice(&mesh.vertices[i * VERTEX_SIZE..(i + 1) * VERTEX_SIZE]);
+            }
+            *index = remap[*index as usize];
+        }
+    }
+    mesh.vertices = vertices.into();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weld::{self, WeldOptions};
+
+    // a cube from -1 to 1, each side cut in `n` x `n` squares, facing outwards
+    fn subdivided_cube(n: usize) -> Mesh {
+        let sides: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+            ([-1.0, -1.0, 1.0], [2.0, 0.0, 0.0], [0.0, 2.0, 0.0]),
+            ([1.0, -1.0, -1.0], [-2.0, 0.0, 0.0], [0.0, 2.0, 0.0]),
+            ([1.0, -1.0, 1.0], [0.0, 0.0, -2.0], [0.0, 2.0, 0.0]),
+            ([-1.0, -1.0, -1.0], [0.0, 0.0, 2.0], [0.0, 2.0, 0.0]),
+            ([-1.0, 1.0, 1.0], [2.0, 0.0, 0.0], [0.0, 0.0, -2.0]),
+            ([-1.0, -1.0, -1.0], [2.0, 0.0, 0.0], [0.0, 0.0, 2.0]),
+        ];
+        let mut positions = Vec::new();
+        let mut indices = Vec::new();
+        for (origin, u, v) in sides {
+            let first = positions.len() as u32;
+            for j in 0..=n {
+                for i in 0..=n {
+                    let (s, t) = (i as f32 / n as f32, j as f32 / n as f32);
+                    positions.push([0, 1, 2].map(|k| origin[k] + s * u[k] + t * v[k]));
+                }
+            }
+            let at = |i: usize, j: usize| first + (j * (n + 1) + i) as u32;
+            for j in 0..n {
+                for i in 0..n {
+                    indices.extend_from_slice(&[at(i, j), at(i + 1, j), at(i + 1, j + 1), at(i, j), at(i + 1, j + 1), at(i, j + 1)]);
+                }
+            }
+        }
+        // the sides share their edges
+        let mut mesh = Mesh::from_triangles(&positions, &indices);
+        weld::weld(&mut mesh, &WeldOptions { epsilon: 0.0, positions_only: true });
+        mesh
+    }
+
+    // a mesh with that many (empty) triangles
+    fn with_triangles(count: usize) -> Mesh {
+        Mesh::from_triangles(&[[0.0; 3]], &vec![0; count * 3])
+    }
+
+    #[test]
+    fn cube_is_simplified_to_the_target_without_flipped_triangles() {
+        let mesh = subdivided_cube(8);
+        assert_eq!(mesh.indices.len() / 3, 768);
+        for target in [400, 100, 12] {
+            let simplified = simplify(&mesh, target);
+            let triangles = simplified.indices.len() / 3;
+            // a collapse removes two triangles of a closed mesh
+            assert!(triangles <= target && triangles + 2 > target, "{} triangles for {}", triangles, target);
+
+            // the cube is convex: every triangle still faces away from its center
+            let position = |index: u32| -> [f32; 3] {
+                let p = index as usize * VERTEX_SIZE + POSITION_OFFSET;
+                [simplified.vertices[p], simplified.vertices[p + 1], simplified.vertices[p + 2]]
+            };
+            for triangle in simplified.indices.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| position(triangle[i]));
+                let n = triangle_normal(a, b, c);
+                assert!(dot(n, add(add(a, b), c)) > 0.0, "{:?} is flipped ({} triangles)", [a, b, c], target);
+            }
+        }
+    }
+
+    #[test]
+    fn lod_is_chosen_by_the_size_on_screen() {
+        let lods = [with_triangles(4000), with_triangles(2000), with_triangles(1000)];
+        // a disc of radius r covers pi r^2 pixels, PIXELS_PER_TRIANGLE per triangle
+        assert_eq!(select_lod(&lods, 200.0), 0);
+        assert_eq!(select_lod(&lods, 72.0), 0);
+        assert_eq!(select_lod(&lods, 71.0), 1);
+        assert_eq!(select_lod(&lods, 40.0), 2);
+        // smaller than the coarsest level
+        assert_eq!(select_lod(&lods, 1.0), 2);
+        assert_eq!(select_lod(&lods[..1], 1.0), 0);
+    }
+}
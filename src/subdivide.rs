@@ -0,0 +1,419 @@
+use std::collections::HashMap;
+
+use crate::half_edge::HalfEdgeMesh;
+use crate::mesh::{Mesh, Primitive, POSITION_OFFSET, TEX_COORD_OFFSET, NORMAL_OFFSET, COLOR_OFFSET, VERTEX_SIZE};
+use crate::tangent;
+use crate::vector::{add, normalize, scale, triangle_normal};
+use crate::weld;
+
+// smooth a mesh by splitting its faces
+// ------------------------------------------------------------------------
+// the surface is refined on positions, so corners split by their normal or texture
+// coordinates move together. the new corners average the other attributes of the corners
+// of their face, and every vertex gets a smooth normal and a new tangent. boundaries, and edges
+// of more than two faces, are creases that stay sharp.
+
+// no level is computed past this number of triangles
+pub const MAX_TRIANGLES: usize = 2_000_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scheme {
+    // each triangle is split in 4 (loop)
+    Loop,
+    // each polygon of n corners is split in n quads (catmull-clark)
+    CatmullClark,
+}
+
+// catmull-clark for meshes that are mostly quads, loop otherwise
+pub fn scheme(mesh: &Mesh) -> Scheme {
+    let quads = mesh.polygon_sizes.iter().filter(|&&size| size == 4).count();
+    if quads > 0 && quads * 2 >= mesh.polygon_sizes.len() && polygons_match(mesh) {
+        Scheme::CatmullClark
+    } else {
+        Scheme::Loop
+    }
+}
+
+// number of triangles of the next level
+pub fn subdivided_triangles(mesh: &Mesh) -> usize {
+    match scheme(mesh) {
+        Scheme::Loop => mesh.indices.len() / 3 * 4,
+        // n quads of 2 triangles per polygon
+        Scheme::CatmullClark => mesh.polygon_sizes.iter().map(|&size| size as usize * 2).sum(),
+    }
+}
+
+// one level of subdivision. the result keeps its quads as polygons, so that
+// catmull-clark goes on with the next level. lines and points are kept as they are.
+pub fn subdivide(mesh: &Mesh) -> Mesh {
+    let scheme = scheme(mesh);
+
+    // faces, and the range of faces of each sub-mesh
+    let (corners, face_starts, ranges): (&[u32], Vec<usize>, Vec<(usize, usize)>) = match scheme {
+        Scheme::Loop => (
+            &mesh.indices,
+            (0..=mesh.indices.len() / 3).map(|face| face * 3).collect(),
+            mesh.sub_meshes.iter().map(|s| (s.start / 3, (s.start + s.count) / 3)).collect(),
+        ),
+        Scheme::CatmullClark => {
+            let mut face_starts = vec![0];
+            // first triangle of each polygon
+            let mut triangle_starts = vec![0];
+            for &size in &mesh.polygon_sizes {
+                face_starts.push(face_starts[face_starts.len() - 1] + size as usize);
+                triangle_starts.push(triangle_starts[triangle_starts.len() - 1] + size as usize - 2);
+            }
+            let ranges = mesh.sub_meshes.iter()
+                .map(|s| (
+                    triangle_starts.partition_point(|&t| t < s.start / 3),
+                    triangle_starts.partition_point(|&t| t < (s.start + s.count) / 3),
+                ))
+                .collect();
+            (&mesh.polygons, face_starts, ranges)
+        }
+    };
+    let surface = HalfEdgeMesh::from_faces(mesh, corners, face_starts);
+    let new_points = new_points(&surface, scheme);
+    let point_count = surface.vertex_count() as u32;
+    let edge_count = surface.edges.len() as u32;
+
+    let mut builder = Builder {
+        mesh,
+        points: &new_points,
+        vertices: Vec::new(),
+        vertex_points: Vec::new(),
+        vertex_map: HashMap::new(),
+    };
+    let mut indices = Vec::new();
+    let mut polygons = Vec::new();
+    let mut polygon_sizes = Vec::new();
+    let mut sub_meshes = mesh.sub_meshes.clone();
+
+    for (sub_mesh, &(first_face, end_face)) in sub_meshes.iter_mut().zip(&ranges) {
+        if sub_mesh.primitive != Primitive::Triangles {
+            continue;
+        }
+        let start = indices.len();
+        for face in first_face..end_face {
+            let range = surface.face_half_edges(face);
+            let size = range.len();
+            let corner = |h: usize| surface.half_edges[h].corner;
+            // corner on each vertex, and in the middle of the edge that follows it
+            let vertex_corners: Vec<u32> = range.clone()
+                .map(|h| builder.corner(surface.origin(h), &[corner(h)]))
+                .collect();
+            let edge_corners: Vec<u32> = range.clone()
+                .map(|h| builder.corner(point_count + surface.half_edges[h].edge, &[corner(h), corner(surface.next(h))]))
+                .collect();
+
+            match scheme {
+                Scheme::Loop => {
+                    let (v, e) = (&vertex_corners, &edge_corners);
+                    indices.extend_from_slice(&[v[0], e[0], e[2], v[1], e[1], e[0], v[2], e[2], e[1], e[0], e[1], e[2]]);
+                }
+                Scheme::CatmullClark => {
+                    let center = builder.corner(point_count + edge_count + face as u32, &corners[range]);
+                    for i in 0..size {
+                        let quad = [vertex_corners[i], edge_corners[i], center, edge_corners[(i + size - 1) % size]];
+                        indices.extend_from_slice(&[quad[0], quad[1], quad[2], quad[0], quad[2], quad[3]]);
+                        polygons.extend_from_slice(&quad);
+                        polygon_sizes.push(4);
+                    }
+                }
+            }
+        }
+        sub_mesh.start = start;
+        sub_mesh.count = indices.len() - start;
+    }
+    builder.smooth_normals(&indices);
+
+    // lines and points keep their vertices
+    let mut remap: HashMap<u32, u32> = HashMap::new();
+    let mut keep = |index: &u32| -> u32 {
+        *remap.entry(*index).or_insert_with(|| {
+            let i = *index as usize;
+            builder.vertices.extend_from_slice(&mesh.vertices[i * VERTEX_SIZE..(i + 1) * VERTEX_SIZE]);
+            (builder.vertices.len() / VERTEX_SIZE - 1) as u32
+        })
+    };
+    let lines = mesh.lines.iter().map(&mut keep).collect();
+    let points = mesh.points.iter().map(&mut keep).collect();
+
+    let mut subdivided = Mesh {
+        vertices: builder.vertices.into(),
+        indices: indices.into(),
+        lines,
+        points,
+        polygons,
+        polygon_sizes,
+        has_tex_coords: mesh.has_tex_coords,
+        has_colors: mesh.has_colors,
+        materials: mesh.materials.clone(),
+        material_libraries: mesh.material_libraries.clone(),
+        nodes: mesh.nodes.clone(),
+        sub_meshes,
+    };
+    tangent::generate(&mut subdivided);
+    subdivided
+}
+
+// the polygons give the triangles of the mesh (see Mesh::polygons)
+fn polygons_match(mesh: &Mesh) -> bool {
+    mesh.polygon_sizes.iter().all(|&size| size >= 3)
+        && mesh.polygon_sizes.iter().map(|&size| size as usize).sum::<usize>() == mesh.polygons.len()
+        && mesh.polygon_sizes.iter().map(|&size| size as usize - 2).sum::<usize>() == mesh.indices.len() / 3
+}
+
+// positions of the next level: the moved points, then a point per edge,
+// then (catmull-clark) a point per face
+fn new_points(surface: &HalfEdgeMesh, scheme: Scheme) -> Vec<[f32; 3]> {
+    let position = |h: usize| surface.positions[surface.origin(h) as usize];
+    let face_points: Vec<[f32; 3]> = match scheme {
+        Scheme::Loop => Vec::new(),
+        Scheme::CatmullClark => (0..surface.face_count())
+            .map(|face| {
+                let range = surface.face_half_edges(face);
+                let size = range.len() as f32;
+                range.fold([0.0; 3], |sum, h| add(sum, position(h))).map(|value| value / size)
+            })
+            .collect(),
+    };
+
+    // edges without a twin are creases (see half_edge), their points stay in the middle
+    let edge_points = surface.edges.iter().map(|edge| {
+        let h = edge.half_edge;
+        let (pa, pb) = (position(h), position(surface.next(h)));
+        match surface.twin(h) {
+            None => scale(add(pa, pb), 0.5),
+            // 3/8 of the ends and 1/8 of the opposite corners
+            Some(twin) if scheme == Scheme::Loop => add(
+                scale(add(pa, pb), 3.0 / 8.0),
+                scale(add(position(surface.prev(h)), position(surface.prev(twin))), 1.0 / 8.0),
+            ),
+            // average of the ends and of the two face points
+            Some(twin) => {
+                let faces = add(face_points[surface.face(h)], face_points[surface.face(twin)]);
+                scale(add(add(pa, pb), faces), 0.25)
+            }
+        }
+    });
+
+    // per point: creases and the sum of their other end
+    let mut creases = vec![0u32; surface.vertex_count()];
+    let mut crease_neighbours = vec![[0.0f32; 3]; surface.vertex_count()];
+    for edge in &surface.edges {
+        let h = edge.half_edge;
+        let (a, b) = (surface.origin(h), surface.target(h));
+        if surface.twin(h).is_none() && a != b {
+            for (v, other) in [(a, b), (b, a)] {
+                creases[v as usize] += 1;
+                crease_neighbours[v as usize] = add(crease_neighbours[v as usize], surface.positions[other as usize]);
+            }
+        }
+    }
+
+    let mut new_points: Vec<[f32; 3]> = surface.positions.iter().enumerate()
+        .map(|(i, &p)| {
+            let vertex = i as u32;
+            match creases[i] {
+                // interior vertex
+                0 => {
+                    let (valence, neighbours) = surface.one_ring(vertex)
+                        .fold((0, [0.0; 3]), |(count, sum), other| (count + 1, add(sum, surface.positions[other as usize])));
+                    let n = valence as f32;
+                    match scheme {
+                        _ if valence == 0 => p,
+                        Scheme::Loop => {
+                            let beta = if valence == 3 { 3.0 / 16.0 } else { 3.0 / (8.0 * n) };
+                            add(scale(p, 1.0 - n * beta), scale(neighbours, beta))
+                        }
+                        // (Q + 2R + (n - 3)P) / n, with Q the average of the face points
+                        // and R the average of the edge middles
+                        Scheme::CatmullClark => {
+                            let (faces, face_sum) = surface.vertex_faces(vertex)
+                                .fold((0, [0.0; 3]), |(count, sum), face| (count + 1, add(sum, face_points[face])));
+                            let q = scale(face_sum, 1.0 / faces as f32);
+                            let r = scale(add(scale(p, n), neighbours), 0.5 / n);
+                            scale(add(add(q, scale(r, 2.0)), scale(p, n - 3.0)), 1.0 / n)
+                        }
+                    }
+                }
+                // on a boundary or a crease: a curve through its two neighbours
+                2 => add(scale(p, 0.75), scale(crease_neighbours[i], 0.125)),
+                // corner where creases meet
+                _ => p,
+            }
+        })
+        .collect();
+    new_points.extend(edge_points);
+    new_points.extend(face_points);
+    new_points
+}
+
+// vertex buffer of the next level
+struct Builder<'a> {
+    mesh: &'a Mesh,
+    points: &'a [[f32; 3]],
+    vertices: Vec<f32>,
+    // point of each vertex, for the normals
+    vertex_points: Vec<u32>,
+    // corners on the same point with the same texture coordinates and color are one vertex
+    vertex_map: HashMap<(u32, [u32; 5]), u32>,
+}
+
+impl Builder<'_> {
+    // a vertex on a point, with the average attributes of a few corners of the previous level
+    fn corner(&mut self, point: u32, sources: &[u32]) -> u32 {
+        let mut vertex = [0.0f32; VERTEX_SIZE];
+        for &source in sources {
+            let source = &self.mesh.vertices[source as usize * VERTEX_SIZE..(source as usize + 1) * VERTEX_SIZE];
+            for (value, source) in vertex.iter_mut().zip(source) {
+                *value += source / sources.len() as f32;
+            }
+        }
+        vertex[POSITION_OFFSET..POSITION_OFFSET + 3].copy_from_slice(&self.points[point as usize]);
+
+        // texture coordinates and color (the normal is computed afterwards)
+        let mut key = [0; 5];
+        for (bits, i) in key.iter_mut().zip((TEX_COORD_OFFSET..TEX_COORD_OFFSET + 2).chain(COLOR_OFFSET..COLOR_OFFSET + 3)) {
+            *bits = weld::value_key(vertex[i]);
+        }
+        *self.vertex_map.entry((point, key)).or_insert_with(|| {
+            self.vertices.extend_from_slice(&vertex);
+            self.vertex_points.push(point);
+            self.vertex_points.len() as u32 - 1
+        })
+    }
+
+    // normals of the triangles around each point, weighted by their area
+    fn smooth_normals(&mut self, indices: &[u32]) {
+        let mut normals = vec![[0.0f32; 3]; self.points.len()];
+        for triangle in indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|k| self.points[self.vertex_points[triangle[k] as usize] as usize]);
+            let n = triangle_normal(a, b, c);
+            for &index in triangle {
+                let point = self.vertex_points[index as usize] as usize;
+                normals[point] = add(normals[point], n);
+            }
+        }
+
+        for (vertex, &point) in self.vertices.chunks_exact_mut(VERTEX_SIZE).zip(&self.vertex_points) {
+            vertex[NORMAL_OFFSET..NORMAL_OFFSET + 3].copy_from_slice(&normalize(normals[point as usize]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a cube from -1 to 1 made of quads facing outwards, vertex x + 2y + 4z
+    // at the corner of those coordinates (0 for -1, 1 for 1)
+    fn cube() -> Mesh {
+        let positions: Vec<[f32; 3]> = (0..8)
+            .map(|i| [i & 1, i >> 1 & 1, i >> 2 & 1].map(|bit| if bit == 1 { 1.0 } else { -1.0 }))
+            .collect();
+        let quads = [[0, 2, 3, 1], [4, 5, 7, 6], [0, 1, 5, 4], [2, 6, 7, 3], [0, 4, 6, 2], [1, 3, 7, 5]];
+        let indices: Vec<u32> = quads.iter().flat_map(|&[a, b, c, d]| [a, b, c, a, c, d]).collect();
+        let mut mesh = Mesh::from_triangles(&positions, &indices);
+        mesh.polygons = quads.concat();
+        mesh.polygon_sizes = vec![4; quads.len()];
+        mesh
+    }
+
+    // the cube without its top side (the quad on y = 1)
+    fn open_cube() -> Mesh {
+        let mut mesh = cube();
+        mesh.indices.to_mut().drain(18..24);
+        mesh.sub_meshes[0].count -= 6;
+        mesh.polygons.drain(12..16);
+        mesh.polygon_sizes.pop();
+        mesh
+    }
+
+    fn counts(mesh: &Mesh) -> (usize, usize) {
+        (mesh.indices.len() / 3, mesh.vertices.len() / VERTEX_SIZE)
+    }
+
+    // loops of the open edges, with the positions of their vertices
+    fn boundaries(mesh: &Mesh) -> Vec<Vec<[f32; 3]>> {
+        let surface = HalfEdgeMesh::new(mesh);
+        surface.boundary_loops().into_iter()
+            .map(|boundary| boundary.into_iter().map(|vertex| surface.positions[vertex as usize]).collect())
+            .collect()
+    }
+
+    #[test]
+    fn catmull_clark_cube() {
+        let mut mesh = cube();
+        // triangles, vertices: 6 quads and 8 vertices, then 4 quads per quad, and a vertex
+        // per vertex, edge and face
+        for expected in [(48, 26), (192, 98), (768, 386)] {
+            assert_eq!(scheme(&mesh), Scheme::CatmullClark);
+            assert_eq!(subdivided_triangles(&mesh), expected.0);
+            mesh = subdivide(&mesh);
+            assert_eq!(counts(&mesh), expected);
+            assert_eq!(mesh.polygon_sizes.len(), expected.0 / 2);
+        }
+        // the surface shrinks towards a sphere, and stays closed
+        assert!(boundaries(&mesh).is_empty());
+        for vertex in mesh.vertices.chunks_exact(VERTEX_SIZE) {
+            let p = &vertex[POSITION_OFFSET..POSITION_OFFSET + 3];
+            assert!(p.iter().all(|value| value.abs() < 1.0));
+        }
+    }
+
+    #[test]
+    fn loop_cube() {
+        let mut mesh = cube();
+        mesh.polygons.clear();
+        mesh.polygon_sizes.clear();
+        // 12 triangles, 8 vertices and 18 edges: 4 triangles per triangle, and a vertex
+        // per vertex and edge
+        for expected in [(48, 26), (192, 98), (768, 386)] {
+            assert_eq!(scheme(&mesh), Scheme::Loop);
+            assert_eq!(subdivided_triangles(&mesh), expected.0);
+            mesh = subdivide(&mesh);
+            assert_eq!(counts(&mesh), expected);
+        }
+        assert!(boundaries(&mesh).is_empty());
+    }
+
+    #[test]
+    fn boundary_loops_stay_in_place() {
+        for polygons in [true, false] {
+            let mut mesh = open_cube();
+            if !polygons {
+                mesh.polygons.clear();
+                mesh.polygon_sizes.clear();
+            }
+            for vertices in [8, 16] {
+                mesh = subdivide(&mesh);
+                // one hole, cut in two at each level, still in the plane of the missing side
+                let boundaries = boundaries(&mesh);
+                assert_eq!(boundaries.len(), 1);
+                assert_eq!(boundaries[0].len(), vertices);
+                assert!(boundaries[0].iter().all(|p| p[1] == 1.0), "{:?}", boundaries[0]);
+            }
+        }
+    }
+
+    #[test]
+    fn boundary_corners_follow_the_curve() {
+        let positions = [[-1.0, -1.0, 0.0], [1.0, -1.0, 0.0], [1.0, 1.0, 0.0], [-1.0, 1.0, 0.0]];
+        let mut mesh = Mesh::from_triangles(&positions, &[0, 1, 2, 0, 2, 3]);
+        mesh.polygons = vec![0, 1, 2, 3];
+        mesh.polygon_sizes = vec![4];
+        let mesh = subdivide(&mesh);
+        assert_eq!(counts(&mesh), (8, 9));
+        let boundaries = boundaries(&mesh);
+        assert_eq!(boundaries.len(), 1);
+        // corners move a quarter of the way in, edge points are the middles of the edges
+        let mut boundary = boundaries[0].clone();
+        boundary.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(boundary, [
+            [-1.0, 0.0, 0.0], [-0.75, -0.75, 0.0], [-0.75, 0.75, 0.0], [0.0, -1.0, 0.0],
+            [0.0, 1.0, 0.0], [0.75, -0.75, 0.0], [0.75, 0.75, 0.0], [1.0, 0.0, 0.0],
+        ]);
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+
+use bevy_mikktspace::Geometry;
+
+use crate::mesh::{Mesh, POSITION_OFFSET, TEX_COORD_OFFSET, NORMAL_OFFSET, TANGENT_OFFSET, VERTEX_SIZE};
+use crate::vector::{dot, orthogonal};
+
+// tangent space of the vertices, for normal maps
+// ------------------------------------------------------------------------
+// computed by mikktspace (the bevy_mikktspace port of the reference implementation), like
+// blender, substance and the gltf samples, so that baked normal maps match. the bitangent
+// is not stored, it is w * cross(normal, tangent). mikktspace gives a tangent per corner
+// of each triangle, a vertex whose corners got different tangents (a uv seam, mirrored
+// triangles...) is split.
+
+// the triangles of a mesh as mikktspace sees them, and the tangent of each corner
+struct Corners<'a> {
+    mesh: &'a Mesh,
+    tangents: Vec<[f32; 4]>,
+}
+
+impl Corners<'_> {
+    fn attribute<const N: usize>(&self, face: usize, vert: usize, offset: usize) -> [f32; N] {
+        let p = self.mesh.indices[face * 3 + vert] as usize * VERTEX_SIZE + offset;
+        std::array::from_fn(|i| self.mesh.vertices[p + i])
+    }
+}
+
+impl Geometry for Corners<'_> {
+    fn num_faces(&self) -> usize {
+        self.mesh.indices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.attribute(face, vert, POSITION_OFFSET)
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.attribute(face, vert, NORMAL_OFFSET)
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        self.attribute(face, vert, TEX_COORD_OFFSET)
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.tangents[face * 3 + vert] = tangent;
+    }
+}
+
+// write the tangent of every vertex used by the triangles
+pub fn generate(mesh: &mut Mesh) {
+    if mesh.indices.is_empty() {
+        return;
+    }
+    let mut corners = Corners { mesh, tangents: vec![[0.0; 4]; mesh.indices.len()] };
+    bevy_mikktspace::generate_tangents(&mut corners);
+    let tangents = corners.tangents;
+
+    // tangent given to each vertex, and the copies of the vertices with another one
+    let mut assigned: Vec<Option<[u32; 4]>> = vec![None; mesh.vertices.len() / VERTEX_SIZE];
+    let mut copies: HashMap<(u32, [u32; 4]), u32> = HashMap::new();
+    for (index, mut tangent) in mesh.indices.iter_mut().zip(tangents) {
+        let vertex = *index as usize;
+        // corners left without a tangent (no texture coordinates around them) get any
+        // direction orthogonal to the normal
+        if dot([tangent[0], tangent[1], tangent[2]], [tangent[0], tangent[1], tangent[2]]) == 0.0 {
+            let n = vertex * VERTEX_SIZE + NORMAL_OFFSET;
+            let t = orthogonal([mesh.vertices[n], mesh.vertices[n + 1], mesh.vertices[n + 2]]);
+            tangent = [t[0], t[1], t[2], 1.0];
+        }
+        let key = tangent.map(f32::to_bits);
+        *index = match assigned[vertex] {
+            Some(current) if current == key => *index,
+            Some(_) => *copies.entry((*index, key)).or_insert_with(|| {
+                mesh.vertices.to_mut().extend_from_within(vertex * VERTEX_SIZE..(vertex + 1) * VERTEX_SIZE);
+                let copy = mesh.vertices.len() / VERTEX_SIZE - 1;
+                mesh.vertices[copy * VERTEX_SIZE + TANGENT_OFFSET..(copy + 1) * VERTEX_SIZE].copy_from_slice(&tangent);
+                copy as u32
+            }),
+            None => {
+                assigned[vertex] = Some(key);
+                mesh.vertices[vertex * VERTEX_SIZE + TANGENT_OFFSET..(vertex + 1) * VERTEX_SIZE].copy_from_slice(&tangent);
+                *index
+            }
+        };
+    }
+    // the polygons keep the first vertex of a split corner, they are only
+    // used to interpolate the other attributes, and tangents are computed again
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a unit cube, 4 vertices per side with texture coordinates from 0 to 1,
+    // u going along `u` and v along `v`
+    fn cube() -> (Mesh, Vec<[f32; 3]>) {
+        let sides: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+            ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+            ([1.0, 0.0, 0.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+            ([1.0, 0.0, 1.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+            ([0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+            ([0.0, 1.0, 1.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+            ([0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+        ];
+        let mut positions = Vec::new();
+        let mut indices = Vec::new();
+        let mut attributes = Vec::new();
+        for (origin, u, v) in sides {
+            let first = positions.len() as u32;
+            for (s, t) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+                positions.push([0, 1, 2].map(|i| origin[i] + s * u[i] + t * v[i]));
+                attributes.push(([s, t], crate::vector::cross(u, v)));
+            }
+            indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+        }
+        let mut mesh = Mesh::from_triangles(&positions, &indices);
+        for (vertex, (uv, normal)) in mesh.vertices.chunks_exact_mut(VERTEX_SIZE).zip(attributes) {
+            vertex[TEX_COORD_OFFSET..TEX_COORD_OFFSET + 2].copy_from_slice(&uv);
+            vertex[NORMAL_OFFSET..NORMAL_OFFSET + 3].copy_from_slice(&normal);
+        }
+        mesh.has_tex_coords = true;
+        let expected = sides.iter().flat_map(|&(_, u, _)| [u; 4]).collect();
+        (mesh, expected)
+    }
+
+    fn tangents(mesh: &Mesh) -> Vec<[f32; 4]> {
+        mesh.vertices.chunks_exact(VERTEX_SIZE)
+            .map(|vertex| std::array::from_fn(|i| vertex[TANGENT_OFFSET + i]))
+            .collect()
+    }
+
+    fn assert_close(tangent: [f32; 4], expected: [f32; 4]) {
+        assert!((0..4).all(|i| (tangent[i] - expected[i]).abs() < 1e-5), "{:?} != {:?}", tangent, expected);
+    }
+
+    #[test]
+    fn cube_tangents_follow_u() {
+        let (mut mesh, expected) = cube();
+        generate(&mut mesh);
+        // no seam inside a side, no vertex is split
+        assert_eq!(mesh.vertices.len() / VERTEX_SIZE, 24);
+        for (tangent, u) in tangents(&mesh).into_iter().zip(expected) {
+            assert_close(tangent, [u[0], u[1], u[2], 1.0]);
+        }
+    }
+
+    #[test]
+    fn mirrored_texture_flips_the_bitangent() {
+        let (mut mesh, expected) = cube();
+        // u goes the other way on every side
+        for vertex in mesh.vertices.chunks_exact_mut(VERTEX_SIZE) {
+            vertex[TEX_COORD_OFFSET] = 1.0 - vertex[TEX_COORD_OFFSET];
+        }
+        generate(&mut mesh);
+        for (tangent, u) in tangents(&mesh).into_iter().zip(expected) {
+            assert_close(tangent, [-u[0], -u[1], -u[2], -1.0]);
+        }
+    }
+
+    #[test]
+    fn seam_splits_vertices() {
+        let positions = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+        let mut mesh = Mesh::from_triangles(&positions, &[0, 1, 2, 0, 2, 3]);
+        // the second triangle has its u along y: the diagonal is a seam
+        for (vertex, uv) in mesh.vertices.chunks_exact_mut(VERTEX_SIZE).zip([[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [1.0, 0.0]]) {
+            vertex[TEX_COORD_OFFSET..TEX_COORD_OFFSET + 2].copy_from_slice(&uv);
+            vertex[NORMAL_OFFSET..NORMAL_OFFSET + 3].copy_from_slice(&[0.0, 0.0, 1.0]);
+        }
+        generate(&mut mesh);
+        assert_eq!(mesh.vertices.len() / VERTEX_SIZE, 6);
+        let tangents = tangents(&mesh);
+        for &index in &mesh.indices[..3] {
+            assert_close(tangents[index as usize], [1.0, 0.0, 0.0, 1.0]);
+        }
+        for &index in &mesh.indices[3..] {
+            assert_close(tangents[index as usize], [0.0, 1.0, 0.0, -1.0]);
+        }
+    }
+}
@@ -1,18 +1,38 @@
 use std::path::Path;
 use std::os::raw::c_void;
 use image;
-use image::GenericImage;
+use image::{DynamicImage, GenericImage};
 use image::DynamicImage::*;
 
-// TODO: add error management
-pub unsafe fn load_texture(path: &str) -> u32 {
-    let mut texture_id = 0;
+use crate::mesh::TextureImage;
+
+// load an image file as a texture, warn and return None if it can't be decoded
+pub unsafe fn load_texture(path: &str) -> Option<u32> {
+    match image::open(Path::new(path)) {
+        Ok(img) => Some(create_texture(img)),
+        Err(error) => {
+            println!("warning: texture {}: {}", path, error);
+            None
+        }
+    }
+}
+
+// decode a png or jpeg held in memory (an image embedded in a gltf model),
+// warn and return None if it is not a valid image
+pub unsafe fn load_texture_from_memory(data: &[u8]) -> Option<u32> {
+    match image::load_from_memory(data) {
+        Ok(img) => Some(create_texture(img)),
+        Err(error) => {
+            println!("warning: embedded texture: {}", error);
+            None
+        }
+    }
+}
 
-    // load and create a texture
-    // -------------------------
+// upload the image as a new texture
+unsafe fn create_texture(img: DynamicImage) -> u32 {
+    let mut texture_id = 0;
     gl::GenTextures(1, &mut texture_id);
-    let img = image::open(&Path::new(path))
-    .expect("Texture failed to load");
 
     // flip image vertically
     let img = img.flipv();
@@ -71,3 +91,20 @@ pub unsafe fn load_texture(path: &str) -> u32 {
 
     texture_id
 }
+
+// load a texture referenced by a model, warn and return None if the file is missing or invalid
+pub unsafe fn load_optional_texture(path: &Path) -> Option<u32> {
+    if !path.is_file() {
+        println!("warning: texture {:?} not found", path);
+        return None;
+    }
+    load_texture(&path.to_string_lossy())
+}
+
+// the texture map of a material, from its file or from the model
+pub unsafe fn load_material_texture(image: &TextureImage) -> Option<u32> {
+    match image {
+        TextureImage::File(path) => load_optional_texture(path),
+        TextureImage::Embedded(data) => load_texture_from_memory(data),
+    }
+}
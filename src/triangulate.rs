@@ -0,0 +1,200 @@
+use crate::vector::{cross, dot, length, normalize, scale};
+
+// split polygons into triangles
+// ------------------------------------------------------------------------
+
+// below this (projected) area a corner is considered flat
+const EPSILON: f32 = 1e-12;
+
+// triangulate a polygon given by its vertex indices and their positions,
+// `normal` is the normal of the best-fit plane of the polygon (newell's method).
+// convex polygons are fanned, others are ear-clipped in the plane.
+// the triangles are appended to `out`.
+pub fn triangulate(polygon: &[u32], positions: &[[f32; 3]], normal: [f32; 3], out: &mut Vec<u32>) {
+    if polygon.len() == 3 {
+        out.extend_from_slice(polygon);
+        return;
+    }
+
+    let points = match project(positions, normal) {
+        Some(points) => points,
+        // no plane to work in (every vertex on a line)
+        None => return fan(polygon, out),
+    };
+
+    if is_convex(&points) {
+        return fan(polygon, out);
+    }
+
+    ear_clip(polygon, &points, out)
+}
+
+// fan the polygon from its first vertex, only valid for convex polygons
+pub fn fan(polygon: &[u32], out: &mut Vec<u32>) {
+    for i in 1..polygon.len() - 1 {
+        out.push(polygon[0]);
+        out.push(polygon[i]);
+        out.push(polygon[i + 1]);
+    }
+}
+
+// 2d coordinates of the points in the plane orthogonal to `normal`,
+// oriented so that the polygon winds counter clockwise
+fn project(positions: &[[f32; 3]], normal: [f32; 3]) -> Option<Vec<[f32; 2]>> {
+    let length = length(normal);
+    if length <= EPSILON {
+        return None;
+    }
+    let n = scale(normal, 1.0 / length);
+
+    // any vector orthogonal to n, built from its smallest component
+    let axis = if n[0].abs() < n[1].abs() && n[0].abs() < n[2].abs() {
+        [1.0, 0.0, 0.0]
+    } else if n[1].abs() < n[2].abs() {
+        [0.0, 1.0, 0.0]
+    } else {
+        [0.0, 0.0, 1.0]
+    };
+    let u = normalize(cross(axis, n));
+    // (u, v, n) is right-handed
+    let v = cross(n, u);
+
+    Some(positions.iter()
+        .map(|p| [dot(*p, u), dot(*p, v)])
+        .collect())
+}
+
+fn is_convex(points: &[[f32; 2]]) -> bool {
+    let len = points.len();
+    (0..len).all(|i| {
+        corner(points[(i + len - 1) % len], points[i], points[(i + 1) % len]) >= 0.0
+    })
+}
+
+fn ear_clip(polygon: &[u32], points: &[[f32; 2]], out: &mut Vec<u32>) {
+    // indices (in polygon) of the vertices not clipped yet
+    let mut remaining: Vec<usize> = (0..polygon.len()).collect();
+
+    while remaining.len() > 3 {
+        let len = remaining.len();
+
+        let ear = (0..len).find(|&i| {
+            is_ear(points, &remaining, (i + len - 1) % len, i, (i + 1) % len)
+        });
+
+        // self-intersecting or degenerate polygons may have no ear left:
+        // clip the least concave corner to make progress anyway
+        let ear = ear.unwrap_or_else(|| {
+            (0..len)
+                .max_by(|&a, &b| {
+                    let area = |i: usize| corner(
+                        points[remaining[(i + len - 1) % len]],
+                        points[remaining[i]],
+                        points[remaining[(i + 1) % len]]
+                    );
+                    area(a).total_cmp(&area(b))
+                })
+                .unwrap_or(0)
+        });
+
+        out.push(polygon[remaining[(ear + len - 1) % len]]);
+        out.push(polygon[remaining[ear]]);
+        out.push(polygon[remaining[(ear + 1) % len]]);
+        remaining.remove(ear);
+    }
+
+    out.push(polygon[remaining[0]]);
+    out.push(polygon[remaining[1]]);
+    out.push(polygon[remaining[2]]);
+}
+
+// a convex corner whose triangle contains no other vertex of the polygon
+fn is_ear(points: &[[f32; 2]], remaining: &[usize], prev: usize, current: usize, next: usize) -> bool {
+    let (a, b, c) = (points[remaining[prev]], points[remaining[current]], points[remaining[next]]);
+
+    if corner(a, b, c) <= EPSILON {
+        return false;
+    }
+
+    remaining.iter()
+        .enumerate()
+        .filter(|(i, _)| *i != prev && *i != current && *i != next)
+        .map(|(_, &p)| points[p])
+        // duplicated vertices (touching holes) don't block the ear
+        .filter(|p| *p != a && *p != b && *p != c)
+        .all(|p| !in_triangle(p, a, b, c))
+}
+
+// twice the signed area of the triangle abc, positive when counter clockwise
+fn corner(a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+// p inside or on the edges of the counter clockwise triangle abc
+fn in_triangle(p: [f32; 2], a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> bool {
+    corner(a, b, p) >= 0.0 && corner(b, c, p) >= 0.0 && corner(c, a, p) >= 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector::{newell_normal, triangle_normal};
+
+    // the triangles of a polygon numbered from `first`, as positions
+    fn triangles(positions: &[[f32; 3]], first: u32) -> Vec<[[f32; 3]; 3]> {
+        let polygon: Vec<u32> = (first..first + positions.len() as u32).collect();
+        let mut out = Vec::new();
+        triangulate(&polygon, positions, newell_normal(positions.iter().copied()), &mut out);
+        out.chunks_exact(3)
+            .map(|triangle| [0, 1, 2].map(|i| positions[(triangle[i] - first) as usize]))
+            .collect()
+    }
+
+    fn l_shape() -> Vec<[f32; 3]> {
+        vec![[0.0, 0.0, 0.0], [2.0, 0.0, 0.0], [2.0, 1.0, 0.0], [1.0, 1.0, 0.0], [1.0, 2.0, 0.0], [0.0, 2.0, 0.0]]
+    }
+
+    #[test]
+    fn concave_polygons_are_ear_clipped() {
+        let mut positions = l_shape();
+        for normal_z in [1.0, -1.0] {
+            let split = triangles(&positions, 10);
+            assert_eq!(split.len(), 4);
+            let mut area = 0.0;
+            for [a, b, c] in split {
+                // every triangle winds like the polygon, inside the l
+                let n = triangle_normal(a, b, c);
+                assert!(n[2] * normal_z > 0.0, "{:?}", [a, b, c]);
+                area += n[2].abs() / 2.0;
+                let center = [0, 1].map(|i| (a[i] + b[i] + c[i]) / 3.0);
+                assert!(center[0] < 1.0 || center[1] < 1.0, "{:?}", [a, b, c]);
+            }
+            assert_eq!(area, 3.0);
+            // the same l going clockwise
+            positions.reverse();
+        }
+    }
+
+    #[test]
+    fn non_planar_quads_are_split() {
+        // convex: fanned, both triangles facing the best-fit plane
+        let positions = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.2], [0.0, 1.0, 0.0]];
+        let normal = newell_normal(positions);
+        let split = triangles(&positions, 0);
+        assert_eq!(split.len(), 2);
+        for [a, b, c] in split {
+            assert!(dot(triangle_normal(a, b, c), normal) > 0.0);
+        }
+
+        // concave (a dart whose last corner points in): the only diagonal is 1 3
+        let positions = [[0.0, 0.0, 0.0], [2.0, 1.0, 0.1], [0.0, 2.0, 0.0], [0.5, 1.0, -0.1]];
+        let normal = newell_normal(positions);
+        let split = triangles(&positions, 0);
+        assert_eq!(split.len(), 2);
+        for triangle in split {
+            assert!(triangle.contains(&positions[1]) && triangle.contains(&positions[3]), "{:?}", triangle);
+            let [a, b, c] = triangle;
+            assert!(dot(triangle_normal(a, b, c), normal) > 0.0);
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+use crate::mesh::{Mesh, POSITION_OFFSET, TEX_COORD_OFFSET, TANGENT_OFFSET, VERTEX_SIZE};
+use crate::tangent;
+use crate::vector::{length, triangle_normal};
+use crate::weld::{self, WeldOptions};
+
+// texture coordinates for meshes that have none
+// ------------------------------------------------------------------------
+// positions are first scaled to [-1, 1] like the displayed model. vertices on a seam
+// (between two sides of the box, or where spherical and cylindrical coordinates wrap
+// around) are split so that each triangle gets continuous coordinates.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Projection {
+    // along an axis, the texture covers the model once
+    Planar(Axis),
+    // planar along the axis the triangle faces the most (triplanar)
+    Box,
+    // longitude and latitude around the center
+    Spherical,
+    // angle around the y axis and height
+    Cylindrical,
+}
+
+// the order in which the keyboard goes through them
+pub const PROJECTIONS: [Projection; 6] = [
+    Projection::Planar(Axis::X),
+    Projection::Planar(Axis::Y),
+    Projection::Planar(Axis::Z),
+    Projection::Box,
+    Projection::Spherical,
+    Projection::Cylindrical,
+];
+
+impl Projection {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Projection::Planar(Axis::X) => "planar x",
+            Projection::Planar(Axis::Y) => "planar y",
+            Projection::Planar(Axis::Z) => "planar z",
+            Projection::Box => "box",
+            Projection::Spherical => "spherical",
+            Projection::Cylindrical => "cylindrical",
+        }
+    }
+
+    // the next one in PROJECTIONS
+    pub fn next(&self) -> Projection {
+        let index = PROJECTIONS.iter().position(|projection| projection == self).unwrap_or(0);
+        PROJECTIONS[(index + 1) % PROJECTIONS.len()]
+    }
+}
+
+// replace the texture coordinates of the mesh. `bounds` is the bounding box used to scale
+// the positions, the one of the loaded model so that its simplified and subdivided
+// versions get the same coordinates.
+pub fn generate(mesh: &mut Mesh, projection: Projection, bounds: ([f32; 3], [f32; 3])) {
+    let (min, max) = bounds;
+    let center: [f32; 3] = [0, 1, 2].map(|i| (min[i] + max[i]) / 2.0);
+    let half_size = (0..3).map(|i| (max[i] - min[i]) / 2.0).fold(f32::MIN_POSITIVE, f32::max);
+    let normalized = |index: u32| -> [f32; 3] {
+        let p = index as usize * VERTEX_SIZE + POSITION_OFFSET;
+        [0, 1, 2].map(|i| (mesh.vertices[p + i] - center[i]) / half_size)
+    };
+
+    let count = mesh.vertices.len() / VERTEX_SIZE;
+    let mut vertices = mesh.vertices.to_vec();
+    // the first triangle using a vertex sets its coordinates, the others
+    // use a copy when theirs are different
+    let mut assigned: Vec<Option<[f32; 2]>> = vec![None; count];
+    let mut copies: HashMap<(u32, [u32; 2]), u32> = HashMap::new();
+    let mut set = |vertices: &mut Vec<f32>, index: u32, uv: [f32; 2]| -> u32 {
+        let i = index as usize;
+        match assigned[i] {
+            None => {
+                assigned[i] = Some(uv);
+                vertices[i * VERTEX_SIZE + TEX_COORD_OFFSET..i * VERTEX_SIZE + TEX_COORD_OFFSET + 2].copy_from_slice(&uv);
+                index
+            }
+            Some(current) if current == uv => index,
+            Some(_) => *copies.entry((index, uv.map(f32::to_bits))).or_insert_with(|| {
+                let mut vertex = vertices[i * VERTEX_SIZE..(i + 1) * VERTEX_SIZE].to_vec();
+                vertex[TEX_COORD_OFFSET..TEX_COORD_OFFSET + 2].copy_from_slice(&uv);
+                vertices.extend_from_slice(&vertex);
+                (vertices.len() / VERTEX_SIZE - 1) as u32
+            }),
+        }
+    };
+
+    let mut indices = Vec::with_capacity(mesh.indices.len());
+    for triangle in mesh.indices.chunks_exact(3) {
+        let positions = [0, 1, 2].map(|k| normalized(triangle[k]));
+        let mut uvs = match projection {
+            Projection::Planar(axis) => positions.map(|p| planar(p, axis)),
+            Projection::Box => {
+                let n = triangle_normal(positions[0], positions[1], positions[2]).map(f32::abs);
+                let axis = if n[0] >= n[1] && n[0] >= n[2] {
+                    Axis::X
+                } else if n[1] >= n[2] {
+                    Axis::Y
+                } else {
+                    Axis::Z
+                };
+                positions.map(|p| planar(p, axis))
+            }
+            Projection::Spherical => positions.map(|p| {
+                let length = length(p).max(f32::MIN_POSITIVE);
+                [longitude(p), 0.5 + (p[1] / length).clamp(-1.0, 1.0).asin() / PI]
+            }),
+            Projection::Cylindrical => positions.map(|p| [longitude(p), p[1] / 2.0 + 0.5]),
+        };
+        // a triangle across the line where the longitude wraps goes on past 1
+        if matches!(projection, Projection::Spherical | Projection::Cylindrical) {
+            let largest = uvs.iter().map(|uv| uv[0]).fold(0.0, f32::max);
+            for uv in &mut uvs {
+                if largest - uv[0] > 0.5 {
+                    uv[0] += 1.0;
+                }
+            }
+        }
+        for k in 0..3 {
+            indices.push(set(&mut vertices, triangle[k], uvs[k]));
+        }
+    }
+
+    // vertices of lines and points that no triangle uses get the coordinates of their position
+    for index in mesh.lines.iter().chain(&mesh.points) {
+        let i = *index as usize;
+        if assigned[i].is_none() {
+            let p = normalized(*index);
+            let uv = match projection {
+                Projection::Planar(axis) => planar(p, axis),
+                Projection::Box => planar(p, Axis::X),
+                Projection::Spherical | Projection::Cylindrical => [longitude(p), p[1] / 2.0 + 0.5],
+            };
+            vertices[i * VERTEX_SIZE + TEX_COORD_OFFSET..i * VERTEX_SIZE + TEX_COORD_OFFSET + 2].copy_from_slice(&uv);
+        }
+    }
+
+    // a polygon corner becomes the vertex of the same corner in the first of its triangles
+    let mut first_triangle = 0;
+    let mut polygon_start = 0;
+    for &size in &mesh.polygon_sizes {
+        let size = size as usize;
+        let triangles = first_triangle * 3..(first_triangle + size.saturating_sub(2)) * 3;
+        if triangles.end > indices.len() || polygon_start + size > mesh.polygons.len() {
+            break;
+        }
+        for corner in &mut mesh.polygons[polygon_start..polygon_start + size] {
+            if let Some(k) = triangles.clone().find(|&k| mesh.indices[k] == *corner) {
+                *corner = indices[k];
+            }
+        }
+        first_triangle += size.saturating_sub(2);
+        polygon_start += size;
+    }
+
+    mesh.vertices = vertices.into();
+    mesh.indices = indices.into();
+    mesh.has_tex_coords = true;
+    // vertices split by an earlier projection are merged back, tangents follow
+    // the new coordinates
+    for vertex in mesh.vertices.chunks_exact_mut(VERTEX_SIZE) {
+        vertex[TANGENT_OFFSET..TANGENT_OFFSET + 4].fill(0.0);
+    }
+    weld::weld(mesh, &WeldOptions::exact());
+    tangent::generate(mesh);
+}
+
+// the two other axes, seen from the positive side of the axis, in [0, 1]
+fn planar(p: [f32; 3], axis: Axis) -> [f32; 2] {
+    let (u, v) = match axis {
+        Axis::X => (p[2], p[1]),
+        Axis::Y => (p[0], p[2]),
+        Axis::Z => (p[0], p[1]),
+    };
+    [u / 2.0 + 0.5, v / 2.0 + 0.5]
+}
+
+// angle around the y axis, in [0, 1]
+fn longitude(p: [f32; 3]) -> f32 {
+    0.5 + p[0].atan2(p[2]) / (2.0 * PI)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOUNDS: ([f32; 3], [f32; 3]) = ([-1.0; 3], [1.0; 3]);
+
+    // a cube from -1 to 1 whose sides share their 8 vertices, vertex x + 2y + 4z
+    // at the corner of those coordinates (0 for -1, 1 for 1)
+    fn cube() -> Mesh {
+        let positions: Vec<[f32; 3]> = (0..8)
+            .map(|i| [i & 1, i >> 1 & 1, i >> 2 & 1].map(|bit| if bit == 1 { 1.0 } else { -1.0 }))
+            .collect();
+        let quads = [[0, 2, 3, 1], [4, 5, 7, 6], [0, 1, 5, 4], [2, 6, 7, 3], [0, 4, 6, 2], [1, 3, 7, 5]];
+        let indices: Vec<u32> = quads.iter().flat_map(|&[a, b, c, d]| [a, b, c, a, c, d]).collect();
+        Mesh::from_triangles(&positions, &indices)
+    }
+
+    // texture coordinates of the corners of each triangle
+    fn triangle_uvs(mesh: &Mesh) -> Vec<[[f32; 2]; 3]> {
+        mesh.indices.chunks_exact(3)
+            .map(|triangle| [0, 1, 2].map(|k| {
+                let t = triangle[k] as usize * VERTEX_SIZE + TEX_COORD_OFFSET;
+                [mesh.vertices[t], mesh.vertices[t + 1]]
+            }))
+            .collect()
+    }
+
+    // twice the signed area of a triangle of texture coordinates
+    fn area([a, b, c]: [[f32; 2]; 3]) -> f32 {
+        (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+    }
+
+    fn projected(projection: Projection) -> Mesh {
+        let mut mesh = cube();
+        generate(&mut mesh, projection, BOUNDS);
+        assert!(mesh.has_tex_coords);
+        assert_eq!(mesh.indices.len(), 36);
+        mesh
+    }
+
+    #[test]
+    fn planar_covers_the_texture_once() {
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            let mesh = projected(Projection::Planar(axis));
+            let uvs = triangle_uvs(&mesh);
+            for uv in uvs.iter().flatten() {
+                assert!(uv.iter().all(|&value| value == 0.0 || value == 1.0), "{:?}: {:?}", axis, uv);
+            }
+            // the two sides facing the axis get the whole texture, the others are edge on
+            let covering = uvs.iter().filter(|&&triangle| area(triangle).abs() == 1.0).count();
+            let flat = uvs.iter().filter(|&&triangle| area(triangle) == 0.0).count();
+            assert_eq!((covering, flat), (4, 8), "{:?}", axis);
+        }
+    }
+
+    #[test]
+    fn box_splits_the_corners_between_sides() {
+        let mesh = projected(Projection::Box);
+        // each side gets the whole texture: a corner has one vertex per side around it
+        for triangle in triangle_uvs(&mesh) {
+            assert_eq!(area(triangle).abs(), 1.0, "{:?}", triangle);
+        }
+        assert!(mesh.vertices.len() / VERTEX_SIZE > 8);
+    }
+
+    #[test]
+    fn spherical_and_cylindrical_wrap_around_the_seam() {
+        for projection in [Projection::Spherical, Projection::Cylindrical] {
+            let mesh = projected(projection);
+            let uvs = triangle_uvs(&mesh);
+            for triangle in &uvs {
+                // u goes past 1 rather than back to 0 across the seam
+                let us = triangle.map(|uv| uv[0]);
+                let (low, high) = (us.iter().copied().fold(f32::MAX, f32::min), us.iter().copied().fold(f32::MIN, f32::max));
+                assert!(low >= 0.0 && high < 1.5 && high - low <= 0.5 + 1e-6, "{:?}: {:?}", projection, triangle);
+                assert!(triangle.iter().all(|uv| (0.0..=1.0).contains(&uv[1])), "{:?}: {:?}", projection, triangle);
+            }
+            // the side on -z is across the seam (at u = 0 and 1), its corners are split
+            assert!(uvs.iter().flatten().any(|uv| uv[0] > 1.0), "{:?}", projection);
+            assert!(mesh.vertices.len() / VERTEX_SIZE > 8, "{:?}", projection);
+        }
+    }
+
+    #[test]
+    fn projections_go_round() {
+        let mut projection = PROJECTIONS[0];
+        for expected in PROJECTIONS.iter().cycle().skip(1).take(PROJECTIONS.len()) {
+            projection = projection.next();
+            assert_eq!(projection, *expected);
+        }
+    }
+}
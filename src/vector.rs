@@ -0,0 +1,93 @@
+use std::{iter, ops};
+
+// operations on 3d vectors stored as arrays
+// ------------------------------------------------------------------------
+// add, sub, scale, dot and cross work on f32 and f64 (simplify works in f64),
+// the others on f32.
+
+pub trait Scalar: Copy + ops::Add<Output = Self> + ops::Sub<Output = Self> + ops::Mul<Output = Self> {}
+
+impl Scalar for f32 {}
+
+impl Scalar for f64 {}
+
+pub fn add<T: Scalar>(a: [T; 3], b: [T; 3]) -> [T; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+pub fn sub<T: Scalar>(a: [T; 3], b: [T; 3]) -> [T; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub fn scale<T: Scalar>(a: [T; 3], factor: T) -> [T; 3] {
+    [a[0] * factor, a[1] * factor, a[2] * factor]
+}
+
+pub fn dot<T: Scalar>(a: [T; 3], b: [T; 3]) -> T {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub fn cross<T: Scalar>(a: [T; 3], b: [T; 3]) -> [T; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+pub fn length(v: [f32; 3]) -> f32 {
+    dot(v, v).sqrt()
+}
+
+// unit vector in the direction of v, a null vector stays null
+pub fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = length(v);
+    if length > 0.0 {
+        return scale(v, 1.0 / length);
+    }
+    v
+}
+
+// normal of the triangle abc, its length is twice the area
+pub fn triangle_normal(a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> [f32; 3] {
+    cross(sub(b, a), sub(c, a))
+}
+
+// normal of the best-fit plane of a polygon (newell's method),
+// its length is twice the area
+pub fn newell_normal(points: impl IntoIterator<Item = [f32; 3]>) -> [f32; 3] {
+    let mut points = points.into_iter();
+    let mut normal = [0.0; 3];
+    let Some(first) = points.next() else {
+        return normal;
+    };
+    let mut c = first;
+    for n in points.chain(iter::once(first)) {
+        normal[0] += (c[1] - n[1]) * (c[2] + n[2]);
+        normal[1] += (c[2] - n[2]) * (c[0] + n[0]);
+        normal[2] += (c[0] - n[0]) * (c[1] + n[1]);
+        c = n;
+    }
+    normal
+}
+
+// any unit vector orthogonal to v, built from its smallest component
+pub fn orthogonal(v: [f32; 3]) -> [f32; 3] {
+    let axis = if v[0].abs() <= v[1].abs() && v[0].abs() <= v[2].abs() {
+        [1.0, 0.0, 0.0]
+    } else if v[1].abs() <= v[2].abs() {
+        [0.0, 1.0, 0.0]
+    } else {
+        [0.0, 0.0, 1.0]
+    };
+    let t = sub(axis, scale(v, dot(v, axis)));
+    if dot(t, t) > 0.0 { normalize(t) } else { axis }
+}
+
+// vertex colors are usually in [0, 1], some files (or exporters) write them in [0, 255]
+pub fn normalize_color(color: [f32; 3]) -> [f32; 3] {
+    if color.iter().any(|&c| c > 1.0) {
+        return color.map(|c| c / 255.0);
+    }
+    color
+}
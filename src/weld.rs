@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+
+use crate::mesh::{Mesh, Primitive, POSITION_OFFSET, NORMAL_OFFSET, VERTEX_SIZE};
+use crate::vector::{add, length, normalize, triangle_normal};
+
+// merge duplicated vertices
+// ------------------------------------------------------------------------
+
+#[derive(Clone, Copy)]
+pub struct WeldOptions {
+    // largest difference between two merged values, 0.0 only merges identical vertices
+    pub epsilon: f32,
+    // merge vertices that only share their position (texture coordinates and colors
+    // of the first one are kept), and give them smooth normals.
+    // otherwise every attribute has to match
+    pub positions_only: bool,
+}
+
+impl WeldOptions {
+    // identical vertices only: the mesh looks the same, with a smaller vertex buffer
+    pub fn exact() -> Self {
+        Self { epsilon: 0.0, positions_only: false }
+    }
+}
+
+// hash key of a value, the same for equal values:
+// +0.0 turns -0.0 into 0.0 so that both give the same key
+pub fn value_key(value: f32) -> u32 {
+    (value + 0.0).to_bits()
+}
+
+// hash key of a position, see value_key
+pub fn position_key(position: [f32; 3]) -> [u32; 3] {
+    position.map(value_key)
+}
+
+// merge the vertices, remap the triangles, lines and points to the vertices that are kept
+// and drop the ones that collapsed. returns the number of merged vertices.
+pub fn weld(mesh: &mut Mesh, options: &WeldOptions) -> usize {
+    let count = mesh.vertices.len() / VERTEX_SIZE;
+    // attributes compared between vertices
+    let size = if options.positions_only { 3 } else { VERTEX_SIZE };
+    let key = |i: usize| &mesh.vertices[i * VERTEX_SIZE + POSITION_OFFSET..i * VERTEX_SIZE + POSITION_OFFSET + size];
+
+    // new index of every vertex
+    let mut remap: Vec<u32> = Vec::with_capacity(count);
+    // first vertex of each new index
+    let mut kept: Vec<usize> = Vec::new();
+
+    if options.epsilon <= 0.0 {
+        // attributes that are not compared stay zero in the key
+        let mut ids: HashMap<[u32; VERTEX_SIZE], u32> = HashMap::new();
+        for i in 0..count {
+            let mut bits = [0; VERTEX_SIZE];
+            for (bits, &value) in bits.iter_mut().zip(key(i)) {
+                *bits = value_key(value);
+            }
+            let id = *ids.entry(bits).or_insert_with(|| {
+                kept.push(i);
+                (kept.len() - 1) as u32
+            });
+            remap.push(id);
+        }
+    } else {
+        // grid of cells as big as epsilon: a match is in the cell of the position or around it
+        let cell = |i: usize| -> [i64; 3] {
+            let p = &key(i)[..3];
+            [0, 1, 2].map(|axis| (p[axis] / options.epsilon).floor() as i64)
+        };
+        let mut grid: HashMap<[i64; 3], Vec<u32>> = HashMap::new();
+        for i in 0..count {
+            let [x, y, z] = cell(i);
+            let found = (-1..=1)
+                .flat_map(|dx| (-1..=1).flat_map(move |dy| (-1..=1).map(move |dz| [x + dx, y + dy, z + dz])))
+                .filter_map(|neighbour| grid.get(&neighbour))
+                .flatten()
+                .find(|&&id| key(kept[id as usize]).iter().zip(key(i)).all(|(a, b)| (a - b).abs() <= options.epsilon))
+                .copied();
+            let id = match found {
+                Some(id) => id,
+                None => {
+                    kept.push(i);
+                    let id = (kept.len() - 1) as u32;
+                    grid.entry([x, y, z]).or_default().push(id);
+                    id
+                }
+            };
+            remap.push(id);
+        }
+    }
+
+    let merged = count - kept.len();
+    if merged == 0 {
+        return 0;
+    }
+
+    mesh.vertices = kept.iter()
+        .flat_map(|&i| mesh.vertices[i * VERTEX_SIZE..(i + 1) * VERTEX_SIZE].iter().copied())
+        .collect();
+    remap_elements(mesh, &remap);
+    if options.positions_only {
+        smooth_normals(mesh);
+    }
+
+    merged
+}
+
+// renumber the elements of every sub-mesh, without the triangles and segments
+// that have lost their area or length. the polygons are forgotten when a triangle is dropped
+fn remap_elements(mesh: &mut Mesh, remap: &[u32]) {
+    let (mut indices, mut lines, mut points) = (Vec::new(), Vec::new(), Vec::new());
+
+    for sub_mesh in &mut mesh.sub_meshes {
+        let (source, target, size) = match sub_mesh.primitive {
+            Primitive::Triangles => (&mesh.indices[..], &mut indices, 3),
+            Primitive::Lines => (&mesh.lines[..], &mut lines, 2),
+            Primitive::Points => (&mesh.points[..], &mut points, 1),
+        };
+        let start = target.len();
+        for element in source[sub_mesh.start..sub_mesh.start + sub_mesh.count].chunks_exact(size) {
+            let element: Vec<u32> = element.iter().map(|&index| remap[index as usize]).collect();
+            let collapsed = (1..size).any(|i| element[i] == element[i - 1]) || (size == 3 && element[0] == element[2]);
+            if !collapsed {
+                target.extend_from_slice(&element);
+            }
+        }
+        sub_mesh.start = start;
+        sub_mesh.count = target.len() - start;
+    }
+
+    mesh.sub_meshes.retain(|sub_mesh| sub_mesh.count > 0);
+    if indices.len() == mesh.indices.len() {
+        for index in &mut mesh.polygons {
+            *index = remap[*index as usize];
+        }
+    } else {
+        mesh.polygons.clear();
+        mesh.polygon_sizes.clear();
+    }
+    mesh.indices = indices.into();
+    mesh.lines = lines;
+    mesh.points = points;
+}
+
+// normals of the triangles around each vertex, weighted by their area
+fn smooth_normals(mesh: &mut Mesh) {
+    let mut normals = vec![[0.0f32; 3]; mesh.vertices.len() / VERTEX_SIZE];
+    let position = |index: u32| -> [f32; 3] {
+        let p = index as usize * VERTEX_SIZE + POSITION_OFFSET;
+        [mesh.vertices[p], mesh.vertices[p + 1], mesh.vertices[p + 2]]
+    };
+
+    for triangle in mesh.indices.chunks_exact(3) {
+        let n = triangle_normal(position(triangle[0]), position(triangle[1]), position(triangle[2]));
+        for &index in triangle {
+            normals[index as usize] = add(normals[index as usize], n);
+        }
+    }
+
+    for (vertex, n) in mesh.vertices.chunks_exact_mut(VERTEX_SIZE).zip(normals) {
+        // vertices of lines and points keep their (zero) normal
+        if length(n) > 0.0 {
+            vertex[NORMAL_OFFSET..NORMAL_OFFSET + 3].copy_from_slice(&normalize(n));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a mesh whose triangles each have their own 3 vertices
+    fn triangle_soup(triangles: &[[[f32; 3]; 3]]) -> Mesh {
+        let positions: Vec<[f32; 3]> = triangles.iter().flatten().copied().collect();
+        let indices: Vec<u32> = (0..positions.len() as u32).collect();
+        Mesh::from_triangles(&positions, &indices)
+    }
+
+    fn set_normal(mesh: &mut Mesh, vertex: usize, normal: [f32; 3]) {
+        let n = vertex * VERTEX_SIZE + NORMAL_OFFSET;
+        mesh.vertices[n..n + 3].copy_from_slice(&normal);
+    }
+
+    // two triangles of a square, sharing their diagonal, moved by `offset` in the second one
+    fn square(offset: f32) -> Mesh {
+        triangle_soup(&[
+            [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0]],
+            [[offset, offset, 0.0], [1.0 + offset, 1.0, 0.0], [0.0, 1.0, 0.0]],
+        ])
+    }
+
+    #[test]
+    fn exact_weld_merges_identical_vertices() {
+        let mut mesh = square(0.0);
+        assert_eq!(weld(&mut mesh, &WeldOptions::exact()), 2);
+        assert_eq!(mesh.vertices.len() / VERTEX_SIZE, 4);
+        assert_eq!(&mesh.indices[..], &[0, 1, 2, 0, 2, 3]);
+
+        // -0.0 is the same position as 0.0
+        let mut mesh = square(-0.0);
+        assert_eq!(weld(&mut mesh, &WeldOptions::exact()), 2);
+    }
+
+    #[test]
+    fn exact_weld_compares_every_attribute() {
+        let mut mesh = square(0.0);
+        set_normal(&mut mesh, 0, [0.0, 0.0, 1.0]);
+        assert_eq!(weld(&mut mesh, &WeldOptions::exact()), 1);
+
+        // unless only positions are compared, then normals are smoothed
+        let mut mesh = square(0.0);
+        set_normal(&mut mesh, 0, [0.0, 0.0, 1.0]);
+        let options = WeldOptions { epsilon: 0.0, positions_only: true };
+        assert_eq!(weld(&mut mesh, &options), 2);
+        for vertex in mesh.vertices.chunks_exact(VERTEX_SIZE) {
+            assert_eq!(vertex[NORMAL_OFFSET..NORMAL_OFFSET + 3], [0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn epsilon_weld_merges_close_vertices() {
+        let mut mesh = square(1e-4);
+        assert_eq!(weld(&mut mesh, &WeldOptions::exact()), 0);
+        let mut mesh = square(1e-4);
+        assert_eq!(weld(&mut mesh, &WeldOptions { epsilon: 1e-3, positions_only: false }), 2);
+        let mut mesh = square(1e-2);
+        assert_eq!(weld(&mut mesh, &WeldOptions { epsilon: 1e-3, positions_only: false }), 0);
+    }
+
+    #[test]
+    fn epsilon_weld_looks_in_the_neighbour_cells() {
+        // 0.099 and 0.101, -0.001 and 0.001 are on both sides of a cell boundary, on every axis
+        let corners = [[0.099, 0.099, 0.099], [0.101, 0.101, 0.101], [-0.001, -0.001, -0.001], [0.001, 0.001, 0.001]];
+        let mut mesh = triangle_soup(&[
+            [corners[0], [1.0, 0.0, 0.0], corners[2]],
+            [corners[1], corners[3], [0.0, 1.0, 0.0]],
+        ]);
+        let options = WeldOptions { epsilon: 0.01, positions_only: true };
+        assert_eq!(weld(&mut mesh, &options), 2);
+        assert_eq!(&mesh.indices[..], &[0, 1, 2, 0, 2, 3]);
+    }
+}
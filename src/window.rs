@@ -5,9 +5,24 @@ use std::sync::mpsc::Receiver;
 use glfw::{Key, Action, MouseButton};
 
 use crate::matrix::Matrix4;
+use crate::model::Model;
 
 use self::glfw::Context;
 
+// actions asked for with the keyboard (see process_events),
+// the render loop carries them out and resets them
+#[derive(Default)]
+pub struct Requests {
+    // save the mesh as it is displayed
+    pub export: bool,
+    // pick the next level of detail
+    pub lod_change: bool,
+    // 1 or -1, change the subdivision level
+    pub subdivision_step: i32,
+    // switch to the next texture projection
+    pub uv_change: bool,
+}
+
 
 // TODO: manage error
 pub fn create_window(width: u32, height: u32) -> (
@@ -67,7 +82,8 @@ pub fn process_events(
     delta_mix: &mut f32,
     view: &mut Matrix4,
     right_mouse_pressed: &mut bool,
-
+    scene: &mut Model,
+    requests: &mut Requests,
 ) {
     for (_, event) in glfw::flush_messages(events) {
         
@@ -118,6 +134,47 @@ pub fn process_events(
                 *delta_mix = - *delta_mix;
             }
 
+            // objects and groups
+            glfw::WindowEvent::Key(Key::N, _, Action::Press, _) => {
+                scene.select_next();
+            }
+
+            glfw::WindowEvent::Key(Key::P, _, Action::Press, _) => {
+                scene.select_previous();
+            }
+
+            glfw::WindowEvent::Key(Key::H, _, Action::Press, _) => {
+                scene.toggle_selected();
+            }
+
+            glfw::WindowEvent::Key(Key::L, _, Action::Press, _) => {
+                scene.list();
+            }
+
+            // save the (re-centered) mesh
+            glfw::WindowEvent::Key(Key::E, _, Action::Press, _) => {
+                requests.export = true;
+            }
+
+            // force a level of detail, or go back to the automatic one
+            glfw::WindowEvent::Key(Key::O, _, Action::Press, _) => {
+                requests.lod_change = true;
+            }
+
+            // subdivide the mesh once more, or once less
+            glfw::WindowEvent::Key(Key::Equal | Key::KpAdd, _, Action::Press, _) => {
+                requests.subdivision_step = 1;
+            }
+
+            glfw::WindowEvent::Key(Key::Minus | Key::KpSubtract, _, Action::Press, _) => {
+                requests.subdivision_step = -1;
+            }
+
+            // next projection of the texture (models without texture coordinates)
+            glfw::WindowEvent::Key(Key::U, _, Action::Press, _) => {
+                requests.uv_change = true;
+            }
+
             glfw::WindowEvent::Key(Key::Escape, _, Action::Press, _) => {
                 window.set_should_close(true)
             }
@@ -0,0 +1,368 @@
+use std::{path::Path, io, fs};
+
+use serde_json::{json, Value};
+
+use crate::mesh::{Mesh, Material, Primitive, TextureImage, POSITION_OFFSET, TEX_COORD_OFFSET, NORMAL_OFFSET, COLOR_OFFSET, VERTEX_SIZE};
+use crate::write_mtl;
+
+// binary container: header, then a json chunk and a binary chunk
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const GLB_VERSION: u32 = 2;
+const CHUNK_JSON: u32 = 0x4E4F_534A;
+const CHUNK_BIN: u32 = 0x004E_4942;
+
+// accessor component types
+const UNSIGNED_INT: u32 = 5125;
+const FLOAT: u32 = 5126;
+
+// buffer view targets
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+// primitive modes
+const POINTS: u32 = 0;
+const LINES: u32 = 1;
+const TRIANGLES: u32 = 4;
+
+// binary buffer and the json that describes it
+struct Document {
+    buffer: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>,
+}
+
+// write the mesh as a .gltf file with its buffer in a .bin file next to it,
+// or as a single .glb file when the extension is "glb".
+// every object becomes a root node, its groups are its children, each with one gltf mesh
+// holding a primitive per sub-mesh. the diffuse color and texture of the materials
+// become their base color.
+pub fn save_model(mesh: &Mesh, path: &Path) -> io::Result<()> {
+    let binary = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("glb"));
+    let mut document = Document { buffer: Vec::new(), buffer_views: Vec::new(), accessors: Vec::new() };
+
+    // a gltf mesh per node, with the primitives of its sub-meshes
+    let mut primitives: Vec<Vec<Value>> = vec![Vec::new(); mesh.nodes.len()];
+    for sub_mesh in &mesh.sub_meshes {
+        let (list, mode) = match sub_mesh.primitive {
+            Primitive::Triangles => (&mesh.indices[..], TRIANGLES),
+            Primitive::Lines => (&mesh.lines[..], LINES),
+            Primitive::Points => (&mesh.points[..], POINTS),
+        };
+        if sub_mesh.count == 0 {
+            continue;
+        }
+        let (attributes, indices) = document.add_primitive(mesh, &list[sub_mesh.start..sub_mesh.start + sub_mesh.count]);
+        let mut primitive = json!({ "attributes": attributes, "indices": indices, "mode": mode });
+        if let Some(material) = sub_mesh.material {
+            primitive["material"] = json!(material);
+        }
+        primitives[sub_mesh.node].push(primitive);
+    }
+
+    let mut meshes = Vec::new();
+    let mut nodes = Vec::new();
+    for (node, primitives) in mesh.nodes.iter().zip(primitives) {
+        let mut gltf_node = json!({ "name": node.name });
+        if !primitives.is_empty() {
+            gltf_node["mesh"] = json!(meshes.len());
+            meshes.push(json!({ "name": node.name, "primitives": primitives }));
+        }
+        nodes.push(gltf_node);
+    }
+    // groups are children of their object, nodes without a parent are in the scene
+    let mut roots = Vec::new();
+    for (index, node) in mesh.nodes.iter().enumerate() {
+        match node.parent {
+            Some(parent) => match nodes[parent]["children"].as_array_mut() {
+                Some(children) => children.push(json!(index)),
+                None => nodes[parent]["children"] = json!([index]),
+            },
+            None => roots.push(index),
+        }
+    }
+
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    let (materials, textures, images) = materials(&mesh.materials, directory, &mut document);
+
+    let mut buffer = json!({ "byteLength": document.buffer.len() });
+    if !binary {
+        let bin = path.with_extension("bin");
+        if let Some(name) = bin.file_name() {
+            buffer["uri"] = json!(percent_encode(&name.to_string_lossy()));
+        }
+        fs::write(&bin, &document.buffer)?;
+    }
+
+    let mut gltf = json!({
+        "asset": { "version": "2.0", "generator": "scop" },
+        "scene": 0,
+        "scenes": [{ "nodes": roots }],
+        "nodes": nodes,
+        "meshes": meshes,
+        "buffers": [buffer],
+        "bufferViews": document.buffer_views,
+        "accessors": document.accessors,
+    });
+    for (key, values) in [("materials", materials), ("textures", textures), ("images", images)] {
+        if !values.is_empty() {
+            gltf[key] = json!(values);
+        }
+    }
+
+    let json = serde_json::to_vec(&gltf)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+    if binary {
+        fs::write(path, glb(json, document.buffer))
+    } else {
+        fs::write(path, json)
+    }
+}
+
+impl Document {
+    // the vertices used by the elements of a sub-mesh, and the elements renumbered,
+    // so that a primitive only holds its own vertices
+    fn add_primitive(&mut self, mesh: &Mesh, elements: &[u32]) -> (Value, usize) {
+        let mut used: Vec<u32> = elements.to_vec();
+        used.sort_unstable();
+        used.dedup();
+        let indices: Vec<u32> = elements.iter()
+            .map(|index| used.binary_search(index).unwrap_or(0) as u32)
+            .collect();
+
+        let vertices: Vec<&[f32]> = used.iter()
+            .map(|&index| &mesh.vertices[index as usize * VERTEX_SIZE..(index as usize + 1) * VERTEX_SIZE])
+            .collect();
+        let attribute = |offset: usize, size: usize| -> Vec<f32> {
+            vertices.iter()
+                .flat_map(|vertex| vertex[offset..offset + size].iter().copied())
+                .collect()
+        };
+        let mut attributes = json!({});
+
+        let positions = attribute(POSITION_OFFSET, 3);
+        let accessor = self.add_floats(&positions, "VEC3", vertices.len());
+        // gltf requires the bounds of the positions
+        let (min, max) = bounds(&positions);
+        self.accessors[accessor]["min"] = json!(min);
+        self.accessors[accessor]["max"] = json!(max);
+        attributes["POSITION"] = json!(accessor);
+
+        let normals = attribute(NORMAL_OFFSET, 3);
+        attributes["NORMAL"] = json!(self.add_floats(&normals, "VEC3", vertices.len()));
+
+        if mesh.has_tex_coords {
+            // gltf images start at the top, ours at the bottom
+            let mut tex_coords = attribute(TEX_COORD_OFFSET, 2);
+            for v in tex_coords.iter_mut().skip(1).step_by(2) {
+                *v = 1.0 - *v;
+            }
+            attributes["TEXCOORD_0"] = json!(self.add_floats(&tex_coords, "VEC2", vertices.len()));
+        }
+        if mesh.has_colors {
+            let colors = attribute(COLOR_OFFSET, 3);
+            attributes["COLOR_0"] = json!(self.add_floats(&colors, "VEC3", vertices.len()));
+        }
+
+        (attributes, self.add_indices(&indices))
+    }
+
+    // append data to the buffer, 4 byte aligned, and return its buffer view
+    // (images have no target)
+    fn add_view(&mut self, bytes: &[u8], target: Option<u32>) -> usize {
+        self.buffer.resize(self.buffer.len().div_ceil(4) * 4, 0);
+        let mut view = json!({
+            "buffer": 0,
+            "byteOffset": self.buffer.len(),
+            "byteLength": bytes.len(),
+        });
+        if let Some(target) = target {
+            view["target"] = json!(target);
+        }
+        self.buffer_views.push(view);
+        self.buffer.extend_from_slice(bytes);
+        self.buffer_views.len() - 1
+    }
+
+    fn add_floats(&mut self, values: &[f32], kind: &str, count: usize) -> usize {
+        let bytes: Vec<u8> = values.iter().flat_map(|value| value.to_le_bytes()).collect();
+        let view = self.add_view(&bytes, Some(ARRAY_BUFFER));
+        self.accessors.push(json!({
+            "bufferView": view,
+            "componentType": FLOAT,
+            "count": count,
+            "type": kind,
+        }));
+        self.accessors.len() - 1
+    }
+
+    fn add_indices(&mut self, indices: &[u32]) -> usize {
+        let bytes: Vec<u8> = indices.iter().flat_map(|index| index.to_le_bytes()).collect();
+        let view = self.add_view(&bytes, Some(ELEMENT_ARRAY_BUFFER));
+        self.accessors.push(json!({
+            "bufferView": view,
+            "componentType": UNSIGNED_INT,
+            "count": indices.len(),
+            "type": "SCALAR",
+        }));
+        self.accessors.len() - 1
+    }
+}
+
+// materials, textures and images. textures keep pointing to their file,
+// relative to the model. embedded images are stored in the buffer.
+fn materials<'a>(
+    materials: &'a [Material],
+    directory: &Path,
+    document: &mut Document
+) -> (Vec<Value>, Vec<Value>, Vec<Value>) {
+    let mut gltf_materials = Vec::new();
+    let mut textures = Vec::new();
+    let mut images: Vec<Value> = Vec::new();
+    // each image is written once, even when several materials use it
+    let mut written: Vec<&TextureImage> = Vec::new();
+
+    let mut texture = |map: &'a TextureImage| -> usize {
+        let image = written.iter().position(|&image| image == map).unwrap_or_else(|| {
+            images.push(match map {
+                TextureImage::File(path) => {
+                    let path = write_mtl::relative_path(directory, path);
+                    json!({ "uri": percent_encode(&path.to_string_lossy()) })
+                }
+                TextureImage::Embedded(data) => {
+                    let mime_type = if map.extension() == "jpg" { "image/jpeg" } else { "image/png" };
+                    json!({ "bufferView": document.add_view(data, None), "mimeType": mime_type })
+                }
+            });
+            written.push(map);
+            images.len() - 1
+        });
+        textures.push(json!({ "source": image }));
+        textures.len() - 1
+    };
+
+    for material in materials {
+        let [r, g, b] = material.diffuse;
+        let mut pbr = json!({
+            "baseColorFactor": [r, g, b, material.dissolve],
+            "metallicFactor": 0.0,
+            "roughnessFactor": 1.0,
+        });
+        if let Some(map) = &material.diffuse_map {
+            pbr["baseColorTexture"] = json!({ "index": texture(map) });
+        }
+        let mut gltf_material = json!({ "name": material.name, "pbrMetallicRoughness": pbr });
+        if material.dissolve < 1.0 {
+            gltf_material["alphaMode"] = json!("BLEND");
+        }
+        if let Some(map) = &material.bump_map {
+            gltf_material["normalTexture"] = json!({ "index": texture(map) });
+        }
+        gltf_materials.push(gltf_material);
+    }
+
+    (gltf_materials, textures, images)
+}
+
+// header, json chunk (padded with spaces) and binary chunk (padded with zeros)
+fn glb(mut json: Vec<u8>, mut binary: Vec<u8>) -> Vec<u8> {
+    json.resize(json.len().div_ceil(4) * 4, b' ');
+    binary.resize(binary.len().div_ceil(4) * 4, 0);
+
+    let length = 12 + 8 + json.len() + 8 + binary.len();
+    let mut bytes = Vec::with_capacity(length);
+    bytes.extend_from_slice(GLB_MAGIC);
+    bytes.extend_from_slice(&GLB_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(length as u32).to_le_bytes());
+    bytes.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&CHUNK_JSON.to_le_bytes());
+    bytes.extend_from_slice(&json);
+    bytes.extend_from_slice(&(binary.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&CHUNK_BIN.to_le_bytes());
+    bytes.extend_from_slice(&binary);
+    bytes
+}
+
+// smallest and largest x, y, z
+fn bounds(positions: &[f32]) -> ([f32; 3], [f32; 3]) {
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for position in positions.chunks_exact(3) {
+        for i in 0..3 {
+            min[i] = min[i].min(position[i]);
+            max[i] = max[i].max(position[i]);
+        }
+    }
+    (min, max)
+}
+
+// uris can't hold spaces and a few other characters
+fn percent_encode(path: &str) -> String {
+    let mut uri = String::new();
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_gltf, parse_obj};
+
+    // position, texture coordinates, normal and color of the corners of each triangle,
+    // rounded (texture coordinates are flipped twice) and sorted: the primitives may be
+    // loaded in another order than the sub-meshes were written
+    fn triangles(mesh: &Mesh) -> Vec<Vec<i64>> {
+        let attributes = [
+            (POSITION_OFFSET, 3),
+            (TEX_COORD_OFFSET, if mesh.has_tex_coords { 2 } else { 0 }),
+            (NORMAL_OFFSET, 3),
+            (COLOR_OFFSET, if mesh.has_colors { 3 } else { 0 }),
+        ];
+        let mut triangles: Vec<Vec<i64>> = mesh.indices.chunks_exact(3)
+            .map(|triangle| triangle.iter()
+                .flat_map(|&index| {
+                    let vertex = &mesh.vertices[index as usize * VERTEX_SIZE..(index as usize + 1) * VERTEX_SIZE];
+                    attributes.iter().flat_map(move |&(offset, size)| vertex[offset..offset + size].iter().map(|value| (value * 1e5).round() as i64))
+                })
+                .collect())
+            .collect();
+        triangles.sort();
+        triangles
+    }
+
+    fn node_names(mesh: &Mesh) -> Vec<String> {
+        let mut names: Vec<String> = mesh.nodes.iter().map(|node| node.name.clone()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn saved_models_load_back_the_same() {
+        for name in ["redcube/cube.obj", "42/42.obj", "fixtures/colors.obj"] {
+            let mesh = parse_obj::load_model(Path::new(&format!("resources/objects/{}", name))).unwrap();
+            for extension in ["gltf", "glb"] {
+                let directory = std::env::temp_dir().join(format!("scop-test-{}-{}-{}", std::process::id(), name.replace('/', "-"), extension));
+                fs::create_dir_all(&directory).unwrap();
+                let path = directory.join(format!("model.{}", extension));
+                save_model(&mesh, &path).unwrap();
+                let reloaded = parse_gltf::load_model(&path).unwrap();
+                let files = fs::read_dir(&directory).unwrap().count();
+                fs::remove_dir_all(directory).unwrap();
+
+                // the buffer is in a .bin next to the .gltf, inside the .glb
+                assert_eq!(files, if extension == "gltf" { 2 } else { 1 }, "{}", name);
+                assert_eq!(reloaded.has_tex_coords, mesh.has_tex_coords, "{}", name);
+                assert_eq!(reloaded.has_colors, mesh.has_colors, "{}", name);
+                assert!(triangles(&reloaded) == triangles(&mesh), "{} ({}): triangles differ", name, extension);
+                assert_eq!(node_names(&reloaded), node_names(&mesh), "{}", name);
+                let diffuse = |mesh: &Mesh| mesh.materials.iter().map(|material| material.diffuse).collect::<Vec<_>>();
+                assert_eq!(diffuse(&reloaded), diffuse(&mesh), "{}", name);
+            }
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::{path::{Path, PathBuf}, io::{self, Write, BufWriter}, fs::{self, File}};
+
+use crate::mesh::{Material, TextureImage};
+
+// write the materials as a .mtl file
+pub fn save_materials(materials: &[Material], path: &Path) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+
+    writeln!(out, "# exported by scop")?;
+
+    for (index, material) in materials.iter().enumerate() {
+        writeln!(out)?;
+        writeln!(out, "newmtl {}", material.name)?;
+        write_color(&mut out, "Ka", material.ambient)?;
+        write_color(&mut out, "Kd", material.diffuse)?;
+        write_color(&mut out, "Ks", material.specular)?;
+        writeln!(out, "Ns {}", material.shininess)?;
+        writeln!(out, "d {}", material.dissolve)?;
+        writeln!(out, "illum {}", material.illum)?;
+        let maps = [
+            ("map_Kd", &material.diffuse_map),
+            ("map_Bump", &material.bump_map),
+            ("map_d", &material.dissolve_map),
+        ];
+        for (keyword, map) in maps {
+            if let Some(map) = map {
+                let file = map_file(map, path, &format!("{}_{}", index, keyword))?;
+                writeln!(out, "{} {}", keyword, file.display())?;
+            }
+        }
+    }
+
+    out.flush()
+}
+
+fn write_color(out: &mut impl Write, keyword: &str, color: [f32; 3]) -> io::Result<()> {
+    writeln!(out, "{} {} {} {}", keyword, color[0], color[1], color[2])
+}
+
+// textures are written relative to the .mtl, so that it can be saved in another
+// directory than the original one and still be moved with its textures.
+// embedded images are written next to the .mtl, as "<mtl name>_<material>_<map>.png"
+fn map_file(map: &TextureImage, path: &Path, name: &str) -> io::Result<PathBuf> {
+    match map {
+        TextureImage::File(map) => Ok(relative_path(path.parent().unwrap_or_else(|| Path::new("")), map)),
+        TextureImage::Embedded(data) => {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let file = PathBuf::from(format!("{}_{}.{}", stem, name, map.extension()));
+            fs::write(path.with_file_name(&file), data)?;
+            Ok(file)
+        }
+    }
+}
+
+// path of `target` from `directory`, going up with ".." where they differ.
+// a target on another drive stays absolute
+pub fn relative_path(directory: &Path, target: &Path) -> PathBuf {
+    let absolute = |path: &Path| {
+        let path = if path.as_os_str().is_empty() { Path::new(".") } else { path };
+        fs::canonicalize(path)
+            .or_else(|_| std::path::absolute(path))
+            .unwrap_or_else(|_| path.to_path_buf())
+    };
+    let (directory, target) = (absolute(directory), absolute(target));
+
+    let common = directory.components()
+        .zip(target.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common == 0 {
+        return target;
+    }
+    let mut relative = PathBuf::new();
+    for _ in directory.components().skip(common) {
+        relative.push("..");
+    }
+    relative.extend(target.components().skip(common));
+    relative
+}
@@ -0,0 +1,229 @@
+use std::{path::Path, io::{self, Write, BufWriter}, fs::File};
+
+use crate::mesh::{Mesh, Primitive, NodeKind, POSITION_OFFSET, TEX_COORD_OFFSET, NORMAL_OFFSET, COLOR_OFFSET, VERTEX_SIZE};
+use crate::write_mtl;
+
+// "p" lines are cut after this many points
+const POINTS_PER_LINE: usize = 16;
+
+// write the mesh as a .obj file, with its materials in a .mtl file next to it.
+// every vertex of the vertex buffer becomes one v/vt/vn triplet,
+// so a face corner uses the same index for all three.
+pub fn save_model(mesh: &Mesh, path: &Path) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+
+    writeln!(out, "# exported by scop")?;
+
+    if !mesh.materials.is_empty() {
+        let library = path.with_extension("mtl");
+        write_mtl::save_materials(&mesh.materials, &library)?;
+        if let Some(name) = library.file_name() {
+            writeln!(out, "mtllib {}", name.to_string_lossy())?;
+        }
+    }
+
+    // vertex attributes
+    for vertex in mesh.vertices.chunks_exact(VERTEX_SIZE) {
+        let p = &vertex[POSITION_OFFSET..POSITION_OFFSET + 3];
+        if mesh.has_colors {
+            let c = &vertex[COLOR_OFFSET..COLOR_OFFSET + 3];
+            writeln!(out, "v {} {} {} {} {} {}", p[0], p[1], p[2], c[0], c[1], c[2])?;
+        } else {
+            writeln!(out, "v {} {} {}", p[0], p[1], p[2])?;
+        }
+    }
+    if mesh.has_tex_coords {
+        for vertex in mesh.vertices.chunks_exact(VERTEX_SIZE) {
+            let t = &vertex[TEX_COORD_OFFSET..TEX_COORD_OFFSET + 2];
+            writeln!(out, "vt {} {}", t[0], t[1])?;
+        }
+    }
+    for vertex in mesh.vertices.chunks_exact(VERTEX_SIZE) {
+        let n = &vertex[NORMAL_OFFSET..NORMAL_OFFSET + 3];
+        writeln!(out, "vn {} {} {}", n[0], n[1], n[2])?;
+    }
+
+    // "v/vt/vn" of a face corner, "v/vt" of a line or point
+    let corner = |index: u32, normal: bool| -> String {
+        let i = index + 1;
+        match (mesh.has_tex_coords, normal) {
+            (true, true) => format!("{}/{}/{}", i, i, i),
+            (false, true) => format!("{}//{}", i, i),
+            (true, false) => format!("{}/{}", i, i),
+            (false, false) => format!("{}", i),
+        }
+    };
+
+    let mut node = None;
+    let mut object = None;
+    let mut material = None;
+
+    for sub_mesh in &mesh.sub_meshes {
+        if node != Some(sub_mesh.node) {
+            let current = &mesh.nodes[sub_mesh.node];
+            match current.kind {
+                NodeKind::Object => {
+                    writeln!(out, "o {}", current.name)?;
+                    object = Some(sub_mesh.node);
+                }
+                NodeKind::Group => {
+                    // the object of the group has to be the current one
+                    if let Some(parent) = current.parent.filter(|&parent| object != Some(parent)) {
+                        writeln!(out, "o {}", mesh.nodes[parent].name)?;
+                        object = Some(parent);
+                    }
+                    writeln!(out, "g {}", current.name)?;
+                }
+            }
+            node = Some(sub_mesh.node);
+        }
+
+        if material != sub_mesh.material {
+            // an unknown material name brings back the default material
+            let name = sub_mesh.material.map_or("default", |m| &mesh.materials[m].name);
+            writeln!(out, "usemtl {}", name)?;
+            material = sub_mesh.material;
+        }
+
+        let range = sub_mesh.start..sub_mesh.start + sub_mesh.count;
+        match sub_mesh.primitive {
+            Primitive::Triangles => {
+                for triangle in mesh.indices[range].chunks_exact(3) {
+                    writeln!(out, "f {} {} {}",
+                        corner(triangle[0], true),
+                        corner(triangle[1], true),
+                        corner(triangle[2], true)
+                    )?;
+                }
+            }
+            Primitive::Lines => {
+                // consecutive segments are joined back into polylines
+                let mut polyline: Vec<u32> = Vec::new();
+                for segment in mesh.lines[range].chunks_exact(2) {
+                    if polyline.last() != Some(&segment[0]) {
+                        write_element(&mut out, "l", &polyline, |i| corner(i, false))?;
+                        polyline.clear();
+                        polyline.push(segment[0]);
+                    }
+                    polyline.push(segment[1]);
+                }
+                write_element(&mut out, "l", &polyline, |i| corner(i, false))?;
+            }
+            Primitive::Points => {
+                for points in mesh.points[range].chunks(POINTS_PER_LINE) {
+                    write_element(&mut out, "p", points, |i| corner(i, false))?;
+                }
+            }
+        }
+    }
+
+    out.flush()
+}
+
+// one "l" or "p" line, nothing for an empty list
+fn write_element(
+    out: &mut impl Write,
+    keyword: &str,
+    indices: &[u32],
+    corner: impl Fn(u32) -> String
+) -> io::Result<()> {
+    if indices.is_empty() {
+        return Ok(());
+    }
+    write!(out, "{}", keyword)?;
+    for &index in indices {
+        write!(out, " {}", corner(index))?;
+    }
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::*;
+    use crate::mesh::{Material, Node, TextureImage};
+    use crate::parse_obj;
+
+    fn load(name: &str) -> Mesh {
+        parse_obj::load_model(Path::new(&format!("resources/objects/{}", name))).unwrap()
+    }
+
+    // save the mesh in a new directory and load it back
+    fn round_trip(mesh: &Mesh, name: &str) -> (PathBuf, Mesh) {
+        let directory = std::env::temp_dir().join(format!("scop-test-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("model.obj");
+        save_model(mesh, &path).unwrap();
+        let reloaded = parse_obj::load_model(&path).unwrap();
+        (directory, reloaded)
+    }
+
+    // position, texture coordinates, normal and color of the corners of each triangle,
+    // in the order of the triangles: vertices may be numbered another way
+    fn triangles(mesh: &Mesh) -> Vec<Vec<u32>> {
+        let attributes = [
+            (POSITION_OFFSET, 3),
+            (TEX_COORD_OFFSET, if mesh.has_tex_coords { 2 } else { 0 }),
+            (NORMAL_OFFSET, 3),
+            (COLOR_OFFSET, if mesh.has_colors { 3 } else { 0 }),
+        ];
+        mesh.indices.chunks_exact(3)
+            .map(|triangle| triangle.iter()
+                .flat_map(|&index| {
+                    let vertex = &mesh.vertices[index as usize * VERTEX_SIZE..(index as usize + 1) * VERTEX_SIZE];
+                    attributes.iter().flat_map(move |&(offset, size)| vertex[offset..offset + size].iter().map(|value| value.to_bits()))
+                })
+                .collect())
+            .collect()
+    }
+
+    fn node_tree(mesh: &Mesh) -> Vec<(String, NodeKind, Option<usize>)> {
+        mesh.nodes.iter().map(|Node { name, kind, parent }| (name.clone(), *kind, *parent)).collect()
+    }
+
+    fn assert_same_materials(a: &[Material], b: &[Material]) {
+        assert_eq!(a.len(), b.len());
+        for (a, b) in a.iter().zip(b) {
+            assert_eq!(a.name, b.name);
+            assert_eq!((a.ambient, a.diffuse, a.specular), (b.ambient, b.diffuse, b.specular));
+            assert_eq!((a.shininess, a.dissolve, a.illum), (b.shininess, b.dissolve, b.illum));
+        }
+    }
+
+    #[test]
+    fn saved_models_load_back_the_same() {
+        for name in ["redcube/cube.obj", "teapot/teapot.obj", "42/42.obj", "teapot/teapot2.obj"] {
+            let mesh = load(name);
+            let (directory, reloaded) = round_trip(&mesh, &name.replace('/', "-"));
+            fs::remove_dir_all(directory).unwrap();
+
+            assert_eq!(reloaded.has_tex_coords, mesh.has_tex_coords, "{}", name);
+            assert_eq!(reloaded.has_colors, mesh.has_colors, "{}", name);
+            assert!(triangles(&reloaded) == triangles(&mesh), "{}: triangles differ", name);
+            assert_eq!(node_tree(&reloaded), node_tree(&mesh), "{}", name);
+            assert_same_materials(&reloaded.materials, &mesh.materials);
+            let materials = |mesh: &Mesh| mesh.sub_meshes.iter().map(|sub_mesh| sub_mesh.material).collect::<Vec<_>>();
+            assert_eq!(materials(&reloaded), materials(&mesh), "{}", name);
+        }
+    }
+
+    #[test]
+    fn textures_are_found_from_the_saved_model() {
+        let mut mesh = load("42/42.obj");
+        let texture = PathBuf::from("resources/textures/ponies.jpg");
+        mesh.materials[0].diffuse_map = Some(TextureImage::File(texture.clone()));
+        let (directory, reloaded) = round_trip(&mesh, "texture");
+        let mtl = fs::read_to_string(directory.join("model.mtl")).unwrap();
+        let found = match &reloaded.materials[0].diffuse_map {
+            Some(TextureImage::File(path)) => fs::canonicalize(path).ok(),
+            _ => None,
+        };
+        fs::remove_dir_all(directory).unwrap();
+
+        // relative to the .mtl, not absolute
+        let line = mtl.lines().find(|line| line.starts_with("map_Kd")).unwrap();
+        assert!(line.starts_with("map_Kd .."), "{}", line);
+        assert_eq!(found, fs::canonicalize(texture).ok());
+    }
+}
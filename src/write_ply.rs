@@ -0,0 +1,103 @@
+use std::{path::Path, io::{self, Write, BufWriter}, fs::File};
+
+use crate::mesh::{Mesh, POSITION_OFFSET, TEX_COORD_OFFSET, NORMAL_OFFSET, COLOR_OFFSET, VERTEX_SIZE};
+
+// write the mesh as a binary little endian .ply file: every vertex with its normal,
+// texture coordinates and color (when the mesh has them), and the triangles.
+// materials, objects, lines and points are not kept.
+pub fn save_model(mesh: &Mesh, path: &Path) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    let vertex_count = mesh.vertices.len() / VERTEX_SIZE;
+
+    writeln!(out, "ply")?;
+    writeln!(out, "format binary_little_endian 1.0")?;
+    writeln!(out, "comment exported by scop")?;
+    writeln!(out, "element vertex {}", vertex_count)?;
+    for name in ["x", "y", "z", "nx", "ny", "nz"] {
+        writeln!(out, "property float {}", name)?;
+    }
+    if mesh.has_tex_coords {
+        writeln!(out, "property float s")?;
+        writeln!(out, "property float t")?;
+    }
+    if mesh.has_colors {
+        for name in ["red", "green", "blue"] {
+            writeln!(out, "property uchar {}", name)?;
+        }
+    }
+    writeln!(out, "element face {}", mesh.indices.len() / 3)?;
+    writeln!(out, "property list uchar uint vertex_indices")?;
+    writeln!(out, "end_header")?;
+
+    for vertex in mesh.vertices.chunks_exact(VERTEX_SIZE) {
+        let mut values = Vec::with_capacity(8);
+        values.extend_from_slice(&vertex[POSITION_OFFSET..POSITION_OFFSET + 3]);
+        values.extend_from_slice(&vertex[NORMAL_OFFSET..NORMAL_OFFSET + 3]);
+        if mesh.has_tex_coords {
+            values.extend_from_slice(&vertex[TEX_COORD_OFFSET..TEX_COORD_OFFSET + 2]);
+        }
+        for value in values {
+            out.write_all(&value.to_le_bytes())?;
+        }
+        if mesh.has_colors {
+            let color: [u8; 3] = std::array::from_fn(|i| {
+                (vertex[COLOR_OFFSET + i].clamp(0.0, 1.0) * 255.0).round() as u8
+            });
+            out.write_all(&color)?;
+        }
+    }
+
+    for triangle in mesh.indices.chunks_exact(3) {
+        out.write_all(&[3])?;
+        for index in triangle {
+            out.write_all(&index.to_le_bytes())?;
+        }
+    }
+
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{parse_obj, parse_ply};
+
+    // position, texture coordinates, normal and color of the corners of each triangle
+    fn triangles(mesh: &Mesh) -> Vec<Vec<f32>> {
+        let attributes = [
+            (POSITION_OFFSET, 3),
+            (TEX_COORD_OFFSET, if mesh.has_tex_coords { 2 } else { 0 }),
+            (NORMAL_OFFSET, 3),
+            (COLOR_OFFSET, if mesh.has_colors { 3 } else { 0 }),
+        ];
+        mesh.indices.chunks_exact(3)
+            .map(|triangle| triangle.iter()
+                .flat_map(|&index| {
+                    let vertex = &mesh.vertices[index as usize * VERTEX_SIZE..(index as usize + 1) * VERTEX_SIZE];
+                    attributes.iter().flat_map(move |&(offset, size)| vertex[offset..offset + size].iter().copied())
+                })
+                .collect())
+            .collect()
+    }
+
+    #[test]
+    fn saved_models_load_back_the_same() {
+        for name in ["redcube/cube.obj", "teapot/teapot2.obj", "fixtures/colors.obj"] {
+            let mesh = parse_obj::load_model(Path::new(&format!("resources/objects/{}", name))).unwrap();
+            let path = std::env::temp_dir().join(format!("scop-test-{}-{}.ply", std::process::id(), name.replace('/', "-")));
+            save_model(&mesh, &path).unwrap();
+            let reloaded = parse_ply::load_model(&path).unwrap();
+            fs::remove_file(path).unwrap();
+
+            assert_eq!(reloaded.has_tex_coords, mesh.has_tex_coords, "{}", name);
+            assert_eq!(reloaded.has_colors, mesh.has_colors, "{}", name);
+            let (a, b) = (triangles(&reloaded), triangles(&mesh));
+            assert_eq!(a.len(), b.len(), "{}", name);
+            // colors are stored as bytes
+            let close = a.iter().flatten().zip(b.iter().flatten()).all(|(a, b)| (a - b).abs() <= 0.5 / 255.0);
+            assert!(close, "{}: triangles differ", name);
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use std::{path::Path, io::{self, Write, BufWriter}, fs::File};
+
+use crate::mesh::{Mesh, POSITION_OFFSET, VERTEX_SIZE};
+use crate::vector::{normalize, triangle_normal};
+
+// write the triangles as a binary .stl file, with the normal of each facet.
+// stl only has positions: texture coordinates, colors, materials, lines and points are lost.
+pub fn save_model(mesh: &Mesh, path: &Path) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+
+    // 80 bytes of header, which must not start with "solid"
+    let mut header = [b' '; 80];
+    let text = b"exported by scop";
+    header[..text.len()].copy_from_slice(text);
+    out.write_all(&header)?;
+
+    let count = u32::try_from(mesh.indices.len() / 3)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many triangles for stl"))?;
+    out.write_all(&count.to_le_bytes())?;
+
+    let position = |index: u32| -> [f32; 3] {
+        let p = index as usize * VERTEX_SIZE + POSITION_OFFSET;
+        [mesh.vertices[p], mesh.vertices[p + 1], mesh.vertices[p + 2]]
+    };
+
+    for triangle in mesh.indices.chunks_exact(3) {
+        let corners = [position(triangle[0]), position(triangle[1]), position(triangle[2])];
+        for value in facet_normal(&corners).iter().chain(corners.iter().flatten()) {
+            out.write_all(&value.to_le_bytes())?;
+        }
+        // attribute byte count
+        out.write_all(&[0, 0])?;
+    }
+
+    out.flush()
+}
+
+// unit normal of a triangle, 0 0 0 when it is degenerate
+fn facet_normal(&[a, b, c]: &[[f32; 3]; 3]) -> [f32; 3] {
+    normalize(triangle_normal(a, b, c))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::mesh::NORMAL_OFFSET;
+    use crate::{parse_obj, parse_stl};
+
+    // corner positions of each triangle, and the normal of its first corner
+    fn triangles(mesh: &Mesh) -> Vec<([[f32; 3]; 3], [f32; 3])> {
+        let attribute = |index: u32, offset: usize| -> [f32; 3] {
+            let p = index as usize * VERTEX_SIZE + offset;
+            [mesh.vertices[p], mesh.vertices[p + 1], mesh.vertices[p + 2]]
+        };
+        mesh.indices.chunks_exact(3)
+            .map(|t| ([0, 1, 2].map(|i| attribute(t[i], POSITION_OFFSET)), attribute(t[0], NORMAL_OFFSET)))
+            .collect()
+    }
+
+    #[test]
+    fn saved_models_load_back_with_facet_normals() {
+        for name in ["redcube/cube.obj", "teapot/teapot2.obj"] {
+            let mesh = parse_obj::load_model(Path::new(&format!("resources/objects/{}", name))).unwrap();
+            let path = std::env::temp_dir().join(format!("scop-test-{}-{}.stl", std::process::id(), name.replace('/', "-")));
+            save_model(&mesh, &path).unwrap();
+            let reloaded = parse_stl::load_model(&path).unwrap();
+            fs::remove_file(path).unwrap();
+
+            let (a, b) = (triangles(&reloaded), triangles(&mesh));
+            assert_eq!(a.len(), b.len(), "{}", name);
+            for ((corners, normal), (expected, _)) in a.into_iter().zip(b) {
+                assert_eq!(corners, expected, "{}", name);
+                // normalized again by the loader
+                let expected = facet_normal(&expected);
+                assert!((0..3).all(|i| (normal[i] - expected[i]).abs() < 1e-6), "{}: {:?} != {:?}", name, normal, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn degenerate_facets_get_a_null_normal() {
+        assert_eq!(facet_normal(&[[0.0, 0.0, 0.0], [1.0, 1.0, 1.0], [2.0, 2.0, 2.0]]), [0.0; 3]);
+        assert_eq!(facet_normal(&[[0.0, 0.0, 0.0], [2.0, 0.0, 0.0], [0.0, 2.0, 0.0]]), [0.0, 0.0, 1.0]);
+    }
+}